@@ -0,0 +1,34 @@
+-- Ordered list of channels to try for a notification; NULL means use the user's preferences
+ALTER TABLE notifications
+ADD COLUMN channels TEXT[],
+ADD COLUMN delivered_via TEXT, -- The channel that ultimately delivered the notification
+ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0,
+ADD COLUMN last_error TEXT,
+ADD COLUMN sent_at TIMESTAMP WITH TIME ZONE,
+-- Set while a dispatcher is delivering the notification so other instances leave it alone; a lease
+-- that runs out means that dispatcher died and the notification can be picked up again
+ADD COLUMN lease_until TIMESTAMP WITH TIME ZONE;
+
+CREATE INDEX notifications_pending_send_at_idx ON notifications (send_at) WHERE status = 'Pending';
+
+-- Per-user default fallback chain, e.g. {Push,SMS,Email}
+ALTER TABLE user_preferences
+ADD COLUMN fallback_chain TEXT[];
+
+-- Keep a single preferences row per user so it can be upserted
+DELETE FROM user_preferences a USING user_preferences b
+WHERE a.user_id = b.user_id AND a.id < b.id;
+
+CREATE UNIQUE INDEX user_preferences_user_id_key ON user_preferences (user_id);
+
+-- One row per channel attempt made by the dispatcher
+CREATE TABLE notification_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    notification_id UUID NOT NULL REFERENCES notifications(id) ON DELETE CASCADE,
+    channel TEXT,
+    event_type TEXT NOT NULL CONSTRAINT notification_events_event_type_check CHECK (event_type IN ('Sent', 'Failed', 'Skipped')),
+    detail TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX notification_events_notification_id_idx ON notification_events (notification_id);
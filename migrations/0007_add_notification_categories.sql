@@ -0,0 +1,21 @@
+-- Categories let users opt out of whole classes of notifications
+ALTER TABLE notifications
+ADD COLUMN category TEXT NOT NULL DEFAULT 'General'
+    CONSTRAINT notifications_category_check CHECK (category IN ('General', 'Marketing', 'Reminders', 'Security'));
+
+-- Notifications dropped because the user opted out of every channel they could use
+ALTER TABLE notifications
+DROP CONSTRAINT notifications_status_check,
+ADD CONSTRAINT notifications_status_check CHECK (status IN ('Pending', 'Sent', 'Failed', 'Suppressed'));
+
+-- A NULL channel opts the user out of the category on every channel
+CREATE TABLE notification_opt_outs (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    category TEXT NOT NULL CHECK (category IN ('General', 'Marketing', 'Reminders')),
+    channel TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE UNIQUE INDEX notification_opt_outs_user_category_channel_key
+    ON notification_opt_outs (user_id, category, COALESCE(channel, ''));
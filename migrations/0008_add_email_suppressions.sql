@@ -0,0 +1,17 @@
+-- Addresses the email channel must never send to again
+CREATE TABLE suppressions (
+    email TEXT PRIMARY KEY, -- Stored lowercased
+    reason TEXT NOT NULL CHECK (reason IN ('HardBounce', 'Complaint')),
+    detail TEXT,
+    notification_id UUID REFERENCES notifications(id) ON DELETE SET NULL, -- The send that triggered it, if known
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE notification_events
+DROP CONSTRAINT notification_events_event_type_check,
+ADD CONSTRAINT notification_events_event_type_check
+    CHECK (event_type IN ('Sent', 'Failed', 'Skipped', 'Bounced', 'Complained'));
+
+-- Admins can manage operational data such as the suppression list
+ALTER TABLE users
+ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT FALSE;
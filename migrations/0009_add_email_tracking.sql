@@ -0,0 +1,10 @@
+-- Optional HTML body for email; links in it can be rewritten for click tracking
+ALTER TABLE notifications
+ADD COLUMN html_content TEXT;
+
+ALTER TABLE notification_events
+DROP CONSTRAINT notification_events_event_type_check,
+ADD CONSTRAINT notification_events_event_type_check
+    CHECK (event_type IN ('Sent', 'Failed', 'Skipped', 'Bounced', 'Complained', 'Opened', 'Clicked'));
+
+CREATE INDEX notification_events_event_type_created_at_idx ON notification_events (event_type, created_at);
@@ -0,0 +1,13 @@
+ALTER TABLE notification_events
+DROP CONSTRAINT notification_events_event_type_check,
+ADD CONSTRAINT notification_events_event_type_check
+    CHECK (event_type IN ('Sent', 'Failed', 'Skipped', 'Bounced', 'Complained', 'Opened', 'Clicked', 'Read'));
+
+-- Predicted engagement per hour of the week (index 0 = Monday 00:00 UTC), rebuilt by the recompute job.
+-- The row keyed by the nil UUID holds the population-wide profile used as a prior.
+CREATE TABLE engagement_profiles (
+    user_id UUID PRIMARY KEY,
+    hour_scores DOUBLE PRECISION[] NOT NULL CHECK (array_length(hour_scores, 1) = 168),
+    sample_size INTEGER NOT NULL,
+    computed_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
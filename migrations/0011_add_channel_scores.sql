@@ -0,0 +1,11 @@
+-- Predicted engagement rate per user and channel, rebuilt by the recompute job.
+-- Rows keyed by the nil UUID hold the population-wide rate for each channel.
+CREATE TABLE channel_scores (
+    user_id UUID NOT NULL,
+    channel TEXT NOT NULL,
+    delivered INTEGER NOT NULL,
+    engaged INTEGER NOT NULL,
+    score DOUBLE PRECISION NOT NULL,
+    computed_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, channel)
+);
@@ -0,0 +1,5 @@
+-- Which template (or piece of copy) produced the notification, for reporting
+ALTER TABLE notifications
+ADD COLUMN template TEXT;
+
+CREATE INDEX notifications_created_at_idx ON notifications (created_at);
@@ -0,0 +1,36 @@
+-- Reusable notification copy; {{property}} placeholders are filled from event properties
+CREATE TABLE templates (
+    key TEXT PRIMARY KEY,
+    content TEXT NOT NULL,
+    html_content TEXT,
+    category TEXT NOT NULL DEFAULT 'General'
+        CONSTRAINT templates_category_check CHECK (category IN ('General', 'Marketing', 'Reminders', 'Security')),
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Domain events posted by other services
+CREATE TABLE events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    event_type TEXT NOT NULL,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    properties JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX events_user_id_event_type_idx ON events (user_id, event_type, created_at);
+
+-- When an event of `event_type` matching every condition arrives, send `template_key` after `delay_seconds`
+CREATE TABLE notification_rules (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name TEXT NOT NULL,
+    event_type TEXT NOT NULL,
+    conditions JSONB NOT NULL DEFAULT '[]',
+    template_key TEXT NOT NULL REFERENCES templates(key),
+    channel TEXT, -- A delivery method, 'auto', or NULL for the user's preferences
+    delay_seconds INTEGER NOT NULL DEFAULT 0 CONSTRAINT notification_rules_delay_check CHECK (delay_seconds >= 0),
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX notification_rules_event_type_idx ON notification_rules (event_type) WHERE enabled;
@@ -0,0 +1,34 @@
+-- Multi-step sequences, e.g. welcome email -> wait 2 days -> SMS reminder if unverified
+CREATE TABLE workflows (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    key TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    steps JSONB NOT NULL,
+    trigger_event TEXT, -- Event type that starts a run; NULL means runs are only started by an admin
+    exit_events TEXT[] NOT NULL DEFAULT '{}', -- Event types that cancel a user's active run
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX workflows_trigger_event_idx ON workflows (trigger_event) WHERE enabled;
+
+-- One user's progress through a workflow; steps are copied so editing a workflow never breaks runs in flight
+CREATE TABLE workflow_runs (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    workflow_id UUID NOT NULL REFERENCES workflows(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    steps JSONB NOT NULL,
+    current_step INTEGER NOT NULL DEFAULT 0,
+    status TEXT NOT NULL DEFAULT 'Active'
+        CONSTRAINT workflow_runs_status_check CHECK (status IN ('Active', 'Completed', 'Cancelled', 'Failed')),
+    context JSONB NOT NULL DEFAULT '{}', -- Properties of the triggering event, available to templates
+    next_run_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    attempts INTEGER NOT NULL DEFAULT 0, -- Failed tries at the current step; reset once a step succeeds
+    exit_reason TEXT, -- Also the last error while a failing step is retried
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    finished_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX workflow_runs_next_run_at_idx ON workflow_runs (next_run_at) WHERE status = 'Active';
+CREATE UNIQUE INDEX workflow_runs_active_key ON workflow_runs (workflow_id, user_id) WHERE status = 'Active';
@@ -0,0 +1,19 @@
+-- A/B variants of a template; recipients are assigned by hashing the experiment ID with their user ID
+ALTER TABLE templates
+ADD COLUMN experiment_id TEXT;
+
+CREATE TABLE template_variants (
+    template_key TEXT NOT NULL REFERENCES templates(key) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    weight INTEGER NOT NULL CONSTRAINT template_variants_weight_check CHECK (weight > 0),
+    content TEXT NOT NULL,
+    html_content TEXT,
+    PRIMARY KEY (template_key, name)
+);
+
+-- Which experiment and variant produced the notification
+ALTER TABLE notifications
+ADD COLUMN experiment_id TEXT,
+ADD COLUMN variant TEXT;
+
+CREATE INDEX notifications_experiment_id_idx ON notifications (experiment_id) WHERE experiment_id IS NOT NULL;
@@ -0,0 +1,16 @@
+-- Token buckets shared by every dispatcher replica, one per provider a channel sends through
+CREATE TABLE rate_limit_buckets (
+    key TEXT PRIMARY KEY,
+    tokens DOUBLE PRECISION NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Consecutive transient failures per provider; once open, no attempts are made until open_until
+CREATE TABLE circuit_breakers (
+    key TEXT PRIMARY KEY,
+    state TEXT NOT NULL DEFAULT 'Closed' CONSTRAINT circuit_breakers_state_check CHECK (state IN ('Closed', 'Open')),
+    consecutive_failures INTEGER NOT NULL DEFAULT 0,
+    open_until TIMESTAMP WITH TIME ZONE,
+    last_error TEXT,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,10 @@
+-- Messages "sent" through the mock SMS provider, for tests and local development
+CREATE TABLE mock_sms_messages (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    to_number TEXT NOT NULL,
+    from_number TEXT NOT NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX mock_sms_messages_to_number_idx ON mock_sms_messages (to_number, created_at);
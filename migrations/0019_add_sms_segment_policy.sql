@@ -0,0 +1,6 @@
+-- Per-notification overrides of SMS_MAX_SEGMENTS / SMS_OVERFLOW, and what an SMS actually cost
+ALTER TABLE notifications
+    ADD COLUMN sms_max_segments INTEGER CONSTRAINT notifications_sms_max_segments_check CHECK (sms_max_segments > 0),
+    ADD COLUMN sms_overflow TEXT
+        CONSTRAINT notifications_sms_overflow_check CHECK (sms_overflow IN ('Truncate', 'Transliterate', 'Reject')),
+    ADD COLUMN sms_segments INTEGER;  -- Billed segments, set when delivered by SMS
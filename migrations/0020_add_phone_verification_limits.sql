@@ -0,0 +1,5 @@
+-- phone_verification_code now holds a bcrypt hash of the code sent by SMS
+ALTER TABLE users
+    ADD COLUMN phone_verification_expires_at TIMESTAMP WITH TIME ZONE,
+    ADD COLUMN phone_verification_sent_at TIMESTAMP WITH TIME ZONE,
+    ADD COLUMN phone_verification_attempts INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,11 @@
+-- Filled in when phone_number is normalized to E.164
+ALTER TABLE users
+    ADD COLUMN phone_country_code INTEGER,  -- Calling code, e.g. 44
+    ADD COLUMN phone_region TEXT,  -- ISO 3166-1 alpha-2, when the number maps to one region
+    ADD COLUMN phone_number_type TEXT,  -- Mobile, FixedLine, FixedLineOrMobile, Voip, ...
+    ADD COLUMN phone_reentry_required BOOLEAN NOT NULL DEFAULT FALSE;  -- A stored number could not be normalized and was removed
+
+-- Numbers stored before this change are normalized by the server at startup, which sets
+-- phone_country_code on every number it could parse and clears the rest
+
+CREATE INDEX users_phone_number_idx ON users (phone_number);
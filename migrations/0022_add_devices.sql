@@ -0,0 +1,15 @@
+-- Push tokens registered by the mobile apps; a token belongs to whoever registered it last
+CREATE TABLE devices (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    platform TEXT NOT NULL CONSTRAINT devices_platform_check CHECK (platform IN ('Android', 'iOS')),
+    token TEXT NOT NULL,
+    app_version TEXT,
+    active BOOLEAN NOT NULL DEFAULT TRUE,  -- Cleared when the push service reports the token as invalid
+    last_error TEXT,
+    last_seen TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT devices_platform_token_key UNIQUE (platform, token)
+);
+
+CREATE INDEX devices_user_id_idx ON devices (user_id) WHERE active;
@@ -0,0 +1,13 @@
+-- Browser push subscriptions (PushSubscription.toJSON()); removed when the push service says they are gone
+CREATE TABLE web_push_subscriptions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    endpoint TEXT NOT NULL CONSTRAINT web_push_subscriptions_endpoint_key UNIQUE,
+    p256dh TEXT NOT NULL,  -- Browser's P-256 public key, base64url
+    auth TEXT NOT NULL,  -- 16-byte authentication secret, base64url
+    user_agent TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_seen TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX web_push_subscriptions_user_id_idx ON web_push_subscriptions (user_id);
@@ -0,0 +1,28 @@
+-- HTTPS endpoints that receive notifications as signed JSON POSTs
+CREATE TABLE webhook_endpoints (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,  -- HMAC-SHA256 key for the X-Notismart-Signature header
+    description TEXT,
+    active BOOLEAN NOT NULL DEFAULT TRUE,  -- Cleared after too many consecutive failed deliveries
+    consecutive_failures INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    last_success_at TIMESTAMP WITH TIME ZONE,
+    disabled_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX webhook_endpoints_user_id_idx ON webhook_endpoints (user_id);
+
+-- Endpoints that accepted a notification, so a retry only goes to the ones that did not
+CREATE TABLE webhook_deliveries (
+    notification_id UUID NOT NULL REFERENCES notifications(id) ON DELETE CASCADE,
+    endpoint_id UUID NOT NULL REFERENCES webhook_endpoints(id) ON DELETE CASCADE,
+    delivered_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (notification_id, endpoint_id)
+);
+
+ALTER TABLE user_preferences
+DROP CONSTRAINT user_preferences_preferred_method_check,
+ADD CONSTRAINT user_preferences_preferred_method_check CHECK (preferred_method IN ('Email', 'SMS', 'Push', 'Webhook'));
@@ -0,0 +1,22 @@
+-- Optional headline and link buttons, used by channels that can show more than a body
+ALTER TABLE notifications
+ADD COLUMN title TEXT,
+ADD COLUMN actions JSONB; -- [{"label": ..., "url": ...}]
+
+-- Incoming-webhook URLs notifications are posted to in Slack, Discord or Microsoft Teams
+CREATE TABLE chat_webhooks (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    provider TEXT NOT NULL CONSTRAINT chat_webhooks_provider_check CHECK (provider IN ('Slack', 'Discord', 'Teams')),
+    url TEXT NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT TRUE,  -- Cleared when the chat service says the webhook no longer exists
+    last_error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT chat_webhooks_user_id_provider_key UNIQUE (user_id, provider)
+);
+
+ALTER TABLE user_preferences
+DROP CONSTRAINT user_preferences_preferred_method_check,
+ADD CONSTRAINT user_preferences_preferred_method_check
+    CHECK (preferred_method IN ('Email', 'SMS', 'Push', 'Webhook', 'Slack', 'Discord', 'Teams'));
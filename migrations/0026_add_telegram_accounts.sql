@@ -0,0 +1,20 @@
+-- Telegram chats linked to users through the bot's /start deep link
+CREATE TABLE telegram_accounts (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    chat_id BIGINT NOT NULL CONSTRAINT telegram_accounts_chat_id_key UNIQUE,
+    username TEXT,
+    linked_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- One-time tokens carried in the deep link's start parameter
+CREATE TABLE telegram_link_tokens (
+    token TEXT PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE user_preferences
+DROP CONSTRAINT user_preferences_preferred_method_check,
+ADD CONSTRAINT user_preferences_preferred_method_check
+    CHECK (preferred_method IN ('Email', 'SMS', 'Push', 'Webhook', 'Slack', 'Discord', 'Teams', 'Telegram'));
@@ -0,0 +1,16 @@
+-- How urgent a notification is; voice calls are only placed for Critical ones
+ALTER TABLE notifications
+ADD COLUMN priority TEXT NOT NULL DEFAULT 'Normal'
+    CONSTRAINT notifications_priority_check CHECK (priority IN ('Low', 'Normal', 'High', 'Critical')),
+ADD COLUMN voice_acknowledge BOOLEAN NOT NULL DEFAULT TRUE,  -- Ask the callee to press 1 to acknowledge
+ADD COLUMN acknowledged_at TIMESTAMP WITH TIME ZONE;
+
+ALTER TABLE notification_events
+DROP CONSTRAINT notification_events_event_type_check,
+ADD CONSTRAINT notification_events_event_type_check
+    CHECK (event_type IN ('Sent', 'Failed', 'Skipped', 'Bounced', 'Complained', 'Opened', 'Clicked', 'Read', 'Acknowledged'));
+
+ALTER TABLE user_preferences
+DROP CONSTRAINT user_preferences_preferred_method_check,
+ADD CONSTRAINT user_preferences_preferred_method_check
+    CHECK (preferred_method IN ('Email', 'SMS', 'Push', 'Webhook', 'Slack', 'Discord', 'Teams', 'Telegram', 'Voice'));
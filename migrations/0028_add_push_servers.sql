@@ -0,0 +1,19 @@
+-- Self-hosted push servers users publish their notifications to: an ntfy topic or a Gotify application
+CREATE TABLE push_servers (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    provider TEXT NOT NULL CONSTRAINT push_servers_provider_check CHECK (provider IN ('Ntfy', 'Gotify')),
+    server_url TEXT NOT NULL,
+    topic TEXT,  -- ntfy only
+    token TEXT,  -- ntfy access token if the topic is protected, or the Gotify application token
+    active BOOLEAN NOT NULL DEFAULT TRUE,  -- Cleared when the server rejects the token or topic
+    last_error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT push_servers_user_id_provider_key UNIQUE (user_id, provider)
+);
+
+ALTER TABLE user_preferences
+DROP CONSTRAINT user_preferences_preferred_method_check,
+ADD CONSTRAINT user_preferences_preferred_method_check
+    CHECK (preferred_method IN ('Email', 'SMS', 'Push', 'Webhook', 'Slack', 'Discord', 'Teams', 'Telegram', 'Voice', 'Ntfy', 'Gotify'));
@@ -0,0 +1,140 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+use time::macros::format_description;
+use time::{Date, OffsetDateTime};
+use utoipa::IntoParams;
+
+use crate::auth::extractor::AdminUser;
+use crate::services::analytics::{self, Grouping, CSV_HEADER};
+
+const DEFAULT_RANGE_DAYS: i64 = 30;
+const MAX_RANGE_DAYS: i64 = 366;
+
+#[derive(Deserialize, IntoParams)]
+pub struct DeliveryStatsQuery {
+    pub from: Option<String>,      // First day to include, YYYY-MM-DD (UTC); defaults to 30 days before `to`
+    pub to: Option<String>,        // Last day to include, YYYY-MM-DD (UTC); defaults to today
+    pub group_by: Option<String>,  // Comma separated subset of channel, category, template, variant, day; defaults to all
+    pub format: Option<String>,    // "json" (default) or "csv"
+}
+
+// GET /analytics/deliveries - Delivery, bounce and engagement rates over a date range
+#[utoipa::path(
+    get,
+    path = "/api/analytics/deliveries",
+    params(DeliveryStatsQuery),
+    responses(
+        (status = 200, description = "Delivery statistics retrieved successfully", body = [DeliveryStats]),
+        (status = 400, description = "Invalid date range, dimension or format"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    tag = "Analytics API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn delivery_stats(
+    query: web::Query<DeliveryStatsQuery>,
+    db: web::Data<PgPool>,
+    _admin: AdminUser,
+) -> HttpResponse {
+    let to = match query.to.as_deref().map(parse_date) {
+        Some(Ok(date)) => date,
+        Some(Err(e)) => return HttpResponse::BadRequest().json(e),
+        None => OffsetDateTime::now_utc().date(),
+    };
+    let from = match query.from.as_deref().map(parse_date) {
+        Some(Ok(date)) => date,
+        Some(Err(e)) => return HttpResponse::BadRequest().json(e),
+        None => match to.checked_sub(time::Duration::days(DEFAULT_RANGE_DAYS)) {
+            Some(date) => date,
+            None => return HttpResponse::BadRequest().json("to is too early to default from"),
+        },
+    };
+
+    if from > to {
+        return HttpResponse::BadRequest().json("from must not be after to");
+    }
+    if (to - from).whole_days() > MAX_RANGE_DAYS {
+        return HttpResponse::BadRequest().json(format!("Date range must not exceed {} days", MAX_RANGE_DAYS));
+    }
+
+    let grouping = match query.group_by.as_deref() {
+        Some(value) => match Grouping::parse(value) {
+            Ok(grouping) => grouping,
+            Err(e) => return HttpResponse::BadRequest().json(e),
+        },
+        None => Grouping::all(),
+    };
+
+    let csv = match query.format.as_deref() {
+        None | Some("json") => false,
+        Some("csv") => true,
+        Some(_) => return HttpResponse::BadRequest().json("format must be json or csv"),
+    };
+
+    let stats = match analytics::delivery_stats(db.get_ref(), grouping, from, to).await {
+        Ok(stats) => stats,
+        Err(_) => return HttpResponse::InternalServerError().json("Error computing delivery statistics"),
+    };
+
+    if !csv {
+        return HttpResponse::Ok().json(stats);
+    }
+
+    let mut body = String::from(CSV_HEADER);
+    body.push('\n');
+    for row in &stats {
+        body.push_str(&row.to_csv_row());
+        body.push('\n');
+    }
+
+    HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"deliveries-{}-{}.csv\"", from, to),
+        ))
+        .body(body)
+}
+
+// GET /analytics/experiments/{id} - Engagement per A/B variant with significance against the baseline
+#[utoipa::path(
+    get,
+    path = "/api/analytics/experiments/{id}",
+    params(
+        ("id" = String, Path, description = "Experiment ID, the template key unless set explicitly")
+    ),
+    responses(
+        (status = 200, description = "Experiment statistics retrieved successfully", body = ExperimentStats),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    tag = "Analytics API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn experiment_stats(
+    experiment_id: web::Path<String>,
+    db: web::Data<PgPool>,
+    _admin: AdminUser,
+) -> HttpResponse {
+    match analytics::experiment_stats(db.get_ref(), &experiment_id).await {
+        Ok(stats) => HttpResponse::Ok().json(stats),
+        Err(_) => HttpResponse::InternalServerError().json("Error computing experiment statistics"),
+    }
+}
+
+/// Parses a YYYY-MM-DD calendar date
+fn parse_date(value: &str) -> Result<Date, String> {
+    Date::parse(value.trim(), format_description!("[year]-[month]-[day]"))
+        .map_err(|_| format!("Invalid date: {} (expected YYYY-MM-DD)", value))
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/analytics/deliveries", web::get().to(delivery_stats))          // GET /analytics/deliveries
+        .route("/analytics/experiments/{id}", web::get().to(experiment_stats)); // GET /analytics/experiments/{id}
+}
@@ -0,0 +1,230 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use p256::PublicKey;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::auth::extractor::AuthenticatedUser;
+use crate::channels::push::web::{decode_key, is_push_service};
+use crate::channels::Channels;
+use crate::db::models::Platform;
+use crate::services::{devices, web_push};
+
+#[derive(Deserialize, ToSchema)]
+pub struct RegisterDeviceRequest {
+    pub platform: String,  // Android (FCM) or iOS (APNs)
+    pub token: String,  // FCM registration token or APNs device token
+    pub app_version: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RemoveDeviceRequest {
+    pub token: String,
+}
+
+/// Shape of `PushSubscription.toJSON()` in the browser
+#[derive(Deserialize, ToSchema)]
+pub struct WebPushSubscriptionRequest {
+    pub endpoint: String,
+    pub keys: WebPushKeys,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct WebPushKeys {
+    pub p256dh: String,  // Browser's P-256 public key, base64url
+    pub auth: String,  // 16-byte authentication secret, base64url
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RemoveWebPushSubscriptionRequest {
+    pub endpoint: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct VapidPublicKeyResponse {
+    pub public_key: String,  // applicationServerKey for pushManager.subscribe(), base64url
+}
+
+// POST /me/devices - Register a push token, or refresh it on app start
+#[utoipa::path(
+    post,
+    path = "/api/me/devices",
+    request_body = RegisterDeviceRequest,
+    responses(
+        (status = 200, description = "Device registered", body = Device),
+        (status = 400, description = "Unknown platform or malformed token"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "Devices API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn register_device(
+    device_data: web::Json<RegisterDeviceRequest>,
+    db: web::Data<PgPool>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    let platform = match device_data.platform.parse::<Platform>() {
+        Ok(platform) => platform,
+        Err(e) => return HttpResponse::BadRequest().json(e),
+    };
+
+    let token = device_data.token.trim();
+    if token.is_empty() {
+        return HttpResponse::BadRequest().json("Token is required");
+    }
+    if let Err(e) = check_token(platform, token) {
+        return HttpResponse::BadRequest().json(e);
+    }
+
+    match devices::register_device(db.get_ref(), auth_user.sub, platform, token, device_data.app_version.as_deref()).await {
+        Ok(device) => HttpResponse::Ok().json(device),
+        Err(_) => HttpResponse::InternalServerError().json("Error registering device"),
+    }
+}
+
+/// The token ends up in the APNs request path and the FCM message body, so
+/// only accept the shapes the providers hand out
+fn check_token(platform: Platform, token: &str) -> Result<(), &'static str> {
+    match platform {
+        Platform::Ios if token.len() != 64 || !token.bytes().all(|b| b.is_ascii_hexdigit()) => {
+            Err("APNs device tokens are 64 hex characters")
+        }
+        Platform::Android if token.len() > 4096
+            || !token.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'-' | b':')) =>
+        {
+            Err("Malformed FCM registration token")
+        }
+        _ => Ok(()),
+    }
+}
+
+// DELETE /me/devices - Stop sending pushes to a token, e.g. on logout
+#[utoipa::path(
+    delete,
+    path = "/api/me/devices",
+    request_body = RemoveDeviceRequest,
+    responses(
+        (status = 200, description = "Device removed"),
+        (status = 404, description = "No such device"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "Devices API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn remove_device(
+    device_data: web::Json<RemoveDeviceRequest>,
+    db: web::Data<PgPool>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    match devices::remove_device(db.get_ref(), auth_user.sub, device_data.token.trim()).await {
+        Ok(true) => HttpResponse::Ok().json("Device removed"),
+        Ok(false) => HttpResponse::NotFound().json("Device not found"),
+        Err(_) => HttpResponse::InternalServerError().json("Error removing device"),
+    }
+}
+
+// GET /web-push/vapid-public-key - Key browsers need to subscribe to Web Push
+#[utoipa::path(
+    get,
+    path = "/api/web-push/vapid-public-key",
+    responses(
+        (status = 200, description = "VAPID public key", body = VapidPublicKeyResponse),
+        (status = 404, description = "Web Push is not configured")
+    ),
+    tag = "Devices API"
+)]
+pub async fn get_vapid_public_key(channels: web::Data<Channels>) -> HttpResponse {
+    match channels.vapid_public_key() {
+        Some(public_key) => HttpResponse::Ok().json(VapidPublicKeyResponse { public_key: public_key.to_string() }),
+        None => HttpResponse::NotFound().json("Web Push is not configured"),
+    }
+}
+
+// POST /me/web-push-subscriptions - Save a browser push subscription
+#[utoipa::path(
+    post,
+    path = "/api/me/web-push-subscriptions",
+    request_body = WebPushSubscriptionRequest,
+    responses(
+        (status = 200, description = "Subscription saved", body = WebPushSubscription),
+        (status = 400, description = "Invalid endpoint or keys"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "Devices API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn subscribe_web_push(
+    req: HttpRequest,
+    subscription: web::Json<WebPushSubscriptionRequest>,
+    db: web::Data<PgPool>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    let endpoint = subscription.endpoint.trim();
+    if !Url::parse(endpoint).is_ok_and(|url| is_push_service(&url)) {
+        return HttpResponse::BadRequest().json("Endpoint must be an https URL of a browser push service");
+    }
+    if decode_key(&subscription.keys.p256dh).and_then(|bytes| PublicKey::from_sec1_bytes(&bytes).ok()).is_none() {
+        return HttpResponse::BadRequest().json("keys.p256dh must be a base64url P-256 public key");
+    }
+    if decode_key(&subscription.keys.auth).is_none_or(|bytes| bytes.len() != 16) {
+        return HttpResponse::BadRequest().json("keys.auth must be a base64url 16-byte secret");
+    }
+
+    let user_agent = req.headers().get("user-agent").and_then(|value| value.to_str().ok());
+
+    match web_push::subscribe(
+        db.get_ref(),
+        auth_user.sub,
+        endpoint,
+        subscription.keys.p256dh.trim(),
+        subscription.keys.auth.trim(),
+        user_agent,
+    )
+    .await
+    {
+        Ok(subscription) => HttpResponse::Ok().json(subscription),
+        Err(_) => HttpResponse::InternalServerError().json("Error saving subscription"),
+    }
+}
+
+// DELETE /me/web-push-subscriptions - Forget a browser subscription, e.g. after unsubscribe()
+#[utoipa::path(
+    delete,
+    path = "/api/me/web-push-subscriptions",
+    request_body = RemoveWebPushSubscriptionRequest,
+    responses(
+        (status = 200, description = "Subscription removed"),
+        (status = 404, description = "No such subscription"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "Devices API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn unsubscribe_web_push(
+    subscription: web::Json<RemoveWebPushSubscriptionRequest>,
+    db: web::Data<PgPool>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    match web_push::unsubscribe(db.get_ref(), auth_user.sub, subscription.endpoint.trim()).await {
+        Ok(true) => HttpResponse::Ok().json("Subscription removed"),
+        Ok(false) => HttpResponse::NotFound().json("Subscription not found"),
+        Err(_) => HttpResponse::InternalServerError().json("Error removing subscription"),
+    }
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/me/devices", web::post().to(register_device))   // POST /me/devices
+        .route("/me/devices", web::delete().to(remove_device))  // DELETE /me/devices
+        .route("/me/web-push-subscriptions", web::post().to(subscribe_web_push))     // POST /me/web-push-subscriptions
+        .route("/me/web-push-subscriptions", web::delete().to(unsubscribe_web_push)) // DELETE /me/web-push-subscriptions
+        .route("/web-push/vapid-public-key", web::get().to(get_vapid_public_key));   // GET /web-push/vapid-public-key
+}
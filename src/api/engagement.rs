@@ -0,0 +1,65 @@
+use actix_web::{web, HttpResponse};
+use serde::Serialize;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::auth::extractor::AdminUser;
+use crate::db::models::{Category, DeliveryMethod};
+use crate::services::{engagement::{self, ChannelScore}, preferences};
+
+#[derive(Serialize, ToSchema)]
+pub struct ChannelScoresResponse {
+    pub user_id: Uuid,
+    pub scores: Vec<ChannelScore>,
+    pub enabled: Vec<DeliveryMethod>,  // Channels the user accepts General notifications on
+    pub auto_choice: Vec<DeliveryMethod>,  // The chain `channel: "auto"` would produce right now
+}
+
+// GET /admin/users/{id}/channel-scores - Audit how "auto" channel selection sees a user
+#[utoipa::path(
+    get,
+    path = "/api/admin/users/{id}/channel-scores",
+    responses(
+        (status = 200, description = "Channel scores retrieved successfully", body = ChannelScoresResponse),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    params(
+        ("id" = Uuid, Path, description = "ID of the User")
+    ),
+    tag = "Engagement API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn get_channel_scores(
+    user_id: web::Path<Uuid>,
+    db: web::Data<PgPool>,
+    _admin: AdminUser,
+) -> HttpResponse {
+    let user_id = user_id.into_inner();
+
+    let scores = match engagement::get_channel_scores(db.get_ref(), user_id).await {
+        Ok(scores) => scores,
+        Err(_) => return HttpResponse::InternalServerError().json("Error fetching channel scores"),
+    };
+
+    let prefs = preferences::get_preferences(db.get_ref(), user_id).await;
+    let opt_outs = preferences::get_opt_outs(db.get_ref(), user_id).await;
+    let enabled = match (prefs, opt_outs) {
+        (Ok(prefs), Ok(opt_outs)) => preferences::enabled_channels(prefs.as_ref(), &opt_outs, Category::General),
+        _ => return HttpResponse::InternalServerError().json("Error fetching preferences"),
+    };
+
+    HttpResponse::Ok().json(ChannelScoresResponse {
+        user_id,
+        auto_choice: engagement::rank_channels(&enabled, &scores),
+        enabled,
+        scores,
+    })
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/admin/users/{id}/channel-scores", web::get().to(get_channel_scores)); // GET /admin/users/{id}/channel-scores
+}
@@ -0,0 +1,89 @@
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::auth::extractor::AuthenticatedUser;
+use crate::services::template::InstantiateError;
+use crate::services::{rules, user};
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct EventRequest {
+    #[serde(rename = "type")]
+    pub event_type: String,  // e.g. "order_shipped"
+    pub user_id: Uuid,
+    #[schema(value_type = Object)]
+    #[serde(default)]
+    pub properties: Value,   // Matched by rule conditions and substituted into templates
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct EventResponse {
+    pub event_id: Uuid,
+    pub notifications: Vec<Uuid>,  // Notifications created by matching rules
+    pub workflow_runs: Vec<Uuid>,  // Workflow runs the event started
+}
+
+// POST /events - Ingest a domain event, run the notification rules and start or exit workflows
+#[utoipa::path(
+    post,
+    path = "/api/events",
+    request_body = EventRequest,
+    responses(
+        (status = 200, description = "Event ingested", body = EventResponse),
+        (status = 400, description = "Invalid event"),
+        (status = 404, description = "User not found"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Events can only be sent for yourself unless you are an admin")
+    ),
+    tag = "Events API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn ingest_event(
+    event: web::Json<EventRequest>,
+    db: web::Data<PgPool>,
+    auth_user: AuthenticatedUser,  // Bearer authentication
+) -> HttpResponse {
+    if auth_user.sub != event.user_id && !auth_user.admin {
+        return HttpResponse::Forbidden().json("You can only send events for yourself");
+    }
+
+    if event.event_type.trim().is_empty() {
+        return HttpResponse::BadRequest().json("Event type is required");
+    }
+    if !event.properties.is_object() && !event.properties.is_null() {
+        return HttpResponse::BadRequest().json("Event properties must be an object");
+    }
+
+    match user::find_user(db.get_ref(), event.user_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json("User not found"),
+        Err(_) => return HttpResponse::InternalServerError().json("Error ingesting event"),
+    }
+
+    let properties = match &event.properties {
+        Value::Null => Value::Object(Default::default()),
+        properties => properties.clone(),
+    };
+
+    match rules::ingest_event(db.get_ref(), &event.event_type, event.user_id, &properties).await {
+        Ok(ingested) => HttpResponse::Ok().json(EventResponse {
+            event_id: ingested.event_id,
+            notifications: ingested.notifications,
+            workflow_runs: ingested.workflow_runs,
+        }),
+        // A rule that no longer resolves is a configuration problem, not a bad event
+        Err(e @ InstantiateError::InvalidChannel(_)) => {
+            HttpResponse::InternalServerError().json(format!("Error ingesting event: {}", e))
+        }
+        Err(InstantiateError::Database(_)) => HttpResponse::InternalServerError().json("Error ingesting event"),
+    }
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/events", web::post().to(ingest_event)); // POST /events
+}
@@ -1,12 +1,42 @@
+pub mod analytics;
+pub mod devices;
+pub mod engagement;
+pub mod events;
 pub mod notification;
+pub mod preferences;
+pub mod rules;
+pub mod sms;
+pub mod suppression;
+pub mod telegram;
+pub mod throttle;
+pub mod tracking;
+pub mod unsubscribe;
 pub mod user;
+pub mod voice;
+pub mod webhooks;
+pub mod workflows;
 
 use actix_web::web;
 
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/api")
+            .configure(analytics::init_routes)    // Add delivery analytics routes
+            .configure(devices::init_routes)      // Add push device and Web Push routes
+            .configure(engagement::init_routes)   // Add engagement admin routes
+            .configure(events::init_routes)       // Add event ingestion routes
             .configure(notification::init_routes) // Add notification routes
+            .configure(preferences::init_routes)  // Add preference routes
+            .configure(rules::init_routes)        // Add template and rule admin routes
+            .configure(sms::init_routes)          // Add mock SMS admin routes
+            .configure(unsubscribe::init_routes)  // Add public unsubscribe routes
+            .configure(suppression::init_routes)  // Add bounce webhook and suppression routes
+            .configure(telegram::init_routes)     // Add Telegram linking and bot update routes
+            .configure(throttle::init_routes)     // Add circuit breaker admin routes
             .configure(user::init_routes)         // Add user routes
-    );
+            .configure(voice::init_routes)        // Add voice call keypress callback routes
+            .configure(webhooks::init_routes)     // Add webhook endpoint routes
+            .configure(workflows::init_routes)    // Add workflow admin routes
+    )
+    .configure(tracking::init_routes); // Tracking links live outside /api to keep them short
 }
@@ -1,19 +1,47 @@
 use actix_web::{web, HttpResponse};
+use reqwest::Url;
 use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
 use sqlx::PgPool;
 use utoipa::ToSchema;
 use uuid::Uuid;
-use crate::db::models::Notification;
-use crate::services::notification;
+use crate::channels::Channels;
+use crate::db::models::{Category, DeliveryMethod, Notification, NotificationAction, PendingNotification, Priority, SmsOverflow};
+use crate::services::{engagement, notification, preferences, preview::{self, ChannelPreview}, user};
 use time::OffsetDateTime;
 use time::format_description::well_known::Rfc3339;
 use crate::auth::extractor::AuthenticatedUser;
 
+const MAX_ACTIONS: usize = 5;
+
 #[derive(Serialize, Deserialize, ToSchema)]
 pub struct CreateNotification {
     pub user_id: String,
     pub content: String,
-    pub send_at: Option<String>,
+    pub html_content: Option<String>,   // Optional HTML body for email
+    pub title: Option<String>,          // Headline for push and chat
+    pub actions: Option<Vec<NotificationAction>>,  // Up to 5 link buttons for chat
+    pub send_at: Option<String>,        // RFC 3339, "optimal" for the best time in the window, or omitted for the preferred time
+    pub window_start: Option<String>,   // Delivery window for "optimal"; defaults to now
+    pub window_end: Option<String>,     // Defaults to 24 hours after the window start
+    pub channels: Option<Vec<String>>,  // Ordered fallback chain, e.g. ["Push", "SMS", "Email"]
+    pub channel: Option<String>,        // A single channel, or "auto" to pick the one the user engages with most
+    pub category: Option<String>,       // General (default), Marketing, Reminders or Security
+    pub priority: Option<String>,       // Low, Normal (default), High or Critical; only Critical may use Voice
+    pub voice_acknowledge: Option<bool>,  // Ask for "press 1 to acknowledge" on voice calls; defaults to true
+    pub template: Option<String>,       // Template key, used to group delivery analytics
+    pub sms_max_segments: Option<i32>,  // Segment budget if sent by SMS; defaults to SMS_MAX_SEGMENTS
+    pub sms_overflow: Option<String>,   // Truncate, Transliterate or Reject; defaults to SMS_OVERFLOW
+}
+
+/// Everything a notification would turn into, without it being stored or sent
+#[derive(Serialize, ToSchema)]
+pub struct PreviewResponse {
+    pub user_id: Uuid,
+    pub category: Category,
+    pub send_at: OffsetDateTime,  // When the dispatcher would pick it up, after the user's preferred time is applied
+    pub resolved_channel: Option<DeliveryMethod>,  // First channel in the chain that would be attempted
+    pub channels: Vec<ChannelPreview>,
 }
 
 #[derive(ToSchema, Serialize)]
@@ -29,7 +57,7 @@ pub struct NotificationResponse {
     request_body = CreateNotification,
     responses(
         (status = 200, description = "Notification successfully created", body = NotificationResponse),
-        (status = 400, description = "Invalid input"),
+        (status = 400, description = "Invalid input, an SMS body over its segment budget with the Reject policy, or Voice for a non-critical notification"),
         (status = 401, description = "Unauthorized")
     ),
     tag = "Notification API",
@@ -40,23 +68,25 @@ pub struct NotificationResponse {
 pub async fn create_notification(
     notification_data: web::Json<CreateNotification>,
     db: web::Data<PgPool>,
-    auth_user: AuthenticatedUser,  // Bearer authentication
+    channels: web::Data<Channels>,
+    _auth_user: AuthenticatedUser,  // Bearer authentication
 ) -> HttpResponse {
-    let user_id = match Uuid::parse_str(&notification_data.user_id) {
-        Ok(uuid) => uuid,
-        Err(_) => return invalid_uuid_response(),
-    };
-
-    let send_at = match parse_send_at(&notification_data.send_at) {
-        Ok(datetime) => datetime,
+    let new_notification = match build_notification(db.get_ref(), &notification_data).await {
+        Ok(notification) => notification,
         Err(err_response) => return err_response,
     };
 
-    let new_notification = Notification {
-        user_id,
-        content: notification_data.content.clone(),
-        send_at,
-    };
+    // Only a chain that may reach SMS has to fit the segment budget
+    let may_use_sms = new_notification
+        .channels
+        .as_ref()
+        .is_none_or(|chain| chain.contains(&DeliveryMethod::Sms));
+    if may_use_sms {
+        let policy = channels.sms_policy(new_notification.sms_max_segments, new_notification.sms_overflow);
+        if let Err(reason) = policy.apply(&new_notification.content) {
+            return bad_request(reason);
+        }
+    }
 
     match notification::create_notification(db.get_ref(), new_notification.clone()).await {
         Ok(_) => HttpResponse::Ok().json(NotificationResponse {
@@ -68,6 +98,241 @@ pub async fn create_notification(
     }
 }
 
+// POST /notifications/preview - Render a notification for every channel without storing or sending it.
+// The send time is resolved exactly as for a created notification, including the user's preferred time.
+#[utoipa::path(
+    post,
+    path = "/api/notifications/preview",
+    request_body = CreateNotification,
+    responses(
+        (status = 200, description = "Notification rendered", body = PreviewResponse),
+        (status = 400, description = "Invalid input"),
+        (status = 404, description = "User not found"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Previewing for another user requires admin")
+    ),
+    tag = "Notification API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn preview_notification(
+    notification_data: web::Json<CreateNotification>,
+    db: web::Data<PgPool>,
+    channels: web::Data<Channels>,
+    auth_user: AuthenticatedUser,  // Bearer authentication
+) -> HttpResponse {
+    // The preview shows the recipient's addresses and a working unsubscribe link
+    if let Ok(user_id) = Uuid::parse_str(&notification_data.user_id) {
+        if auth_user.sub != user_id && !auth_user.admin {
+            return HttpResponse::Forbidden().json(NotificationResponse {
+                success: false,
+                message: "You can only preview notifications for yourself".to_string(),
+                notification: None,
+            });
+        }
+    }
+
+    let new_notification = match build_notification(db.get_ref(), &notification_data).await {
+        Ok(notification) => notification,
+        Err(err_response) => return err_response,
+    };
+
+    let recipient = match user::find_user(db.get_ref(), new_notification.user_id).await {
+        Ok(Some(recipient)) => recipient,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(NotificationResponse {
+                success: false,
+                message: "User not found".to_string(),
+                notification: None,
+            })
+        }
+        Err(_) => return internal_server_error(),
+    };
+
+    // Never stored, so tracking and unsubscribe links are signed for the nil ID
+    let pending = PendingNotification {
+        id: Uuid::nil(),
+        user_id: new_notification.user_id,
+        content: new_notification.content.clone(),
+        html_content: new_notification.html_content.clone(),
+        title: new_notification.title.clone(),
+        actions: new_notification.actions.clone().map(Json),
+        channels: new_notification
+            .channels
+            .as_ref()
+            .map(|chain| chain.iter().map(|m| m.as_str().to_string()).collect()),
+        category: new_notification.category.as_str().to_string(),
+        priority: new_notification.priority.as_str().to_string(),
+        voice_acknowledge: new_notification.voice_acknowledge,
+        attempts: 0,
+        sms_max_segments: new_notification.sms_max_segments,
+        sms_overflow: new_notification.sms_overflow.map(|overflow| overflow.as_str().to_string()),
+    };
+
+    let (resolved_channel, channel_previews) =
+        match preview::preview_chain(db.get_ref(), &channels, &recipient, &pending).await {
+            Ok(result) => result,
+            Err(_) => return internal_server_error(),
+        };
+
+    // The dispatcher picks up anything due on its next poll
+    let now = OffsetDateTime::now_utc();
+    let send_at = new_notification.send_at.filter(|send_at| *send_at > now).unwrap_or(now);
+
+    HttpResponse::Ok().json(PreviewResponse {
+        user_id: new_notification.user_id,
+        category: new_notification.category,
+        send_at,
+        resolved_channel,
+        channels: channel_previews,
+    })
+}
+
+// POST /notifications/{id}/read - Mark a notification as read by its recipient
+#[utoipa::path(
+    post,
+    path = "/api/notifications/{id}/read",
+    responses(
+        (status = 200, description = "Notification marked as read", body = NotificationResponse),
+        (status = 404, description = "Notification not found"),
+        (status = 401, description = "Unauthorized")
+    ),
+    params(
+        ("id" = Uuid, Path, description = "ID of the notification")
+    ),
+    tag = "Notification API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn mark_read(
+    notification_id: web::Path<Uuid>,
+    db: web::Data<PgPool>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    match notification::mark_read(db.get_ref(), notification_id.into_inner(), auth_user.sub).await {
+        Ok(true) => HttpResponse::Ok().json(NotificationResponse {
+            success: true,
+            message: "Notification marked as read".to_string(),
+            notification: None,
+        }),
+        Ok(false) => HttpResponse::NotFound().json(NotificationResponse {
+            success: false,
+            message: "Notification not found".to_string(),
+            notification: None,
+        }),
+        Err(_) => internal_server_error(),
+    }
+}
+
+/// Validates a request body and resolves its send time and channels
+async fn build_notification(db: &PgPool, notification_data: &CreateNotification) -> Result<Notification, HttpResponse> {
+    let user_id = Uuid::parse_str(&notification_data.user_id).map_err(|_| invalid_uuid_response())?;
+
+    let send_at = if notification_data.send_at.as_deref() == Some("optimal") {
+        Some(optimal_send_at(db, user_id, notification_data).await?)
+    } else {
+        parse_send_at(&notification_data.send_at)?
+    };
+
+    let category = match notification_data.category.as_deref().map(str::parse::<Category>) {
+        Some(Ok(category)) => category,
+        Some(Err(e)) => return Err(bad_request(e)),
+        None => Category::General,
+    };
+
+    let priority = match notification_data.priority.as_deref().map(str::parse::<Priority>) {
+        Some(Ok(priority)) => priority,
+        Some(Err(e)) => return Err(bad_request(e)),
+        None => Priority::Normal,
+    };
+
+    let preferred_time = preferences::get_preferred_time(db, user_id).await.map_err(|_| internal_server_error())?;
+    let send_at = notification::resolve_send_at(send_at, priority, preferred_time, OffsetDateTime::now_utc());
+
+    let channels = resolve_channels(db, user_id, category, priority, notification_data).await?;
+    if priority != Priority::Critical {
+        check_no_voice(db, user_id, channels.as_deref()).await?;
+    }
+
+    if notification_data.sms_max_segments.is_some_and(|max| max < 1) {
+        return Err(bad_request("sms_max_segments must be at least 1".to_string()));
+    }
+
+    let sms_overflow = match notification_data.sms_overflow.as_deref().map(str::parse::<SmsOverflow>) {
+        Some(Ok(overflow)) => Some(overflow),
+        Some(Err(e)) => return Err(bad_request(e)),
+        None => None,
+    };
+
+    if let Some(actions) = &notification_data.actions {
+        if actions.len() > MAX_ACTIONS {
+            return Err(bad_request(format!("At most {} actions are allowed", MAX_ACTIONS)));
+        }
+        let valid = |action: &NotificationAction| {
+            !action.label.trim().is_empty()
+                && Url::parse(&action.url).is_ok_and(|url| url.scheme() == "https" || url.scheme() == "http")
+        };
+        if !actions.iter().all(valid) {
+            return Err(bad_request("Every action needs a label and an http(s) URL".to_string()));
+        }
+    }
+
+    Ok(Notification {
+        user_id,
+        content: notification_data.content.clone(),
+        html_content: notification_data.html_content.clone(),
+        title: notification_data.title.clone().filter(|title| !title.trim().is_empty()),
+        actions: notification_data.actions.clone().filter(|actions| !actions.is_empty()),
+        send_at,
+        channels,
+        category,
+        priority,
+        voice_acknowledge: notification_data.voice_acknowledge.unwrap_or(true),
+        template: notification_data.template.clone(),
+        experiment_id: None,
+        variant: None,
+        sms_max_segments: notification_data.sms_max_segments,
+        sms_overflow,
+    })
+}
+
+/// Rejects a non-critical notification whose chain, as the dispatcher will resolve it, relies on Voice
+///
+/// An explicit chain may not name Voice at all; the dispatcher skips Voice in the user's own
+/// chain, so that only needs another channel to fall back to.
+async fn check_no_voice(db: &PgPool, user_id: Uuid, explicit: Option<&[DeliveryMethod]>) -> Result<(), HttpResponse> {
+    if explicit.is_some_and(|chain| chain.contains(&DeliveryMethod::Voice)) {
+        return Err(bad_request("Voice is only allowed for critical notifications".to_string()));
+    }
+
+    let prefs = preferences::get_preferences(db, user_id).await.map_err(|_| internal_server_error())?;
+    let chain = preferences::resolve_chain(explicit, prefs.as_ref());
+    if chain.iter().all(|method| *method == DeliveryMethod::Voice) {
+        return Err(bad_request("The user can only be reached by Voice, which is only allowed for critical notifications".to_string()));
+    }
+    Ok(())
+}
+
+/// Resolves `send_at: "optimal"` to the user's best predicted time within the delivery window
+async fn optimal_send_at(
+    db: &PgPool,
+    user_id: Uuid,
+    notification_data: &CreateNotification,
+) -> Result<OffsetDateTime, HttpResponse> {
+    let start = parse_send_at(&notification_data.window_start)?.unwrap_or_else(OffsetDateTime::now_utc);
+    let end = parse_send_at(&notification_data.window_end)?.unwrap_or(start + time::Duration::DAY);
+
+    if end <= start {
+        return Err(bad_request("Delivery window must end after it starts".to_string()));
+    }
+
+    engagement::optimal_send_time(db, user_id, start, end)
+        .await
+        .map_err(|_| internal_server_error())
+}
+
 fn parse_send_at(send_at_str: &Option<String>) -> Result<Option<OffsetDateTime>, HttpResponse> {
     match send_at_str {
         Some(date) => match OffsetDateTime::parse(date, &Rfc3339) {
@@ -82,6 +347,55 @@ fn parse_send_at(send_at_str: &Option<String>) -> Result<Option<OffsetDateTime>,
     }
 }
 
+/// Turns `channel` / `channels` into the fallback chain stored on the notification
+async fn resolve_channels(
+    db: &PgPool,
+    user_id: Uuid,
+    category: Category,
+    priority: Priority,
+    notification_data: &CreateNotification,
+) -> Result<Option<Vec<DeliveryMethod>>, HttpResponse> {
+    match (notification_data.channel.as_deref(), &notification_data.channels) {
+        (Some(_), Some(_)) => Err(bad_request("Specify either channel or channels, not both".to_string())),
+        (Some("auto"), None) => {
+            // Keep every enabled channel, best first, so the fallback chain still applies
+            let mut conn = db.acquire().await.map_err(|_| internal_server_error())?;
+            let mut ranked = engagement::auto_channels(&mut conn, user_id, category)
+                .await
+                .map_err(|_| internal_server_error())?;
+            if priority != Priority::Critical {
+                ranked.retain(|method| *method != DeliveryMethod::Voice);
+            }
+            Ok(Some(ranked).filter(|chain| !chain.is_empty()))
+        }
+        (Some(name), None) => name
+            .parse::<DeliveryMethod>()
+            .map(|method| Some(vec![method]))
+            .map_err(bad_request),
+        (None, channels) => parse_channels(channels),
+    }
+}
+
+fn parse_channels(channels: &Option<Vec<String>>) -> Result<Option<Vec<DeliveryMethod>>, HttpResponse> {
+    match channels {
+        Some(names) => names
+            .iter()
+            .map(|name| name.parse::<DeliveryMethod>())
+            .collect::<Result<Vec<_>, _>>()
+            .map(Some)
+            .map_err(bad_request),
+        None => Ok(None),
+    }
+}
+
+fn bad_request(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(NotificationResponse {
+        success: false,
+        message,
+        notification: None,
+    })
+}
+
 fn invalid_uuid_response() -> HttpResponse {
     HttpResponse::BadRequest().json(NotificationResponse {
         success: false,
@@ -100,5 +414,7 @@ fn internal_server_error() -> HttpResponse {
 
 
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
-    cfg.route("/notifications", web::post().to(create_notification));
+    cfg.route("/notifications", web::post().to(create_notification))
+        .route("/notifications/preview", web::post().to(preview_notification))
+        .route("/notifications/{id}/read", web::post().to(mark_read));
 }
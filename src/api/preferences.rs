@@ -0,0 +1,456 @@
+use actix_web::{web, HttpResponse};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::auth::extractor::AuthenticatedUser;
+use crate::channels::{chat, public_addr};
+use crate::config::Config;
+use crate::db::models::{Category, DeliveryMethod, OptOut};
+use crate::services::{chat_webhooks, preferences, push_servers};
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct UpdatePreferencesRequest {
+    pub preferred_method: Option<String>,
+    pub fallback_chain: Option<Vec<String>>,  // Channels to try in order, e.g. ["Push", "SMS", "Email"]
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct OptOutRequest {
+    pub category: String,
+    pub channel: Option<String>,  // Omit to opt out on every channel
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ChatWebhookRequest {
+    pub url: String,  // Incoming-webhook URL from Slack, Discord or a Teams channel/workflow
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PushServerRequest {
+    pub server_url: String,  // Base URL of the ntfy or Gotify server
+    pub topic: Option<String>,  // ntfy only, required there
+    pub token: Option<String>,  // ntfy access token for protected topics, or the Gotify application token (required)
+}
+
+// GET /me/preferences - Fetch the caller's delivery preferences
+#[utoipa::path(
+    get,
+    path = "/api/me/preferences",
+    responses(
+        (status = 200, description = "Preferences retrieved successfully", body = UserPreferences),
+        (status = 404, description = "No preferences saved yet"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "Preferences API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn get_preferences(
+    db: web::Data<PgPool>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    match preferences::get_preferences(db.get_ref(), auth_user.sub).await {
+        Ok(Some(prefs)) => HttpResponse::Ok().json(prefs),
+        Ok(None) => HttpResponse::NotFound().json("Preferences not found"),
+        Err(_) => HttpResponse::InternalServerError().json("Error fetching preferences"),
+    }
+}
+
+// PUT /me/preferences - Create or update the caller's delivery preferences
+#[utoipa::path(
+    put,
+    path = "/api/me/preferences",
+    request_body = UpdatePreferencesRequest,
+    responses(
+        (status = 200, description = "Preferences updated successfully", body = UserPreferences),
+        (status = 400, description = "Unknown delivery method"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "Preferences API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn update_preferences(
+    prefs_data: web::Json<UpdatePreferencesRequest>,
+    db: web::Data<PgPool>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    let mut preferred_method = match prefs_data.preferred_method.as_deref().map(str::parse::<DeliveryMethod>) {
+        Some(Ok(method)) => Some(method),
+        Some(Err(e)) => return HttpResponse::BadRequest().json(e),
+        None => None,
+    };
+
+    let fallback_chain = match &prefs_data.fallback_chain {
+        Some(names) => match names.iter().map(|name| name.parse()).collect::<Result<Vec<DeliveryMethod>, _>>() {
+            Ok(chain) => Some(chain),
+            Err(e) => return HttpResponse::BadRequest().json(e),
+        },
+        None => None,
+    };
+
+    // Keep the preferred method in step with the head of the chain unless both were given
+    if preferred_method.is_none() {
+        preferred_method = fallback_chain.as_ref().and_then(|chain| chain.first().copied());
+    }
+
+    match preferences::upsert_preferences(db.get_ref(), auth_user.sub, preferred_method, fallback_chain).await {
+        Ok(prefs) => HttpResponse::Ok().json(prefs),
+        Err(_) => HttpResponse::InternalServerError().json("Error updating preferences"),
+    }
+}
+
+// GET /me/opt-outs - List the categories the caller has opted out of
+#[utoipa::path(
+    get,
+    path = "/api/me/opt-outs",
+    responses(
+        (status = 200, description = "Opt-outs retrieved successfully", body = [OptOut]),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "Preferences API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn list_opt_outs(
+    db: web::Data<PgPool>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    match preferences::get_opt_outs(db.get_ref(), auth_user.sub).await {
+        Ok(opt_outs) => HttpResponse::Ok().json(opt_outs),
+        Err(_) => HttpResponse::InternalServerError().json("Error fetching opt-outs"),
+    }
+}
+
+// POST /me/opt-outs - Opt out of a category on one or all channels
+#[utoipa::path(
+    post,
+    path = "/api/me/opt-outs",
+    request_body = OptOutRequest,
+    responses(
+        (status = 200, description = "Opted out successfully"),
+        (status = 400, description = "Unknown or non-optional category, or unknown channel"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "Preferences API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn add_opt_out(
+    opt_out_data: web::Json<OptOutRequest>,
+    db: web::Data<PgPool>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    let opt_out = match parse_opt_out(&opt_out_data) {
+        Ok(opt_out) => opt_out,
+        Err(err_response) => return err_response,
+    };
+
+    if !opt_out.category.is_optional() {
+        return HttpResponse::BadRequest().json(format!("{} notifications cannot be opted out of", opt_out.category));
+    }
+
+    match preferences::add_opt_out(db.get_ref(), auth_user.sub, &opt_out).await {
+        Ok(_) => HttpResponse::Ok().json("Opted out"),
+        Err(_) => HttpResponse::InternalServerError().json("Error saving opt-out"),
+    }
+}
+
+// DELETE /me/opt-outs - Opt back in to a category
+#[utoipa::path(
+    delete,
+    path = "/api/me/opt-outs",
+    request_body = OptOutRequest,
+    responses(
+        (status = 200, description = "Opted back in successfully"),
+        (status = 404, description = "No matching opt-out"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "Preferences API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn remove_opt_out(
+    opt_out_data: web::Json<OptOutRequest>,
+    db: web::Data<PgPool>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    let opt_out = match parse_opt_out(&opt_out_data) {
+        Ok(opt_out) => opt_out,
+        Err(err_response) => return err_response,
+    };
+
+    match preferences::remove_opt_out(db.get_ref(), auth_user.sub, &opt_out).await {
+        Ok(true) => HttpResponse::Ok().json("Opted back in"),
+        Ok(false) => HttpResponse::NotFound().json("Opt-out not found"),
+        Err(_) => HttpResponse::InternalServerError().json("Error removing opt-out"),
+    }
+}
+
+// GET /me/chat-webhooks - List the caller's Slack, Discord and Teams webhooks
+#[utoipa::path(
+    get,
+    path = "/api/me/chat-webhooks",
+    responses(
+        (status = 200, description = "Chat webhooks", body = [ChatWebhook]),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "Preferences API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn list_chat_webhooks(
+    db: web::Data<PgPool>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    match chat_webhooks::list_webhooks(db.get_ref(), auth_user.sub).await {
+        Ok(webhooks) => HttpResponse::Ok().json(webhooks),
+        Err(_) => HttpResponse::InternalServerError().json("Error fetching chat webhooks"),
+    }
+}
+
+// PUT /me/chat-webhooks/{provider} - Set the incoming-webhook URL for Slack, Discord or Teams
+#[utoipa::path(
+    put,
+    path = "/api/me/chat-webhooks/{provider}",
+    params(
+        ("provider" = String, Path, description = "Slack, Discord or Teams")
+    ),
+    request_body = ChatWebhookRequest,
+    responses(
+        (status = 200, description = "Webhook saved", body = ChatWebhook),
+        (status = 400, description = "Unknown provider or invalid URL"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "Preferences API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn set_chat_webhook(
+    provider: web::Path<String>,
+    webhook_data: web::Json<ChatWebhookRequest>,
+    db: web::Data<PgPool>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    let provider = match parse_chat_provider(&provider) {
+        Ok(provider) => provider,
+        Err(err_response) => return err_response,
+    };
+
+    let url = webhook_data.url.trim();
+    if !Url::parse(url).is_ok_and(|url| chat::is_provider_url(provider, &url)) {
+        return HttpResponse::BadRequest().json(format!("Webhook URL must be an https:// {} incoming-webhook URL", provider));
+    }
+
+    match chat_webhooks::set_webhook(db.get_ref(), auth_user.sub, provider, url).await {
+        Ok(webhook) => HttpResponse::Ok().json(webhook),
+        Err(_) => HttpResponse::InternalServerError().json("Error saving chat webhook"),
+    }
+}
+
+// DELETE /me/chat-webhooks/{provider} - Stop posting notifications to a chat service
+#[utoipa::path(
+    delete,
+    path = "/api/me/chat-webhooks/{provider}",
+    params(
+        ("provider" = String, Path, description = "Slack, Discord or Teams")
+    ),
+    responses(
+        (status = 200, description = "Webhook removed"),
+        (status = 400, description = "Unknown provider"),
+        (status = 404, description = "No webhook set for this provider"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "Preferences API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn remove_chat_webhook(
+    provider: web::Path<String>,
+    db: web::Data<PgPool>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    let provider = match parse_chat_provider(&provider) {
+        Ok(provider) => provider,
+        Err(err_response) => return err_response,
+    };
+
+    match chat_webhooks::remove_webhook(db.get_ref(), auth_user.sub, provider).await {
+        Ok(true) => HttpResponse::Ok().json("Chat webhook removed"),
+        Ok(false) => HttpResponse::NotFound().json("Chat webhook not found"),
+        Err(_) => HttpResponse::InternalServerError().json("Error removing chat webhook"),
+    }
+}
+
+// GET /me/push-servers - List the caller's ntfy and Gotify servers
+#[utoipa::path(
+    get,
+    path = "/api/me/push-servers",
+    responses(
+        (status = 200, description = "Self-hosted push servers", body = [PushServer]),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "Preferences API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn list_push_servers(
+    db: web::Data<PgPool>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    match push_servers::list_servers(db.get_ref(), auth_user.sub).await {
+        Ok(servers) => HttpResponse::Ok().json(servers),
+        Err(_) => HttpResponse::InternalServerError().json("Error fetching push servers"),
+    }
+}
+
+// PUT /me/push-servers/{provider} - Set the ntfy topic or Gotify application to publish to
+#[utoipa::path(
+    put,
+    path = "/api/me/push-servers/{provider}",
+    params(
+        ("provider" = String, Path, description = "Ntfy or Gotify")
+    ),
+    request_body = PushServerRequest,
+    responses(
+        (status = 200, description = "Server saved", body = PushServer),
+        (status = 400, description = "Unknown provider, invalid URL or topic, or missing token"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "Preferences API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn set_push_server(
+    provider: web::Path<String>,
+    server_data: web::Json<PushServerRequest>,
+    db: web::Data<PgPool>,
+    config: web::Data<Config>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    let provider = match parse_push_server_provider(&provider) {
+        Ok(provider) => provider,
+        Err(err_response) => return err_response,
+    };
+
+    let server_url = server_data.server_url.trim().trim_end_matches('/');
+    let url = match Url::parse(server_url) {
+        Ok(url) if url.host().is_some() && (url.scheme() == "https" || (config.webhook_allow_http && url.scheme() == "http")) => url,
+        _ => return HttpResponse::BadRequest().json("Server URL must be an https:// URL"),
+    };
+    if let Err(e) = public_addr::check_host(&url).await {
+        return HttpResponse::BadRequest().json(format!("Server URL must point to a public server: {}", e));
+    }
+
+    let topic = server_data.topic.as_deref().map(str::trim).filter(|topic| !topic.is_empty());
+    let token = server_data.token.as_deref().map(str::trim).filter(|token| !token.is_empty());
+    match provider {
+        DeliveryMethod::Ntfy => {
+            let valid = topic.is_some_and(|topic| {
+                topic.len() <= 64 && topic.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            });
+            if !valid {
+                return HttpResponse::BadRequest().json("ntfy needs a topic of up to 64 letters, digits, - or _");
+            }
+        }
+        _ => {
+            if topic.is_some() {
+                return HttpResponse::BadRequest().json("Gotify does not use topics");
+            }
+            if token.is_none() {
+                return HttpResponse::BadRequest().json("Gotify needs an application token");
+            }
+        }
+    }
+
+    match push_servers::set_server(db.get_ref(), auth_user.sub, provider, server_url, topic, token).await {
+        Ok(server) => HttpResponse::Ok().json(server),
+        Err(_) => HttpResponse::InternalServerError().json("Error saving push server"),
+    }
+}
+
+// DELETE /me/push-servers/{provider} - Stop publishing notifications to ntfy or Gotify
+#[utoipa::path(
+    delete,
+    path = "/api/me/push-servers/{provider}",
+    params(
+        ("provider" = String, Path, description = "Ntfy or Gotify")
+    ),
+    responses(
+        (status = 200, description = "Server removed"),
+        (status = 400, description = "Unknown provider"),
+        (status = 404, description = "No server set for this provider"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "Preferences API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn remove_push_server(
+    provider: web::Path<String>,
+    db: web::Data<PgPool>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    let provider = match parse_push_server_provider(&provider) {
+        Ok(provider) => provider,
+        Err(err_response) => return err_response,
+    };
+
+    match push_servers::remove_server(db.get_ref(), auth_user.sub, provider).await {
+        Ok(true) => HttpResponse::Ok().json("Push server removed"),
+        Ok(false) => HttpResponse::NotFound().json("Push server not found"),
+        Err(_) => HttpResponse::InternalServerError().json("Error removing push server"),
+    }
+}
+
+fn parse_chat_provider(value: &str) -> Result<DeliveryMethod, HttpResponse> {
+    match value.parse::<DeliveryMethod>() {
+        Ok(method) if method.is_chat() => Ok(method),
+        _ => Err(HttpResponse::BadRequest().json(format!("Unknown chat provider: {}", value))),
+    }
+}
+
+fn parse_push_server_provider(value: &str) -> Result<DeliveryMethod, HttpResponse> {
+    match value.parse::<DeliveryMethod>() {
+        Ok(method) if method.is_self_hosted() => Ok(method),
+        _ => Err(HttpResponse::BadRequest().json(format!("Unknown push server provider: {}", value))),
+    }
+}
+
+fn parse_opt_out(request: &OptOutRequest) -> Result<OptOut, HttpResponse> {
+    let category = request.category.parse::<Category>().map_err(|e| HttpResponse::BadRequest().json(e))?;
+    let channel = match request.channel.as_deref().map(str::parse::<DeliveryMethod>) {
+        Some(Ok(channel)) => Some(channel),
+        Some(Err(e)) => return Err(HttpResponse::BadRequest().json(e)),
+        None => None,
+    };
+
+    Ok(OptOut { category, channel })
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/me/preferences", web::get().to(get_preferences))                       // GET /me/preferences
+        .route("/me/preferences", web::put().to(update_preferences))                   // PUT /me/preferences
+        .route("/me/opt-outs", web::get().to(list_opt_outs))                           // GET /me/opt-outs
+        .route("/me/opt-outs", web::post().to(add_opt_out))                            // POST /me/opt-outs
+        .route("/me/opt-outs", web::delete().to(remove_opt_out))                       // DELETE /me/opt-outs
+        .route("/me/chat-webhooks", web::get().to(list_chat_webhooks))                 // GET /me/chat-webhooks
+        .route("/me/chat-webhooks/{provider}", web::put().to(set_chat_webhook))        // PUT /me/chat-webhooks/{provider}
+        .route("/me/chat-webhooks/{provider}", web::delete().to(remove_chat_webhook))  // DELETE /me/chat-webhooks/{provider}
+        .route("/me/push-servers", web::get().to(list_push_servers))                   // GET /me/push-servers
+        .route("/me/push-servers/{provider}", web::put().to(set_push_server))          // PUT /me/push-servers/{provider}
+        .route("/me/push-servers/{provider}", web::delete().to(remove_push_server));   // DELETE /me/push-servers/{provider}
+}
@@ -0,0 +1,412 @@
+use actix_web::{web, HttpResponse};
+use log::info;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::auth::extractor::AdminUser;
+use crate::db::models::{Category, DeliveryMethod};
+use crate::services::rules::{self, Condition, NewRule};
+use crate::services::template::{self, Variant};
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct UpsertTemplateRequest {
+    pub content: String,               // Plain text body; {{property}} placeholders come from event properties
+    pub html_content: Option<String>,
+    pub category: Option<String>,      // General (default), Marketing, Reminders or Security
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ReplaceVariantsRequest {
+    pub experiment_id: Option<String>,  // Defaults to the template key; change it to reshuffle assignments
+    pub variants: Vec<Variant>,         // An empty list ends the experiment
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct VariantsResponse {
+    pub experiment_id: Option<String>,
+    pub variants: Vec<Variant>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CreateRuleRequest {
+    pub name: String,
+    pub event_type: String,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,    // All must hold for the rule to fire
+    pub template_key: String,
+    pub channel: Option<String>,       // A delivery method or "auto"; omit to follow the user's preferences
+    pub delay_seconds: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct UpdateRuleRequest {
+    pub enabled: bool,
+}
+
+// GET /admin/templates - List notification templates
+#[utoipa::path(
+    get,
+    path = "/api/admin/templates",
+    responses(
+        (status = 200, description = "Templates retrieved successfully", body = [Template]),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    tag = "Rules API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn list_templates(db: web::Data<PgPool>, _admin: AdminUser) -> HttpResponse {
+    match template::list_templates(db.get_ref()).await {
+        Ok(templates) => HttpResponse::Ok().json(templates),
+        Err(_) => HttpResponse::InternalServerError().json("Error fetching templates"),
+    }
+}
+
+// GET /admin/templates/{key} - Fetch one template
+#[utoipa::path(
+    get,
+    path = "/api/admin/templates/{key}",
+    params(
+        ("key" = String, Path, description = "Template key")
+    ),
+    responses(
+        (status = 200, description = "Template retrieved successfully", body = Template),
+        (status = 404, description = "Template not found"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    tag = "Rules API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn get_template(key: web::Path<String>, db: web::Data<PgPool>, _admin: AdminUser) -> HttpResponse {
+    match template::get_template(db.get_ref(), &key).await {
+        Ok(Some(template)) => HttpResponse::Ok().json(template),
+        Ok(None) => HttpResponse::NotFound().json("Template not found"),
+        Err(_) => HttpResponse::InternalServerError().json("Error fetching template"),
+    }
+}
+
+// PUT /admin/templates/{key} - Create or replace a template
+#[utoipa::path(
+    put,
+    path = "/api/admin/templates/{key}",
+    params(
+        ("key" = String, Path, description = "Template key")
+    ),
+    request_body = UpsertTemplateRequest,
+    responses(
+        (status = 200, description = "Template saved", body = Template),
+        (status = 400, description = "Invalid template"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    tag = "Rules API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn upsert_template(
+    key: web::Path<String>,
+    body: web::Json<UpsertTemplateRequest>,
+    db: web::Data<PgPool>,
+    admin: AdminUser,
+) -> HttpResponse {
+    let category = match body.category.as_deref().map(str::parse::<Category>) {
+        Some(Ok(category)) => category,
+        Some(Err(e)) => return HttpResponse::BadRequest().json(e),
+        None => Category::General,
+    };
+
+    match template::upsert_template(db.get_ref(), &key, &body.content, body.html_content.as_deref(), category).await {
+        Ok(template) => {
+            info!("Admin {} saved template {}", admin.0.sub, template.key);
+            HttpResponse::Ok().json(template)
+        }
+        Err(_) => HttpResponse::InternalServerError().json("Error saving template"),
+    }
+}
+
+// DELETE /admin/templates/{key} - Delete a template no rule uses
+#[utoipa::path(
+    delete,
+    path = "/api/admin/templates/{key}",
+    params(
+        ("key" = String, Path, description = "Template key")
+    ),
+    responses(
+        (status = 200, description = "Template deleted"),
+        (status = 404, description = "Template not found"),
+        (status = 409, description = "Template is used by a rule"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    tag = "Rules API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn delete_template(key: web::Path<String>, db: web::Data<PgPool>, _admin: AdminUser) -> HttpResponse {
+    match template::delete_template(db.get_ref(), &key).await {
+        Ok(true) => HttpResponse::Ok().json("Template deleted"),
+        Ok(false) => HttpResponse::NotFound().json("Template not found"),
+        Err(sqlx::Error::Database(e)) if e.is_foreign_key_violation() => {
+            HttpResponse::Conflict().json("Template is used by a rule")
+        }
+        Err(_) => HttpResponse::InternalServerError().json("Error deleting template"),
+    }
+}
+
+// GET /admin/templates/{key}/variants - Show a template's A/B variants
+#[utoipa::path(
+    get,
+    path = "/api/admin/templates/{key}/variants",
+    params(
+        ("key" = String, Path, description = "Template key")
+    ),
+    responses(
+        (status = 200, description = "Variants retrieved successfully", body = VariantsResponse),
+        (status = 404, description = "Template not found"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    tag = "Rules API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn get_variants(key: web::Path<String>, db: web::Data<PgPool>, _admin: AdminUser) -> HttpResponse {
+    let template = match template::get_template(db.get_ref(), &key).await {
+        Ok(Some(template)) => template,
+        Ok(None) => return HttpResponse::NotFound().json("Template not found"),
+        Err(_) => return HttpResponse::InternalServerError().json("Error fetching variants"),
+    };
+
+    match template::get_variants(db.get_ref(), &key).await {
+        Ok(variants) => HttpResponse::Ok().json(VariantsResponse {
+            experiment_id: template.experiment_id,
+            variants,
+        }),
+        Err(_) => HttpResponse::InternalServerError().json("Error fetching variants"),
+    }
+}
+
+// PUT /admin/templates/{key}/variants - Start, change or end an A/B test on a template
+#[utoipa::path(
+    put,
+    path = "/api/admin/templates/{key}/variants",
+    params(
+        ("key" = String, Path, description = "Template key")
+    ),
+    request_body = ReplaceVariantsRequest,
+    responses(
+        (status = 200, description = "Variants saved", body = VariantsResponse),
+        (status = 400, description = "Invalid variants"),
+        (status = 404, description = "Template not found"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    tag = "Rules API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn replace_variants(
+    key: web::Path<String>,
+    body: web::Json<ReplaceVariantsRequest>,
+    db: web::Data<PgPool>,
+    admin: AdminUser,
+) -> HttpResponse {
+    if body.variants.len() == 1 {
+        return HttpResponse::BadRequest().json("An experiment needs at least two variants");
+    }
+    if body.variants.iter().any(|variant| variant.weight <= 0) {
+        return HttpResponse::BadRequest().json("Variant weights must be positive");
+    }
+    if body.variants.iter().any(|variant| variant.name.trim().is_empty()) {
+        return HttpResponse::BadRequest().json("Variant names must not be empty");
+    }
+
+    match template::get_template(db.get_ref(), &key).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json("Template not found"),
+        Err(_) => return HttpResponse::InternalServerError().json("Error saving variants"),
+    }
+
+    match template::replace_variants(db.get_ref(), &key, body.experiment_id.as_deref(), &body.variants).await {
+        Ok(()) => {
+            info!("Admin {} set {} variant(s) on template {}", admin.0.sub, body.variants.len(), key);
+            let experiment_id = (!body.variants.is_empty()).then(|| body.experiment_id.clone().unwrap_or(key.clone()));
+            HttpResponse::Ok().json(VariantsResponse {
+                experiment_id,
+                variants: body.into_inner().variants,
+            })
+        }
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            HttpResponse::BadRequest().json("Variant names must be unique")
+        }
+        Err(_) => HttpResponse::InternalServerError().json("Error saving variants"),
+    }
+}
+
+// GET /admin/rules - List event rules
+#[utoipa::path(
+    get,
+    path = "/api/admin/rules",
+    responses(
+        (status = 200, description = "Rules retrieved successfully", body = [Rule]),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    tag = "Rules API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn list_rules(db: web::Data<PgPool>, _admin: AdminUser) -> HttpResponse {
+    match rules::list_rules(db.get_ref()).await {
+        Ok(rules) => HttpResponse::Ok().json(rules),
+        Err(_) => HttpResponse::InternalServerError().json("Error fetching rules"),
+    }
+}
+
+// POST /admin/rules - Send a template whenever a matching event arrives
+#[utoipa::path(
+    post,
+    path = "/api/admin/rules",
+    request_body = CreateRuleRequest,
+    responses(
+        (status = 201, description = "Rule created", body = Rule),
+        (status = 400, description = "Invalid rule"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    tag = "Rules API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn create_rule(
+    body: web::Json<CreateRuleRequest>,
+    db: web::Data<PgPool>,
+    admin: AdminUser,
+) -> HttpResponse {
+    let body = body.into_inner();
+
+    if body.event_type.trim().is_empty() {
+        return HttpResponse::BadRequest().json("Event type is required");
+    }
+    if let Some(channel) = body.channel.as_deref().filter(|c| *c != "auto") {
+        match channel.parse::<DeliveryMethod>() {
+            // Rules create Normal notifications, which are never called
+            Ok(DeliveryMethod::Voice) => {
+                return HttpResponse::BadRequest().json("Voice is only allowed for critical notifications, which rules do not send");
+            }
+            Ok(_) => {}
+            Err(e) => return HttpResponse::BadRequest().json(e),
+        }
+    }
+    let delay_seconds = body.delay_seconds.unwrap_or(0);
+    if delay_seconds < 0 {
+        return HttpResponse::BadRequest().json("Delay must not be negative");
+    }
+
+    match template::get_template(db.get_ref(), &body.template_key).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::BadRequest().json("Unknown template"),
+        Err(_) => return HttpResponse::InternalServerError().json("Error creating rule"),
+    }
+
+    let new_rule = NewRule {
+        name: body.name,
+        event_type: body.event_type,
+        conditions: body.conditions,
+        template_key: body.template_key,
+        channel: body.channel,
+        delay_seconds,
+    };
+
+    match rules::create_rule(db.get_ref(), new_rule).await {
+        Ok(rule) => {
+            info!("Admin {} created rule {} for {} events", admin.0.sub, rule.id, rule.event_type);
+            HttpResponse::Created().json(rule)
+        }
+        Err(_) => HttpResponse::InternalServerError().json("Error creating rule"),
+    }
+}
+
+// PATCH /admin/rules/{id} - Enable or disable a rule
+#[utoipa::path(
+    patch,
+    path = "/api/admin/rules/{id}",
+    params(
+        ("id" = Uuid, Path, description = "ID of the rule")
+    ),
+    request_body = UpdateRuleRequest,
+    responses(
+        (status = 200, description = "Rule updated"),
+        (status = 404, description = "Rule not found"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    tag = "Rules API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn update_rule(
+    rule_id: web::Path<Uuid>,
+    body: web::Json<UpdateRuleRequest>,
+    db: web::Data<PgPool>,
+    _admin: AdminUser,
+) -> HttpResponse {
+    match rules::set_rule_enabled(db.get_ref(), rule_id.into_inner(), body.enabled).await {
+        Ok(true) => HttpResponse::Ok().json("Rule updated"),
+        Ok(false) => HttpResponse::NotFound().json("Rule not found"),
+        Err(_) => HttpResponse::InternalServerError().json("Error updating rule"),
+    }
+}
+
+// DELETE /admin/rules/{id} - Delete a rule
+#[utoipa::path(
+    delete,
+    path = "/api/admin/rules/{id}",
+    params(
+        ("id" = Uuid, Path, description = "ID of the rule")
+    ),
+    responses(
+        (status = 200, description = "Rule deleted"),
+        (status = 404, description = "Rule not found"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    tag = "Rules API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn delete_rule(rule_id: web::Path<Uuid>, db: web::Data<PgPool>, _admin: AdminUser) -> HttpResponse {
+    match rules::delete_rule(db.get_ref(), rule_id.into_inner()).await {
+        Ok(true) => HttpResponse::Ok().json("Rule deleted"),
+        Ok(false) => HttpResponse::NotFound().json("Rule not found"),
+        Err(_) => HttpResponse::InternalServerError().json("Error deleting rule"),
+    }
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/admin/templates", web::get().to(list_templates))          // GET /admin/templates
+        .route("/admin/templates/{key}", web::get().to(get_template))       // GET /admin/templates/{key}
+        .route("/admin/templates/{key}", web::put().to(upsert_template))    // PUT /admin/templates/{key}
+        .route("/admin/templates/{key}", web::delete().to(delete_template)) // DELETE /admin/templates/{key}
+        .route("/admin/templates/{key}/variants", web::get().to(get_variants))      // GET /admin/templates/{key}/variants
+        .route("/admin/templates/{key}/variants", web::put().to(replace_variants))  // PUT /admin/templates/{key}/variants
+        .route("/admin/rules", web::get().to(list_rules))                   // GET /admin/rules
+        .route("/admin/rules", web::post().to(create_rule))                 // POST /admin/rules
+        .route("/admin/rules/{id}", web::patch().to(update_rule))           // PATCH /admin/rules/{id}
+        .route("/admin/rules/{id}", web::delete().to(delete_rule));         // DELETE /admin/rules/{id}
+}
@@ -0,0 +1,73 @@
+use actix_web::{web, HttpResponse};
+use log::info;
+use serde::Deserialize;
+use sqlx::PgPool;
+use utoipa::IntoParams;
+
+use crate::auth::extractor::AdminUser;
+use crate::channels::sms::provider;
+
+#[derive(Deserialize, IntoParams)]
+pub struct MockMessagesQuery {
+    pub to: Option<String>,  // Only messages sent to this number
+    pub limit: Option<i64>,
+}
+
+// GET /admin/sms/mock-messages - Messages recorded by the mock SMS provider
+#[utoipa::path(
+    get,
+    path = "/api/admin/sms/mock-messages",
+    params(
+        MockMessagesQuery
+    ),
+    responses(
+        (status = 200, description = "Recorded messages, newest first", body = [MockSmsMessage]),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    tag = "SMS API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn list_mock_messages(
+    query: web::Query<MockMessagesQuery>,
+    db: web::Data<PgPool>,
+    _admin: AdminUser,
+) -> HttpResponse {
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+
+    match provider::list_mock_messages(db.get_ref(), query.to.as_deref(), limit).await {
+        Ok(messages) => HttpResponse::Ok().json(messages),
+        Err(_) => HttpResponse::InternalServerError().json("Error fetching mock messages"),
+    }
+}
+
+// DELETE /admin/sms/mock-messages - Forget every message recorded by the mock provider
+#[utoipa::path(
+    delete,
+    path = "/api/admin/sms/mock-messages",
+    responses(
+        (status = 200, description = "Mock messages cleared"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    tag = "SMS API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn clear_mock_messages(db: web::Data<PgPool>, admin: AdminUser) -> HttpResponse {
+    match provider::clear_mock_messages(db.get_ref()).await {
+        Ok(count) => {
+            info!("Admin {} cleared {} mock SMS messages", admin.0.sub, count);
+            HttpResponse::Ok().json("Mock messages cleared")
+        }
+        Err(_) => HttpResponse::InternalServerError().json("Error clearing mock messages"),
+    }
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/admin/sms/mock-messages", web::get().to(list_mock_messages))       // GET /admin/sms/mock-messages
+        .route("/admin/sms/mock-messages", web::delete().to(clear_mock_messages)); // DELETE /admin/sms/mock-messages
+}
@@ -0,0 +1,197 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use log::info;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::auth::extractor::AdminUser;
+use crate::auth::secret::constant_time_eq;
+use crate::config::Config;
+use crate::services::dsn;
+use crate::services::suppression::{self, SuppressionReason};
+
+/// Provider-agnostic bounce/complaint event
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct EmailEventRequest {
+    pub event: String,               // "bounce" or "complaint"
+    pub email: String,
+    pub bounce_type: Option<String>, // "hard" (default) or "soft"
+    pub message_id: Option<String>,  // Message-ID of the original email; events without one are ignored
+    pub detail: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct SuppressionQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+// POST /inbound/email-events - Bounce or complaint webhook from an email provider
+#[utoipa::path(
+    post,
+    path = "/api/inbound/email-events",
+    request_body = EmailEventRequest,
+    responses(
+        (status = 200, description = "Event processed"),
+        (status = 400, description = "Unknown event type"),
+        (status = 401, description = "Missing or wrong webhook secret")
+    ),
+    tag = "Suppression API"
+)]
+pub async fn email_event_webhook(
+    req: HttpRequest,
+    event: web::Json<EmailEventRequest>,
+    db: web::Data<PgPool>,
+    config: web::Data<Config>,
+) -> HttpResponse {
+    if let Err(err_response) = check_webhook_secret(&req, &config) {
+        return err_response;
+    }
+
+    let reason = match (event.event.as_str(), event.bounce_type.as_deref()) {
+        ("bounce", None | Some("hard")) => Some(SuppressionReason::HardBounce),
+        ("bounce", Some("soft")) => None,
+        ("complaint", _) => Some(SuppressionReason::Complaint),
+        _ => return HttpResponse::BadRequest().json("Unknown event type"),
+    };
+
+    let notification_id = event.message_id.as_deref().and_then(dsn::notification_id_from_message_id);
+
+    match suppression::record_email_feedback(db.get_ref(), &event.email, reason, event.detail.as_deref(), notification_id).await {
+        Ok(_) => HttpResponse::Ok().json("Event processed"),
+        Err(_) => HttpResponse::InternalServerError().json("Error processing event"),
+    }
+}
+
+// POST /inbound/dsn - Raw RFC 3464 bounce message, e.g. piped from the return-path mailbox
+#[utoipa::path(
+    post,
+    path = "/api/inbound/dsn",
+    request_body(content = String, content_type = "message/rfc822"),
+    responses(
+        (status = 200, description = "Bounce processed"),
+        (status = 400, description = "Not a delivery status notification"),
+        (status = 401, description = "Missing or wrong webhook secret")
+    ),
+    tag = "Suppression API"
+)]
+pub async fn dsn_webhook(
+    req: HttpRequest,
+    body: String,
+    db: web::Data<PgPool>,
+    config: web::Data<Config>,
+) -> HttpResponse {
+    if let Err(err_response) = check_webhook_secret(&req, &config) {
+        return err_response;
+    }
+
+    let report = match dsn::parse(&body) {
+        Some(report) => report,
+        None => return HttpResponse::BadRequest().json("Not a delivery status notification"),
+    };
+
+    let notification_id = report.original_message_id.as_deref().and_then(dsn::notification_id_from_message_id);
+
+    for recipient in report.recipients.iter().filter(|r| r.is_failure()) {
+        let reason = recipient.is_hard_bounce().then_some(SuppressionReason::HardBounce);
+        let detail = recipient.diagnostic.clone().unwrap_or_else(|| recipient.status.clone());
+
+        if suppression::record_email_feedback(db.get_ref(), &recipient.recipient, reason, Some(&detail), notification_id)
+            .await
+            .is_err()
+        {
+            return HttpResponse::InternalServerError().json("Error processing bounce");
+        }
+    }
+
+    HttpResponse::Ok().json("Bounce processed")
+}
+
+// GET /admin/suppressions - List suppressed addresses, newest first
+#[utoipa::path(
+    get,
+    path = "/api/admin/suppressions",
+    params(SuppressionQuery),
+    responses(
+        (status = 200, description = "Suppressions retrieved successfully", body = [Suppression]),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    tag = "Suppression API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn list_suppressions(
+    query: web::Query<SuppressionQuery>,
+    db: web::Data<PgPool>,
+    _admin: AdminUser,
+) -> HttpResponse {
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    match suppression::list_suppressions(db.get_ref(), limit, offset).await {
+        Ok(suppressions) => HttpResponse::Ok().json(suppressions),
+        Err(_) => HttpResponse::InternalServerError().json("Error fetching suppressions"),
+    }
+}
+
+// DELETE /admin/suppressions/{email} - Allow sending to an address again
+#[utoipa::path(
+    delete,
+    path = "/api/admin/suppressions/{email}",
+    params(
+        ("email" = String, Path, description = "Suppressed email address")
+    ),
+    responses(
+        (status = 200, description = "Suppression removed"),
+        (status = 404, description = "Address is not suppressed"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    tag = "Suppression API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn remove_suppression(
+    email: web::Path<String>,
+    db: web::Data<PgPool>,
+    admin: AdminUser,
+) -> HttpResponse {
+    match suppression::remove_suppression(db.get_ref(), &email).await {
+        Ok(true) => {
+            info!("Admin {} removed suppression for {}", admin.0.sub, email);
+            HttpResponse::Ok().json("Suppression removed")
+        }
+        Ok(false) => HttpResponse::NotFound().json("Address is not suppressed"),
+        Err(_) => HttpResponse::InternalServerError().json("Error removing suppression"),
+    }
+}
+
+/// Inbound webhooks are unauthenticated by JWT, so providers must echo our shared secret
+fn check_webhook_secret(req: &HttpRequest, config: &Config) -> Result<(), HttpResponse> {
+    let expected = match &config.inbound_webhook_secret {
+        Some(secret) => secret,
+        None => return Err(HttpResponse::ServiceUnavailable().json("Inbound webhooks are not configured")),
+    };
+
+    let provided = req
+        .headers()
+        .get("X-Webhook-Secret")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    if constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+        Ok(())
+    } else {
+        Err(HttpResponse::Unauthorized().json("Invalid webhook secret"))
+    }
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/inbound/email-events", web::post().to(email_event_webhook))             // POST /inbound/email-events
+        .route("/inbound/dsn", web::post().to(dsn_webhook))                             // POST /inbound/dsn
+        .route("/admin/suppressions", web::get().to(list_suppressions))                 // GET /admin/suppressions
+        .route("/admin/suppressions/{email}", web::delete().to(remove_suppression));    // DELETE /admin/suppressions/{email}
+}
@@ -0,0 +1,193 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use log::error;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+use crate::auth::extractor::AuthenticatedUser;
+use crate::auth::secret::constant_time_eq;
+use crate::channels::Channels;
+use crate::config::Config;
+use crate::services::telegram;
+
+#[derive(Serialize, ToSchema)]
+pub struct TelegramLinkResponse {
+    pub url: String,  // Opens the bot in Telegram; pressing Start links the chat
+    pub expires_at: OffsetDateTime,
+}
+
+/// The parts of a Telegram Update we act on
+#[derive(Deserialize)]
+pub struct TelegramUpdate {
+    message: Option<TelegramMessage>,
+}
+
+#[derive(Deserialize)]
+struct TelegramMessage {
+    chat: TelegramChat,
+    from: Option<TelegramSender>,
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TelegramChat {
+    id: i64,
+}
+
+#[derive(Deserialize)]
+struct TelegramSender {
+    username: Option<String>,
+}
+
+// POST /me/telegram/link - Create a one-time deep link that connects a Telegram chat to the caller
+#[utoipa::path(
+    post,
+    path = "/api/me/telegram/link",
+    responses(
+        (status = 200, description = "Deep link created", body = TelegramLinkResponse),
+        (status = 401, description = "Unauthorized"),
+        (status = 503, description = "Telegram is not configured")
+    ),
+    tag = "Telegram API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn create_link(
+    db: web::Data<PgPool>,
+    channels: web::Data<Channels>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    let Some(bot) = channels.telegram() else {
+        return HttpResponse::ServiceUnavailable().json("Telegram is not configured");
+    };
+
+    match telegram::issue_link_token(db.get_ref(), auth_user.sub).await {
+        Ok((token, expires_at)) => HttpResponse::Ok().json(TelegramLinkResponse { url: bot.deep_link(&token), expires_at }),
+        Err(_) => HttpResponse::InternalServerError().json("Error creating Telegram link"),
+    }
+}
+
+// GET /me/telegram - Show the caller's linked Telegram chat
+#[utoipa::path(
+    get,
+    path = "/api/me/telegram",
+    responses(
+        (status = 200, description = "Linked Telegram chat", body = TelegramAccount),
+        (status = 404, description = "No Telegram chat linked"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "Telegram API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn get_account(
+    db: web::Data<PgPool>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    match telegram::get_account(db.get_ref(), auth_user.sub).await {
+        Ok(Some(account)) => HttpResponse::Ok().json(account),
+        Ok(None) => HttpResponse::NotFound().json("No Telegram chat linked"),
+        Err(_) => HttpResponse::InternalServerError().json("Error fetching Telegram account"),
+    }
+}
+
+// DELETE /me/telegram - Unlink the caller's Telegram chat
+#[utoipa::path(
+    delete,
+    path = "/api/me/telegram",
+    responses(
+        (status = 200, description = "Telegram chat unlinked"),
+        (status = 404, description = "No Telegram chat linked"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "Telegram API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn unlink(
+    db: web::Data<PgPool>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    match telegram::unlink_user(db.get_ref(), auth_user.sub).await {
+        Ok(true) => HttpResponse::Ok().json("Telegram chat unlinked"),
+        Ok(false) => HttpResponse::NotFound().json("No Telegram chat linked"),
+        Err(_) => HttpResponse::InternalServerError().json("Error unlinking Telegram chat"),
+    }
+}
+
+// POST /inbound/telegram - Updates from the Telegram Bot API (registered with setWebhook)
+#[utoipa::path(
+    post,
+    path = "/api/inbound/telegram",
+    responses(
+        (status = 200, description = "Update processed"),
+        (status = 401, description = "Missing or wrong X-Telegram-Bot-Api-Secret-Token"),
+        (status = 503, description = "Telegram is not configured")
+    ),
+    tag = "Telegram API"
+)]
+pub async fn telegram_webhook(
+    req: HttpRequest,
+    update: web::Json<TelegramUpdate>,
+    db: web::Data<PgPool>,
+    config: web::Data<Config>,
+    channels: web::Data<Channels>,
+) -> HttpResponse {
+    let (Some(telegram_config), Some(bot)) = (&config.telegram, channels.telegram()) else {
+        return HttpResponse::ServiceUnavailable().json("Telegram is not configured");
+    };
+
+    let provided = req
+        .headers()
+        .get("X-Telegram-Bot-Api-Secret-Token")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if !constant_time_eq(provided.as_bytes(), telegram_config.webhook_secret.as_bytes()) {
+        return HttpResponse::Unauthorized().json("Invalid webhook secret");
+    }
+
+    // Anything but a command is ignored; Telegram only needs a 200 to stop redelivering
+    let Some(message) = &update.message else {
+        return HttpResponse::Ok().finish();
+    };
+    let chat_id = message.chat.id;
+    let text = message.text.as_deref().unwrap_or_default().trim();
+    let (command, argument) = text.split_once(' ').unwrap_or((text, ""));
+
+    match (command, argument.trim()) {
+        ("/start", "") => bot.reply(chat_id, "To get notifications here, open the Telegram link from your account settings.").await,
+        ("/start", token) => {
+            let username = message.from.as_ref().and_then(|sender| sender.username.as_deref());
+            match telegram::link_chat(db.get_ref(), token, chat_id, username).await {
+                Ok(Some(_)) => bot.reply(chat_id, "Linked! Notifications will be sent to this chat. Send /stop to unlink.").await,
+                Ok(None) => bot.reply(chat_id, "This link has expired or was already used. Please create a new one.").await,
+                Err(e) => {
+                    error!("Failed to link Telegram chat {}: {:?}", chat_id, e);
+                    return HttpResponse::InternalServerError().json("Error linking Telegram chat");
+                }
+            }
+        }
+        ("/stop", _) => match telegram::unlink_chat(db.get_ref(), chat_id).await {
+            Ok(true) => bot.reply(chat_id, "Unlinked. You will no longer get notifications here.").await,
+            Ok(false) => bot.reply(chat_id, "This chat is not linked to an account.").await,
+            Err(e) => {
+                error!("Failed to unlink Telegram chat {}: {:?}", chat_id, e);
+                return HttpResponse::InternalServerError().json("Error unlinking Telegram chat");
+            }
+        },
+        _ => {}
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/me/telegram/link", web::post().to(create_link))       // POST /me/telegram/link
+        .route("/me/telegram", web::get().to(get_account))            // GET /me/telegram
+        .route("/me/telegram", web::delete().to(unlink))              // DELETE /me/telegram
+        .route("/inbound/telegram", web::post().to(telegram_webhook)); // POST /inbound/telegram
+}
@@ -0,0 +1,61 @@
+use actix_web::{web, HttpResponse};
+use log::info;
+use sqlx::PgPool;
+
+use crate::auth::extractor::AdminUser;
+use crate::services::throttle;
+
+// GET /admin/circuit-breakers - Breaker state for every provider that has failed
+#[utoipa::path(
+    get,
+    path = "/api/admin/circuit-breakers",
+    responses(
+        (status = 200, description = "Circuit breakers retrieved successfully", body = [CircuitBreaker]),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    tag = "Throttling API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn list_breakers(db: web::Data<PgPool>, _admin: AdminUser) -> HttpResponse {
+    match throttle::list_breakers(db.get_ref()).await {
+        Ok(breakers) => HttpResponse::Ok().json(breakers),
+        Err(_) => HttpResponse::InternalServerError().json("Error fetching circuit breakers"),
+    }
+}
+
+// POST /admin/circuit-breakers/{key}/reset - Close a breaker before its cooldown ends
+#[utoipa::path(
+    post,
+    path = "/api/admin/circuit-breakers/{key}/reset",
+    params(
+        ("key" = String, Path, description = "Provider the breaker protects, e.g. push:fcm or sms:twilio")
+    ),
+    responses(
+        (status = 200, description = "Circuit breaker closed"),
+        (status = 404, description = "No breaker for this provider"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    tag = "Throttling API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn reset_breaker(key: web::Path<String>, db: web::Data<PgPool>, admin: AdminUser) -> HttpResponse {
+    match throttle::reset_breaker(db.get_ref(), &key).await {
+        Ok(true) => {
+            info!("Admin {} reset the {} circuit breaker", admin.0.sub, key);
+            HttpResponse::Ok().json("Circuit breaker closed")
+        }
+        Ok(false) => HttpResponse::NotFound().json("No breaker for this provider"),
+        Err(_) => HttpResponse::InternalServerError().json("Error resetting circuit breaker"),
+    }
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/admin/circuit-breakers", web::get().to(list_breakers))                // GET /admin/circuit-breakers
+        .route("/admin/circuit-breakers/{key}/reset", web::post().to(reset_breaker));  // POST /admin/circuit-breakers/{key}/reset
+}
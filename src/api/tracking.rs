@@ -0,0 +1,81 @@
+use actix_web::{http::header, web, HttpResponse};
+use log::error;
+use sqlx::PgPool;
+
+use crate::auth::tracking;
+use crate::config::Config;
+use crate::db::models::DeliveryMethod;
+use crate::services::{notification, tracking::PIXEL_GIF};
+
+// GET /t/c/{token} - Record a click and redirect to the original link
+#[utoipa::path(
+    get,
+    path = "/t/c/{token}",
+    responses(
+        (status = 302, description = "Redirect to the original link"),
+        (status = 404, description = "Unknown or tampered link")
+    ),
+    params(
+        ("token" = String, Path, description = "Signed click token")
+    ),
+    tag = "Tracking"
+)]
+pub async fn track_click(
+    token: web::Path<String>,
+    db: web::Data<PgPool>,
+    config: web::Data<Config>,
+) -> HttpResponse {
+    let claims = match tracking::verify_token(&config.jwt_secret, &token) {
+        Ok(claims) => claims,
+        Err(_) => return HttpResponse::NotFound().json("Link not found"),
+    };
+
+    let url = match claims.url {
+        Some(url) => url,
+        None => return HttpResponse::NotFound().json("Link not found"),
+    };
+
+    // The recipient should land on the page even if we fail to record the click
+    if let Err(e) = notification::record_event(db.get_ref(), claims.sub, Some(DeliveryMethod::Email), "Clicked", Some(&url)).await {
+        error!("Failed to record click for notification {}: {:?}", claims.sub, e);
+    }
+
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, url))
+        .finish()
+}
+
+// GET /t/o/{token}.gif - Record an open and serve a transparent pixel
+#[utoipa::path(
+    get,
+    path = "/t/o/{token}.gif",
+    responses(
+        (status = 200, description = "1x1 transparent GIF", content_type = "image/gif")
+    ),
+    params(
+        ("token" = String, Path, description = "Signed open token")
+    ),
+    tag = "Tracking"
+)]
+pub async fn track_open(
+    token: web::Path<String>,
+    db: web::Data<PgPool>,
+    config: web::Data<Config>,
+) -> HttpResponse {
+    if let Ok(claims) = tracking::verify_token(&config.jwt_secret, &token) {
+        if let Err(e) = notification::record_event(db.get_ref(), claims.sub, Some(DeliveryMethod::Email), "Opened", None).await {
+            error!("Failed to record open for notification {}: {:?}", claims.sub, e);
+        }
+    }
+
+    // Always serve the image so broken tokens do not show up as broken images
+    HttpResponse::Ok()
+        .content_type("image/gif")
+        .insert_header((header::CACHE_CONTROL, "no-store, no-cache, must-revalidate"))
+        .body(PIXEL_GIF)
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/t/c/{token}", web::get().to(track_click))      // GET /t/c/{token}
+        .route("/t/o/{token}.gif", web::get().to(track_open)); // GET /t/o/{token}.gif
+}
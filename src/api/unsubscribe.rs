@@ -0,0 +1,76 @@
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+use crate::auth::unsubscribe;
+use crate::config::Config;
+use crate::db::models::OptOut;
+use crate::services::preferences;
+
+// POST /unsubscribe/{token} - RFC 8058 one-click unsubscribe, no login required
+#[utoipa::path(
+    post,
+    path = "/api/unsubscribe/{token}",
+    responses(
+        (status = 200, description = "Unsubscribed successfully"),
+        (status = 400, description = "Invalid unsubscribe token"),
+        (status = 500, description = "Error saving opt-out")
+    ),
+    params(
+        ("token" = String, Path, description = "Signed unsubscribe token from the email")
+    ),
+    tag = "Preferences API"
+)]
+pub async fn unsubscribe(
+    token: web::Path<String>,
+    db: web::Data<PgPool>,
+    config: web::Data<Config>,
+) -> HttpResponse {
+    // Mail clients POST `List-Unsubscribe=One-Click` as the body; the token alone is authoritative
+    let claims = match unsubscribe::verify_token(&config.jwt_secret, &token) {
+        Ok(claims) => claims,
+        Err(_) => return HttpResponse::BadRequest().json("Invalid unsubscribe token"),
+    };
+
+    if !claims.category.is_optional() {
+        return HttpResponse::BadRequest().json(format!("{} notifications cannot be opted out of", claims.category));
+    }
+
+    let opt_out = OptOut {
+        category: claims.category,
+        channel: claims.channel,
+    };
+
+    match preferences::add_opt_out(db.get_ref(), claims.sub, &opt_out).await {
+        Ok(_) => HttpResponse::Ok().json("You have been unsubscribed"),
+        Err(_) => HttpResponse::InternalServerError().json("Error saving opt-out"),
+    }
+}
+
+// GET /unsubscribe/{token} - Confirmation page for links clicked in the email body.
+// Link scanners prefetch GETs, so this never changes anything on its own.
+async fn unsubscribe_page(token: web::Path<String>, config: web::Data<Config>) -> HttpResponse {
+    let claims = match unsubscribe::verify_token(&config.jwt_secret, &token) {
+        Ok(claims) => claims,
+        Err(_) => return HttpResponse::BadRequest().json("Invalid unsubscribe token"),
+    };
+
+    // Re-signed from the verified claims so nothing from the request path is echoed into the page
+    let token = match unsubscribe::sign_token(&config.jwt_secret, claims.sub, claims.category, claims.channel) {
+        Ok(token) => token,
+        Err(_) => return HttpResponse::InternalServerError().json("Error building unsubscribe form"),
+    };
+
+    HttpResponse::Ok().content_type("text/html; charset=utf-8").body(format!(
+        "<!DOCTYPE html><html><body>\
+         <form method=\"post\" action=\"/api/unsubscribe/{}\">\
+         <p>Stop receiving these notifications?</p>\
+         <button type=\"submit\">Unsubscribe</button>\
+         </form></body></html>",
+        token
+    ))
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/unsubscribe/{token}", web::post().to(unsubscribe))      // POST /unsubscribe/{token}
+        .route("/unsubscribe/{token}", web::get().to(unsubscribe_page)); // GET /unsubscribe/{token}
+}
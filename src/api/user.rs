@@ -6,10 +6,17 @@ use sqlx::PgPool;
 use bcrypt::{hash, verify, DEFAULT_COST};
 use jsonwebtoken::{encode, Header, EncodingKey};
 use serde::{Serialize, Deserialize};
-use lettre::{transport::smtp::authentication::Credentials, Message, SmtpTransport, Transport};
+use lettre::message::Mailbox;
+use log::error;
 use std::collections::HashMap;
 
-use crate::{config::Config, db::models::User};  // Import user model
+use crate::auth::extractor::AuthenticatedUser;
+use crate::config::Config;
+use crate::services::engagement::{self, SendTime};
+use crate::channels::{Channels, DeliveryError};
+use crate::services::phone;
+use crate::services::phone_verification::{self, ConfirmError, StartError};
+use crate::services::user;
 
 #[derive(Serialize, Deserialize, ToSchema)]
 pub struct CreateUser {
@@ -17,11 +24,6 @@ pub struct CreateUser {
     password: String,
 }
 
-#[derive(Deserialize)]
-struct UpdateUser {
-    email: String,
-}
-
 #[derive(Serialize, Deserialize, ToSchema)]
 pub struct UserGet {
     pub id: Uuid,
@@ -29,13 +31,17 @@ pub struct UserGet {
     pub phone_number: Option<String>,  // Optional phone number field
     pub email_verified: Option<bool>,  // Track if the email is verified
     pub phone_verified: Option<bool>,  // Track if the phone number is verified
+    pub phone_country_code: Option<i32>,  // Calling code of the phone number, e.g. 44
+    pub phone_number_type: Option<String>,  // Mobile, FixedLine, FixedLineOrMobile, ...
+    pub phone_reentry_required: bool,  // A stored number was invalid and removed; ask the user for it again
     pub created_at: Option<OffsetDateTime>,  // Timestamp for user creation
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
 pub struct UpdateUserRequest {
     email: Option<String>,
-    phone_number: Option<String>,
+    phone_number: Option<String>,  // Normalized to E.164
+    phone_region: Option<String>,  // Region to read a number without a +country code in, e.g. GB; defaults to DEFAULT_PHONE_REGION
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -43,6 +49,7 @@ pub struct UserLogin {
     pub id: Uuid,
     pub email: String,
     pub password_hash: String,
+    pub is_admin: bool,
     pub email_verified: Option<bool>,
     pub phone_verified: Option<bool>,
     pub created_at: Option<OffsetDateTime>,
@@ -55,10 +62,25 @@ pub struct LoginRequest {
     password: String,
 }
 
+#[derive(Deserialize, ToSchema)]
+pub struct ConfirmPhoneRequest {
+    code: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SendTimesResponse {
+    pub user_id: Uuid,
+    pub personalized: bool,  // False when the user has no history and population data is shown
+    pub sample_size: i32,
+    pub computed_at: OffsetDateTime,
+    pub best_times: Vec<SendTime>,
+}
+
 #[derive(Serialize)]
 struct Claims {
     sub: Uuid,  // User ID
     exp: usize, // Expiration timestamp
+    admin: bool, // Grants access to /api/admin endpoints
 }
 
 
@@ -76,8 +98,12 @@ struct Claims {
 pub async fn create_user(
     user_data: web::Json<CreateUser>,  // Now, this holds the plain password
     db: web::Data<PgPool>,
-    config: web::Data<Config>, // Add config to retrieve SMTP settings
+    config: web::Data<Config>,
 ) -> HttpResponse {
+    if user_data.email.parse::<Mailbox>().is_err() {
+        return HttpResponse::BadRequest().json("Invalid email address");
+    }
+
     let hashed_password = match hash(&user_data.password, DEFAULT_COST) {  // Hashing plain password
         Ok(h) => h,
         Err(_) => return HttpResponse::InternalServerError().json("Error hashing password"),
@@ -85,37 +111,11 @@ pub async fn create_user(
 
     let verification_token = Uuid::new_v4();  // Generate a token for email verification
 
-    let result = sqlx::query!(
-        "INSERT INTO users (email, password_hash, verification_token) VALUES ($1, $2, $3)",
-        user_data.email,
-        hashed_password,  // Store the hashed password
-        verification_token
-    )
-    .execute(db.get_ref())
-    .await;
+    // The verification email is queued in the same transaction, so it is sent once the user exists
+    let result = user::register(db.get_ref(), &config.public_url, &user_data.email, &hashed_password, verification_token).await;
 
     match result {
-        Ok(_) => {
-            // Send verification email with token
-            let email = Message::builder()
-                .from(config.smtp_username.parse().unwrap())
-                .to(user_data.email.parse().unwrap())
-                .subject("Verify your email")
-                .body(format!("Please verify your email by clicking the link: http://127.0.0.1:8080/api/verify?token={}", verification_token))
-                .unwrap();
-
-            let creds = Credentials::new(config.smtp_username.clone(), config.smtp_password.clone());
-
-            let mailer = SmtpTransport::starttls_relay(&config.smtp_server)
-                .unwrap()  // Ensure the SMTP server address is correct
-                .credentials(creds)
-                .build();
-
-            match mailer.send(&email) {
-                Ok(_) => HttpResponse::Created().json("User created. Check your email for verification."),  // Use 201 status code
-                Err(e) => HttpResponse::InternalServerError().json(format!("Failed to send verification email: {}", e)),
-            }
-        }
+        Ok(_) => HttpResponse::Created().json("User created. Check your email for verification."),  // Use 201 status code
         Err(_) => HttpResponse::InternalServerError().json("Error creating user"),
     }
 }
@@ -140,7 +140,9 @@ async fn get_user(
 ) -> HttpResponse {
     let user = sqlx::query_as!(
         UserGet,
-        "SELECT id, email, phone_number, email_verified, phone_verified, created_at FROM users WHERE id = $1",
+        "SELECT id, email, phone_number, email_verified, phone_verified, phone_country_code, phone_number_type,
+                phone_reentry_required, created_at
+         FROM users WHERE id = $1",
         user_id.into_inner()
     )
     .fetch_one(db.get_ref())
@@ -160,25 +162,64 @@ async fn get_user(
     request_body = UpdateUserRequest,
     responses(
         (status = 200, description = "User updated successfully"),
+        (status = 400, description = "Invalid phone number", body = InvalidPhoneNumber),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Not your account"),
         (status = 500, description = "Error updating user")
     ),
     params(
         ("id" = Uuid, Path, description = "ID of the User to update")
     ),
-    tag = "User API"
+    tag = "User API",
+    security(
+        ("BearerAuth" = [])
+    )
 )]
 async fn update_user(
     user_id: web::Path<Uuid>,
     user_data: web::Json<UpdateUserRequest>,
     db: web::Data<PgPool>,
+    config: web::Data<Config>,
+    auth_user: AuthenticatedUser,
 ) -> HttpResponse {
     let user_id_inner = user_id.into_inner();  // Move once, store in variable
+    if auth_user.sub != user_id_inner && !auth_user.admin {
+        return HttpResponse::Forbidden().json("You can only update your own account");
+    }
+
+    let phone = match user_data.phone_number.as_deref() {
+        Some(input) => {
+            let region = match user_data.phone_region.as_deref().map(phone::parse_region) {
+                Some(Ok(region)) => Some(region),
+                Some(Err(e)) => return HttpResponse::BadRequest().json(e),
+                None => config.default_phone_region,
+            };
+            match phone::normalize(input, region) {
+                Ok(normalized) => Some(normalized),
+                Err(e) => return HttpResponse::BadRequest().json(e),
+            }
+        }
+        None => None,
+    };
 
-    // Update email and phone_number in a single query, using COALESCE to preserve existing values if none provided
+    // Update email and phone_number in a single query, using COALESCE to preserve existing values if none provided.
+    // A new phone number has to be verified again, and any code sent to the old one is void.
     let result = sqlx::query!(
-        "UPDATE users SET email = COALESCE($1, email), phone_number = COALESCE($2, phone_number) WHERE id = $3",
+        "UPDATE users
+         SET email = COALESCE($1, email),
+             phone_number = COALESCE($2, phone_number),
+             phone_verified = CASE WHEN $2 IS DISTINCT FROM phone_number AND $2 IS NOT NULL THEN FALSE ELSE phone_verified END,
+             phone_verification_code = CASE WHEN $2 IS DISTINCT FROM phone_number AND $2 IS NOT NULL THEN NULL ELSE phone_verification_code END,
+             phone_country_code = COALESCE($3, phone_country_code),
+             phone_region = CASE WHEN $2 IS NOT NULL THEN $4 ELSE phone_region END,
+             phone_number_type = COALESCE($5, phone_number_type),
+             phone_reentry_required = phone_reentry_required AND $2 IS NULL
+         WHERE id = $6",
         user_data.email,
-        user_data.phone_number,
+        phone.as_ref().map(|phone| phone.e164.as_str()),
+        phone.as_ref().map(|phone| phone.country_code),
+        phone.as_ref().and_then(|phone| phone.region.as_deref()),
+        phone.as_ref().map(|phone| phone.number_type),
         user_id_inner
     )
     .execute(db.get_ref())
@@ -281,11 +322,12 @@ async fn verify_email(
 async fn login(
     login_data: web::Json<LoginRequest>,
     db: web::Data<PgPool>,
+    config: web::Data<Config>,
 ) -> HttpResponse {
     // Fetch the user by email
     let user = match sqlx::query_as!(
         UserLogin,
-        "SELECT id, email, password_hash, email_verified, phone_verified, created_at FROM users WHERE email = $1",
+        "SELECT id, email, password_hash, is_admin, email_verified, phone_verified, created_at FROM users WHERE email = $1",
         login_data.email
     )
     .fetch_one(db.get_ref())
@@ -322,13 +364,13 @@ async fn login(
     let claims = Claims {
         sub: user.id,
         exp: expiration,
+        admin: user.is_admin,
     };
 
-    let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
     let token = encode(
         &Header::default(),
         &claims,
-        &EncodingKey::from_secret(secret.as_ref()),
+        &EncodingKey::from_secret(config.jwt_secret.as_ref()),
     )
     .expect("Token creation failed");
 
@@ -344,14 +386,14 @@ async fn login(
     responses(
         (status = 200, description = "Verification email resent successfully"),
         (status = 404, description = "User not found"),
-        (status = 500, description = "Error sending verification email")
+        (status = 500, description = "Error queueing verification email")
     ),
     tag = "User API"
 )]
 pub async fn resend_verification_email(
     user_data: web::Json<LoginRequest>,  // Use the email input from the user
     db: web::Data<PgPool>,
-    config: web::Data<Config>, // Add config to retrieve SMTP settings
+    config: web::Data<Config>,
 ) -> HttpResponse {
     // Fetch the user from the database using their email
     let user = sqlx::query!(
@@ -373,41 +415,190 @@ pub async fn resend_verification_email(
                 None => Uuid::new_v4(), // Generate a new token if missing
             };
 
-            // Update the user's verification token in the database if it was missing
-            let result = sqlx::query!(
-                "UPDATE users SET verification_token = $1 WHERE email = $2",
-                verification_token,
-                user_data.email
-            )
-            .execute(db.get_ref())
-            .await;
-
-            if result.is_err() {
-                return HttpResponse::InternalServerError().json("Failed to update verification token");
+            // Store the token and queue the email together so neither happens without the other
+            let result = user::resend_verification(db.get_ref(), &config.public_url, user.id, &user.email, verification_token).await;
+
+            match result {
+                Ok(_) => HttpResponse::Ok().json("Verification email resent successfully"),
+                Err(_) => HttpResponse::InternalServerError().json("Failed to queue verification email"),
             }
+        }
+        Err(_) => HttpResponse::NotFound().json("User not found"),
+    }
+}
 
-            // Send the new verification email
-            let email = Message::builder()
-                .from(config.smtp_username.parse().unwrap())
-                .to(user_data.email.parse().unwrap())
-                .subject("Resend Verification Email")
-                .body(format!("Please verify your email by clicking this link: http://127.0.0.1:8080/api/verify?token={}", verification_token))
-                .unwrap();
 
-            let creds = Credentials::new(config.smtp_username.clone(), config.smtp_password.clone());
+// GET /users/{id}/send-times - Predicted best hours of the week to reach a user
+#[utoipa::path(
+    get,
+    path = "/api/users/{id}/send-times",
+    responses(
+        (status = 200, description = "Predicted send times, best first", body = SendTimesResponse),
+        (status = 403, description = "Not your account"),
+        (status = 404, description = "No engagement data yet")
+    ),
+    params(
+        ("id" = Uuid, Path, description = "ID of the User")
+    ),
+    tag = "User API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+async fn get_send_times(
+    user_id: web::Path<Uuid>,
+    db: web::Data<PgPool>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    let user_id = user_id.into_inner();
+    if auth_user.sub != user_id && !auth_user.admin {
+        return HttpResponse::Forbidden().json("You can only view your own send times");
+    }
 
-            let mailer = SmtpTransport::starttls_relay(&config.smtp_server)
-                .unwrap()
-                .credentials(creds)
-                .build();
+    match engagement::get_profile(db.get_ref(), user_id).await {
+        Ok(Some(profile)) => HttpResponse::Ok().json(SendTimesResponse {
+            user_id,
+            personalized: profile.user_id == user_id,
+            sample_size: profile.sample_size,
+            computed_at: profile.computed_at,
+            best_times: engagement::top_send_times(&profile.hour_scores, 5),
+        }),
+        Ok(None) => HttpResponse::NotFound().json("No engagement data yet"),
+        Err(_) => HttpResponse::InternalServerError().json("Error fetching send times"),
+    }
+}
 
 
-            match mailer.send(&email) {
-                Ok(_) => HttpResponse::Ok().json("Verification email resent successfully"),
-                Err(_) => HttpResponse::InternalServerError().json("Failed to send verification email"),
+// POST /users/{id}/phone/verify/start - Text a verification code to the user's phone number
+#[utoipa::path(
+    post,
+    path = "/api/users/{id}/phone/verify/start",
+    responses(
+        (status = 200, description = "Verification code sent"),
+        (status = 400, description = "No phone number on file, or it is a landline"),
+        (status = 403, description = "Not your account"),
+        (status = 404, description = "User not found"),
+        (status = 429, description = "A code was sent less than a minute ago"),
+        (status = 502, description = "The SMS provider did not accept the message"),
+        (status = 503, description = "SMS is not configured or temporarily unavailable")
+    ),
+    params(
+        ("id" = Uuid, Path, description = "ID of the User")
+    ),
+    tag = "User API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+async fn start_phone_verification(
+    user_id: web::Path<Uuid>,
+    db: web::Data<PgPool>,
+    channels: web::Data<Channels>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    let user_id = user_id.into_inner();
+    if auth_user.sub != user_id && !auth_user.admin {
+        return HttpResponse::Forbidden().json("You can only verify your own phone number");
+    }
+
+    let user = match user::find_user(db.get_ref(), user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::NotFound().json("User not found"),
+        Err(_) => return HttpResponse::InternalServerError().json("Error fetching user"),
+    };
+
+    let phone_number = match user.phone_number {
+        Some(phone_number) => phone_number,
+        None => return HttpResponse::BadRequest().json("No phone number on file"),
+    };
+    if user.phone_verified.unwrap_or(false) {
+        return HttpResponse::Ok().json("Phone number is already verified");
+    }
+    if user.phone_number_type.as_deref().is_some_and(phone::is_landline) {
+        return HttpResponse::BadRequest().json("Landlines cannot receive SMS");
+    }
+    if !channels.sms_enabled() {
+        return HttpResponse::ServiceUnavailable().json("SMS is not configured");
+    }
+
+    let code = match phone_verification::issue_code(db.get_ref(), user_id).await {
+        Ok(code) => code,
+        Err(StartError::TooSoon) => {
+            return HttpResponse::TooManyRequests().json("Please wait a minute before requesting another code")
+        }
+        Err(e) => {
+            error!("Failed to issue phone verification code for {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().json("Error creating verification code");
+        }
+    };
+
+    let body = format!(
+        "Your verification code is {}. It expires in {} minutes.",
+        code,
+        phone_verification::CODE_TTL.whole_minutes()
+    );
+
+    match channels.send_system_sms(&phone_number, &body).await {
+        Ok(()) => HttpResponse::Ok().json("Verification code sent"),
+        Err(e) => {
+            if phone_verification::revoke_code(db.get_ref(), user_id).await.is_err() {
+                return HttpResponse::InternalServerError().json("Error sending verification code");
+            }
+            match e {
+                DeliveryError::Transient(reason) => {
+                    HttpResponse::ServiceUnavailable().json(format!("Failed to send verification code: {}", reason))
+                }
+                e => HttpResponse::BadGateway().json(format!("Failed to send verification code: {}", e)),
             }
         }
-        Err(_) => HttpResponse::NotFound().json("User not found"),
+    }
+}
+
+
+// POST /users/{id}/phone/verify/confirm - Check the code sent by start_phone_verification
+#[utoipa::path(
+    post,
+    path = "/api/users/{id}/phone/verify/confirm",
+    request_body = ConfirmPhoneRequest,
+    responses(
+        (status = 200, description = "Phone number verified"),
+        (status = 400, description = "Wrong, expired or missing code"),
+        (status = 403, description = "Not your account"),
+        (status = 429, description = "Too many wrong codes; request a new one")
+    ),
+    params(
+        ("id" = Uuid, Path, description = "ID of the User")
+    ),
+    tag = "User API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+async fn confirm_phone_verification(
+    user_id: web::Path<Uuid>,
+    request: web::Json<ConfirmPhoneRequest>,
+    db: web::Data<PgPool>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    let user_id = user_id.into_inner();
+    if auth_user.sub != user_id && !auth_user.admin {
+        return HttpResponse::Forbidden().json("You can only verify your own phone number");
+    }
+
+    match phone_verification::confirm_code(db.get_ref(), user_id, &request.code).await {
+        Ok(()) => HttpResponse::Ok().json("Phone number verified"),
+        Err(ConfirmError::NoActiveCode) => HttpResponse::BadRequest().json("No verification code has been requested"),
+        Err(ConfirmError::Expired) => HttpResponse::BadRequest().json("Verification code has expired"),
+        Err(ConfirmError::TooManyAttempts) => {
+            HttpResponse::TooManyRequests().json("Too many incorrect codes; request a new one")
+        }
+        Err(ConfirmError::Mismatch { attempts_left }) => {
+            HttpResponse::BadRequest().json(format!("Incorrect verification code; {} attempts left", attempts_left))
+        }
+        Err(e) => {
+            error!("Failed to verify phone number for {}: {}", user_id, e);
+            HttpResponse::InternalServerError().json("Error verifying phone number")
+        }
     }
 }
 
@@ -420,6 +611,9 @@ pub fn init_routes(cfg: &mut web::ServiceConfig) {
             .route("/{id}", web::get().to(get_user))    // GET /users/{id}
             .route("/{id}", web::put().to(update_user)) // PUT /users/{id}
             .route("/{id}", web::delete().to(delete_user)) // DELETE /users/{id}
+            .route("/{id}/send-times", web::get().to(get_send_times)) // GET /users/{id}/send-times
+            .route("/{id}/phone/verify/start", web::post().to(start_phone_verification)) // POST /users/{id}/phone/verify/start
+            .route("/{id}/phone/verify/confirm", web::post().to(confirm_phone_verification)) // POST /users/{id}/phone/verify/confirm
     )
     .route("/login", web::post().to(login))  // POST /login
     .route("/verify", web::post().to(verify_email))  // POST /verify
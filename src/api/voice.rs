@@ -0,0 +1,63 @@
+use actix_web::{web, HttpResponse};
+use log::error;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::auth::acknowledge;
+use crate::channels::Channels;
+use crate::config::Config;
+use crate::db::models::DeliveryMethod;
+use crate::services::notification;
+
+/// The part of the provider's `<Gather>` callback we act on
+#[derive(Deserialize)]
+pub struct GatherResult {
+    #[serde(rename = "Digits")]
+    digits: Option<String>,
+}
+
+// POST /inbound/voice/{token} - Keypress from a voice call; 1 acknowledges the notification
+#[utoipa::path(
+    post,
+    path = "/api/inbound/voice/{token}",
+    params(
+        ("token" = String, Path, description = "Signed acknowledge token from the call's TwiML")
+    ),
+    responses(
+        (status = 200, description = "TwiML telling the callee what happened", content_type = "text/xml"),
+        (status = 503, description = "Voice is not configured")
+    ),
+    tag = "Voice API"
+)]
+pub async fn voice_gather(
+    token: web::Path<String>,
+    gather: web::Form<GatherResult>,
+    db: web::Data<PgPool>,
+    config: web::Data<Config>,
+    channels: web::Data<Channels>,
+) -> HttpResponse {
+    let Some(voice) = channels.voice() else {
+        return HttpResponse::ServiceUnavailable().json("Voice is not configured");
+    };
+
+    // The callee is still on the line, so every outcome is answered with speech rather than an error
+    let message = match acknowledge::verify_token(&config.jwt_secret, &token) {
+        Err(_) => "Sorry, this alert can no longer be acknowledged. Goodbye.",
+        Ok(claims) if gather.digits.as_deref() == Some("1") => {
+            match notification::acknowledge(db.get_ref(), claims.sub, DeliveryMethod::Voice).await {
+                Ok(_) => "Thank you, the alert has been acknowledged. Goodbye.",
+                Err(e) => {
+                    error!("Failed to acknowledge notification {}: {:?}", claims.sub, e);
+                    "Sorry, your acknowledgement could not be recorded. Goodbye."
+                }
+            }
+        }
+        Ok(_) => "The alert was not acknowledged. Goodbye.",
+    };
+
+    HttpResponse::Ok().content_type("text/xml").body(voice.reply(message))
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/inbound/voice/{token}", web::post().to(voice_gather)); // POST /inbound/voice/{token}
+}
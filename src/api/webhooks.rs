@@ -0,0 +1,159 @@
+use actix_web::{web, HttpResponse};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::auth::extractor::AuthenticatedUser;
+use crate::channels::public_addr;
+use crate::config::Config;
+use crate::services::webhooks::{self, WebhookEndpoint};
+
+const MIN_SECRET_LEN: usize = 16;
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateWebhookRequest {
+    pub url: String,  // Must be https://
+    pub secret: Option<String>,  // At least 16 characters; generated if omitted
+    pub description: Option<String>,
+}
+
+/// The new endpoint and its signing secret, which is not shown again
+#[derive(Serialize, ToSchema)]
+pub struct CreateWebhookResponse {
+    pub endpoint: WebhookEndpoint,
+    pub secret: String,
+}
+
+// GET /me/webhooks - List the caller's webhook endpoints
+#[utoipa::path(
+    get,
+    path = "/api/me/webhooks",
+    responses(
+        (status = 200, description = "Webhook endpoints", body = [WebhookEndpoint]),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "Webhooks API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn list_webhooks(
+    db: web::Data<PgPool>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    match webhooks::list_endpoints(db.get_ref(), auth_user.sub).await {
+        Ok(endpoints) => HttpResponse::Ok().json(endpoints),
+        Err(_) => HttpResponse::InternalServerError().json("Error fetching webhook endpoints"),
+    }
+}
+
+// POST /me/webhooks - Register an endpoint for the Webhook channel
+#[utoipa::path(
+    post,
+    path = "/api/me/webhooks",
+    request_body = CreateWebhookRequest,
+    responses(
+        (status = 201, description = "Endpoint registered", body = CreateWebhookResponse),
+        (status = 400, description = "URL is not https, not a public server or the secret is too short"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "Webhooks API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn create_webhook(
+    webhook_data: web::Json<CreateWebhookRequest>,
+    db: web::Data<PgPool>,
+    config: web::Data<Config>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    let url = webhook_data.url.trim();
+    let parsed = match Url::parse(url) {
+        Ok(parsed) if parsed.host().is_some() && (parsed.scheme() == "https" || (config.webhook_allow_http && parsed.scheme() == "http")) => parsed,
+        _ => return HttpResponse::BadRequest().json("Webhook URL must be an https:// URL"),
+    };
+    if let Err(e) = public_addr::check_host(&parsed).await {
+        return HttpResponse::BadRequest().json(format!("Webhook URL must point to a public server: {}", e));
+    }
+
+    let secret = match webhook_data.secret.as_deref() {
+        Some(secret) if secret.len() < MIN_SECRET_LEN => {
+            return HttpResponse::BadRequest().json(format!("Secret must be at least {} characters", MIN_SECRET_LEN));
+        }
+        Some(secret) => secret.to_string(),
+        None => webhooks::generate_secret(),
+    };
+
+    match webhooks::create_endpoint(db.get_ref(), auth_user.sub, url, &secret, webhook_data.description.as_deref()).await {
+        Ok(endpoint) => HttpResponse::Created().json(CreateWebhookResponse { endpoint, secret }),
+        Err(_) => HttpResponse::InternalServerError().json("Error registering webhook endpoint"),
+    }
+}
+
+// DELETE /me/webhooks/{id} - Remove a webhook endpoint
+#[utoipa::path(
+    delete,
+    path = "/api/me/webhooks/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Webhook endpoint ID")
+    ),
+    responses(
+        (status = 200, description = "Endpoint removed"),
+        (status = 404, description = "No such endpoint"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "Webhooks API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn delete_webhook(
+    endpoint_id: web::Path<Uuid>,
+    db: web::Data<PgPool>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    match webhooks::delete_endpoint(db.get_ref(), auth_user.sub, endpoint_id.into_inner()).await {
+        Ok(true) => HttpResponse::Ok().json("Webhook endpoint removed"),
+        Ok(false) => HttpResponse::NotFound().json("Webhook endpoint not found"),
+        Err(_) => HttpResponse::InternalServerError().json("Error removing webhook endpoint"),
+    }
+}
+
+// POST /me/webhooks/{id}/enable - Resume deliveries to an endpoint that was disabled after failures
+#[utoipa::path(
+    post,
+    path = "/api/me/webhooks/{id}/enable",
+    params(
+        ("id" = Uuid, Path, description = "Webhook endpoint ID")
+    ),
+    responses(
+        (status = 200, description = "Endpoint enabled", body = WebhookEndpoint),
+        (status = 404, description = "No such endpoint"),
+        (status = 401, description = "Unauthorized")
+    ),
+    tag = "Webhooks API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn enable_webhook(
+    endpoint_id: web::Path<Uuid>,
+    db: web::Data<PgPool>,
+    auth_user: AuthenticatedUser,
+) -> HttpResponse {
+    match webhooks::enable_endpoint(db.get_ref(), auth_user.sub, endpoint_id.into_inner()).await {
+        Ok(Some(endpoint)) => HttpResponse::Ok().json(endpoint),
+        Ok(None) => HttpResponse::NotFound().json("Webhook endpoint not found"),
+        Err(_) => HttpResponse::InternalServerError().json("Error enabling webhook endpoint"),
+    }
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/me/webhooks", web::get().to(list_webhooks))                 // GET /me/webhooks
+        .route("/me/webhooks", web::post().to(create_webhook))              // POST /me/webhooks
+        .route("/me/webhooks/{id}", web::delete().to(delete_webhook))       // DELETE /me/webhooks/{id}
+        .route("/me/webhooks/{id}/enable", web::post().to(enable_webhook)); // POST /me/webhooks/{id}/enable
+}
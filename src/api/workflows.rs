@@ -0,0 +1,242 @@
+use actix_web::{web, HttpResponse};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::PgPool;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::auth::extractor::AdminUser;
+use crate::services::workflow::{self, NewWorkflow, Step};
+use crate::services::{template, user};
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct UpsertWorkflowRequest {
+    pub name: String,
+    pub steps: Vec<Step>,
+    pub trigger_event: Option<String>,  // Event type that starts a run for the event's user
+    #[serde(default)]
+    pub exit_events: Vec<String>,       // Event types that cancel the user's active run
+    pub enabled: Option<bool>,          // Defaults to true
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct StartRunRequest {
+    pub user_id: Uuid,
+    #[schema(value_type = Object)]
+    #[serde(default)]
+    pub context: Value,  // Available to templates and conditions as event properties
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct StartRunResponse {
+    pub run_id: Uuid,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct RunsQuery {
+    pub status: Option<String>,  // Active, Completed, Cancelled or Failed
+    pub limit: Option<i64>,
+}
+
+// GET /admin/workflows - List workflow definitions
+#[utoipa::path(
+    get,
+    path = "/api/admin/workflows",
+    responses(
+        (status = 200, description = "Workflows retrieved successfully", body = [Workflow]),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    tag = "Workflows API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn list_workflows(db: web::Data<PgPool>, _admin: AdminUser) -> HttpResponse {
+    match workflow::list_workflows(db.get_ref()).await {
+        Ok(workflows) => HttpResponse::Ok().json(workflows),
+        Err(_) => HttpResponse::InternalServerError().json("Error fetching workflows"),
+    }
+}
+
+// PUT /admin/workflows/{key} - Create or replace a workflow definition
+#[utoipa::path(
+    put,
+    path = "/api/admin/workflows/{key}",
+    params(
+        ("key" = String, Path, description = "Workflow key")
+    ),
+    request_body = UpsertWorkflowRequest,
+    responses(
+        (status = 200, description = "Workflow saved", body = Workflow),
+        (status = 400, description = "Invalid workflow definition"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    tag = "Workflows API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn upsert_workflow(
+    key: web::Path<String>,
+    body: web::Json<UpsertWorkflowRequest>,
+    db: web::Data<PgPool>,
+    admin: AdminUser,
+) -> HttpResponse {
+    let body = body.into_inner();
+
+    if let Err(e) = workflow::validate_steps(&body.steps) {
+        return HttpResponse::BadRequest().json(e);
+    }
+
+    for template_key in workflow::template_keys(&body.steps) {
+        match template::get_template(db.get_ref(), template_key).await {
+            Ok(Some(_)) => {}
+            Ok(None) => return HttpResponse::BadRequest().json(format!("Unknown template: {}", template_key)),
+            Err(_) => return HttpResponse::InternalServerError().json("Error saving workflow"),
+        }
+    }
+
+    let new_workflow = NewWorkflow {
+        name: body.name,
+        steps: body.steps,
+        trigger_event: body.trigger_event,
+        exit_events: body.exit_events,
+        enabled: body.enabled.unwrap_or(true),
+    };
+
+    match workflow::upsert_workflow(db.get_ref(), &key, new_workflow).await {
+        Ok(saved) => {
+            info!("Admin {} saved workflow {}", admin.0.sub, saved.key);
+            HttpResponse::Ok().json(saved)
+        }
+        Err(_) => HttpResponse::InternalServerError().json("Error saving workflow"),
+    }
+}
+
+// GET /admin/workflows/{key}/runs - Inspect runs of a workflow, newest first
+#[utoipa::path(
+    get,
+    path = "/api/admin/workflows/{key}/runs",
+    params(
+        ("key" = String, Path, description = "Workflow key"),
+        RunsQuery
+    ),
+    responses(
+        (status = 200, description = "Runs retrieved successfully", body = [WorkflowRun]),
+        (status = 404, description = "Workflow not found"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    tag = "Workflows API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn list_runs(
+    key: web::Path<String>,
+    query: web::Query<RunsQuery>,
+    db: web::Data<PgPool>,
+    _admin: AdminUser,
+) -> HttpResponse {
+    let workflow_id = match workflow::find_workflow_id(db.get_ref(), &key).await {
+        Ok(Some(id)) => id,
+        Ok(None) => return HttpResponse::NotFound().json("Workflow not found"),
+        Err(_) => return HttpResponse::InternalServerError().json("Error fetching runs"),
+    };
+
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+
+    match workflow::list_runs(db.get_ref(), workflow_id, query.status.as_deref(), limit).await {
+        Ok(runs) => HttpResponse::Ok().json(runs),
+        Err(_) => HttpResponse::InternalServerError().json("Error fetching runs"),
+    }
+}
+
+// POST /admin/workflows/{key}/runs - Enrol a user in a workflow by hand
+#[utoipa::path(
+    post,
+    path = "/api/admin/workflows/{key}/runs",
+    params(
+        ("key" = String, Path, description = "Workflow key")
+    ),
+    request_body = StartRunRequest,
+    responses(
+        (status = 201, description = "Run started", body = StartRunResponse),
+        (status = 404, description = "Workflow or user not found"),
+        (status = 409, description = "User already has an active run"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    tag = "Workflows API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn start_run(
+    key: web::Path<String>,
+    body: web::Json<StartRunRequest>,
+    db: web::Data<PgPool>,
+    _admin: AdminUser,
+) -> HttpResponse {
+    let workflow_id = match workflow::find_workflow_id(db.get_ref(), &key).await {
+        Ok(Some(id)) => id,
+        Ok(None) => return HttpResponse::NotFound().json("Workflow not found"),
+        Err(_) => return HttpResponse::InternalServerError().json("Error starting run"),
+    };
+
+    match user::find_user(db.get_ref(), body.user_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json("User not found"),
+        Err(_) => return HttpResponse::InternalServerError().json("Error starting run"),
+    }
+
+    let context = match &body.context {
+        Value::Null => Value::Object(Default::default()),
+        context => context.clone(),
+    };
+
+    match workflow::start_run(db.get_ref(), workflow_id, body.user_id, &context).await {
+        Ok(Some(run_id)) => HttpResponse::Created().json(StartRunResponse { run_id }),
+        Ok(None) => HttpResponse::Conflict().json("User already has an active run of this workflow"),
+        Err(_) => HttpResponse::InternalServerError().json("Error starting run"),
+    }
+}
+
+// POST /admin/workflow-runs/{id}/cancel - Stop an active run
+#[utoipa::path(
+    post,
+    path = "/api/admin/workflow-runs/{id}/cancel",
+    params(
+        ("id" = Uuid, Path, description = "ID of the run")
+    ),
+    responses(
+        (status = 200, description = "Run cancelled"),
+        (status = 404, description = "No active run with this ID"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Admin access required")
+    ),
+    tag = "Workflows API",
+    security(
+        ("BearerAuth" = [])
+    )
+)]
+pub async fn cancel_run(run_id: web::Path<Uuid>, db: web::Data<PgPool>, admin: AdminUser) -> HttpResponse {
+    let reason = format!("Cancelled by admin {}", admin.0.sub);
+
+    match workflow::cancel_run(db.get_ref(), run_id.into_inner(), &reason).await {
+        Ok(true) => HttpResponse::Ok().json("Run cancelled"),
+        Ok(false) => HttpResponse::NotFound().json("No active run with this ID"),
+        Err(_) => HttpResponse::InternalServerError().json("Error cancelling run"),
+    }
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/admin/workflows", web::get().to(list_workflows))                    // GET /admin/workflows
+        .route("/admin/workflows/{key}", web::put().to(upsert_workflow))              // PUT /admin/workflows/{key}
+        .route("/admin/workflows/{key}/runs", web::get().to(list_runs))               // GET /admin/workflows/{key}/runs
+        .route("/admin/workflows/{key}/runs", web::post().to(start_run))              // POST /admin/workflows/{key}/runs
+        .route("/admin/workflow-runs/{id}/cancel", web::post().to(cancel_run));       // POST /admin/workflow-runs/{id}/cancel
+}
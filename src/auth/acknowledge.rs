@@ -0,0 +1,36 @@
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+const AUDIENCE: &str = "acknowledge";
+
+/// How long after the call is placed the keypress callback is accepted
+const TOKEN_LIFETIME: Duration = Duration::DAY;
+
+/// Claims carried by the callback URL a voice call reports keypresses to
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AcknowledgeClaims {
+    pub sub: Uuid,  // Notification ID
+    pub aud: String,
+    pub exp: i64,
+}
+
+pub fn sign_token(secret: &str, notification_id: Uuid) -> Result<String, jsonwebtoken::errors::Error> {
+    let claims = AcknowledgeClaims {
+        sub: notification_id,
+        aud: AUDIENCE.to_string(),
+        exp: (OffsetDateTime::now_utc() + TOKEN_LIFETIME).unix_timestamp(),
+    };
+
+    encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_ref()))
+}
+
+pub fn verify_token(secret: &str, token: &str) -> Result<AcknowledgeClaims, jsonwebtoken::errors::Error> {
+    let mut validation = Validation::default();
+    validation.set_audience(&[AUDIENCE]);
+    validation.set_required_spec_claims(&["aud", "sub", "exp"]);
+
+    decode::<AcknowledgeClaims>(token, &DecodingKey::from_secret(secret.as_ref()), &validation)
+        .map(|data| data.claims)
+}
@@ -1,23 +1,35 @@
-use actix_web::{dev::Payload, Error, FromRequest, HttpRequest};
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
 use futures::future::{err, ok, Ready};
-use jsonwebtoken::{decode, Validation, DecodingKey, TokenData};
+use jsonwebtoken::{decode, Validation, DecodingKey};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::config::Config;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthenticatedUser {
     pub sub: Uuid,  // User ID (Subject)
+    #[serde(default)]
+    pub admin: bool,  // Tokens issued before admin roles existed have no claim
 }
 
+/// An authenticated user whose token carries the admin claim
+#[derive(Debug)]
+pub struct AdminUser(pub AuthenticatedUser);
+
 impl FromRequest for AuthenticatedUser {
     type Error = actix_web::Error;
     type Future = Ready<Result<Self, Self::Error>>;
 
     fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let Some(config) = req.app_data::<web::Data<Config>>() else {
+            return err(actix_web::error::ErrorInternalServerError("Configuration is not available"));
+        };
+
         if let Some(auth_header) = req.headers().get("Authorization") {
             if let Ok(auth_str) = auth_header.to_str() {
                 if let Some(token) = extract_bearer_token(auth_str) {
-                    return decode_jwt(token);
+                    return decode_jwt(&config.jwt_secret, token);
                 }
             }
         }
@@ -26,6 +38,19 @@ impl FromRequest for AuthenticatedUser {
     }
 }
 
+impl FromRequest for AdminUser {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        match AuthenticatedUser::from_request(req, payload).into_inner() {
+            Ok(user) if user.admin => ok(AdminUser(user)),
+            Ok(_) => err(actix_web::error::ErrorForbidden("Admin access required")),
+            Err(e) => err(e),
+        }
+    }
+}
+
 fn extract_bearer_token(auth_str: &str) -> Option<&str> {
     if auth_str.starts_with("Bearer ") {
         Some(auth_str.trim_start_matches("Bearer ").trim())
@@ -34,9 +59,9 @@ fn extract_bearer_token(auth_str: &str) -> Option<&str> {
     }
 }
 
-fn decode_jwt(token: &str) -> Ready<Result<AuthenticatedUser, actix_web::Error>> {
-    let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    match decode::<AuthenticatedUser>(&token, &DecodingKey::from_secret(secret.as_ref()), &Validation::default()) {
+/// Checks the token against the same secret the login endpoint signs with
+fn decode_jwt(secret: &str, token: &str) -> Ready<Result<AuthenticatedUser, actix_web::Error>> {
+    match decode::<AuthenticatedUser>(token, &DecodingKey::from_secret(secret.as_ref()), &Validation::default()) {
         Ok(data) => ok(data.claims),
         Err(_) => err(actix_web::error::ErrorUnauthorized("Invalid or expired JWT token")),
     }
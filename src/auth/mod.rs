@@ -1 +1,5 @@
-pub mod extractor;
\ No newline at end of file
+pub mod acknowledge;
+pub mod extractor;
+pub mod secret;
+pub mod tracking;
+pub mod unsubscribe;
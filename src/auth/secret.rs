@@ -0,0 +1,5 @@
+/// Compares a secret presented by a caller with the expected one without leaking, through timing,
+/// how many leading bytes matched
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,34 @@
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const AUDIENCE: &str = "track";
+
+/// Claims carried by open-pixel and click-redirect URLs
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrackingClaims {
+    pub sub: Uuid,  // Notification ID
+    pub aud: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,  // Click destination; absent for the open pixel
+}
+
+/// Signs a tracking token; the signature covers the destination so the redirect cannot be abused
+pub fn sign_token(secret: &str, notification_id: Uuid, url: Option<&str>) -> Result<String, jsonwebtoken::errors::Error> {
+    let claims = TrackingClaims {
+        sub: notification_id,
+        aud: AUDIENCE.to_string(),
+        url: url.map(str::to_string),
+    };
+
+    encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_ref()))
+}
+
+pub fn verify_token(secret: &str, token: &str) -> Result<TrackingClaims, jsonwebtoken::errors::Error> {
+    let mut validation = Validation::default();
+    validation.set_audience(&[AUDIENCE]);
+    validation.set_required_spec_claims(&["aud", "sub"]);
+
+    decode::<TrackingClaims>(token, &DecodingKey::from_secret(secret.as_ref()), &validation)
+        .map(|data| data.claims)
+}
@@ -0,0 +1,43 @@
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::db::models::{Category, DeliveryMethod};
+
+// Audience claim keeps unsubscribe tokens and login tokens from being swapped for one another
+const AUDIENCE: &str = "unsubscribe";
+
+/// Claims carried by a one-click unsubscribe link
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnsubscribeClaims {
+    pub sub: Uuid,
+    pub aud: String,
+    pub category: Category,
+    pub channel: Option<DeliveryMethod>,
+}
+
+/// Signs an unsubscribe token; these deliberately never expire so old emails keep working
+pub fn sign_token(
+    secret: &str,
+    user_id: Uuid,
+    category: Category,
+    channel: Option<DeliveryMethod>,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let claims = UnsubscribeClaims {
+        sub: user_id,
+        aud: AUDIENCE.to_string(),
+        category,
+        channel,
+    };
+
+    encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_ref()))
+}
+
+pub fn verify_token(secret: &str, token: &str) -> Result<UnsubscribeClaims, jsonwebtoken::errors::Error> {
+    let mut validation = Validation::default();
+    validation.set_audience(&[AUDIENCE]);
+    validation.set_required_spec_claims(&["aud", "sub"]);
+
+    decode::<UnsubscribeClaims>(token, &DecodingKey::from_secret(secret.as_ref()), &validation)
+        .map(|data| data.claims)
+}
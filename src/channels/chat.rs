@@ -0,0 +1,230 @@
+use std::time::Duration;
+
+use log::{error, warn};
+use reqwest::{redirect, Client, StatusCode, Url};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+
+use crate::channels::{host_matches, DeliveryError};
+use crate::db::models::{Category, DeliveryMethod, PendingNotification, User};
+use crate::services::chat_webhooks;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Limits of each service's fields, beyond which the whole message is rejected
+const SLACK_HEADER_MAX: usize = 150;
+const SLACK_SECTION_MAX: usize = 3000;
+const SLACK_BUTTON_MAX: usize = 75;
+const DISCORD_TITLE_MAX: usize = 256;
+const DISCORD_DESCRIPTION_MAX: usize = 4096;
+
+const SLACK_HOSTS: [&str; 1] = ["hooks.slack.com"];
+const DISCORD_HOSTS: [&str; 2] = ["discord.com", "discordapp.com"];
+/// Office 365 connectors, then Power Automate workflows on Logic Apps and Power Platform
+const TEAMS_HOSTS: [&str; 3] = ["webhook.office.com", "logic.azure.com", "api.powerplatform.com"];
+
+/// Whether `url` is an https incoming-webhook URL of the chat service `method` names, so a
+/// user-supplied URL cannot make the dispatcher post to any other host
+pub fn is_provider_url(method: DeliveryMethod, url: &Url) -> bool {
+    let hosts: &[&str] = match method {
+        DeliveryMethod::Slack => &SLACK_HOSTS,
+        DeliveryMethod::Discord => &DISCORD_HOSTS,
+        DeliveryMethod::Teams => &TEAMS_HOSTS,
+        _ => return false,
+    };
+    url.scheme() == "https" && url.host_str().is_some_and(|host| host_matches(host, hosts))
+}
+
+/// Posts notifications to Slack, Discord and Microsoft Teams incoming webhooks
+pub struct ChatChannel {
+    pool: PgPool,
+    client: Client,
+}
+
+impl ChatChannel {
+    pub fn new(pool: PgPool) -> Self {
+        ChatChannel {
+            pool,
+            // A redirect could lead away from the chat service's hosts
+            client: Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .redirect(redirect::Policy::none())
+                .build()
+                .expect("Failed to build HTTP client"),
+        }
+    }
+
+    async fn target(&self, method: DeliveryMethod, user: &User) -> Result<chat_webhooks::ChatTarget, DeliveryError> {
+        chat_webhooks::active_webhook(&self.pool, user.id, method)
+            .await
+            .map_err(|e| DeliveryError::Transient(format!("failed to load {} webhook: {}", method, e)))?
+            .ok_or_else(|| DeliveryError::Unreachable(format!("no active {} webhook", method)))
+    }
+
+    pub async fn check(&self, method: DeliveryMethod, user: &User) -> Result<(), DeliveryError> {
+        self.target(method, user).await.map(|_| ())
+    }
+
+    pub async fn send(&self, method: DeliveryMethod, user: &User, notification: &PendingNotification) -> Result<(), DeliveryError> {
+        let target = self.target(method, user).await?;
+        // Also covers URLs saved before the host check existed
+        let url = match Url::parse(&target.url) {
+            Ok(url) if is_provider_url(method, &url) => url,
+            _ => return Err(DeliveryError::Unreachable(format!("{} webhook URL is not a {} host", method, method))),
+        };
+
+        let response = self.client
+            .post(url)
+            .json(&payload(method, notification))
+            .send()
+            .await
+            .map_err(|e| DeliveryError::Transient(format!("{} webhook request failed: {}", method, e)))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+
+        // The body stays in the server log: the reason is shown to the webhook's owner
+        let reason = format!("{} returned {}", method, status);
+        warn!("{} webhook {} returned {}: {}", method, target.id, status, response.text().await.unwrap_or_default().trim());
+        match status {
+            // The webhook was deleted, revoked or its channel archived; the owner has to set a new one
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::NOT_FOUND | StatusCode::GONE => {
+                warn!("Deactivating {} webhook {}", method, target.id);
+                if let Err(e) = chat_webhooks::deactivate_webhook(&self.pool, target.id, &reason).await {
+                    error!("Failed to deactivate {} webhook {}: {:?}", method, target.id, e);
+                }
+                Err(DeliveryError::Unreachable(reason))
+            }
+            StatusCode::TOO_MANY_REQUESTS => Err(DeliveryError::Transient(reason)),
+            status if status.is_server_error() => Err(DeliveryError::Transient(reason)),
+            _ => Err(DeliveryError::Permanent(reason)),
+        }
+    }
+}
+
+/// The request body for `method`, which must be one of the chat methods
+pub fn payload(method: DeliveryMethod, notification: &PendingNotification) -> Value {
+    match method {
+        DeliveryMethod::Slack => slack_payload(notification),
+        DeliveryMethod::Discord => discord_payload(notification),
+        _ => teams_payload(notification),
+    }
+}
+
+/// Cuts `text` to at most `max` characters, ending it with an ellipsis if anything was cut
+fn clip(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max.saturating_sub(1)) {
+        Some((end, _)) if text.chars().count() > max => format!("{}…", &text[..end]),
+        _ => text.to_string(),
+    }
+}
+
+/// Slack treats &, < and > as control characters in mrkdwn text
+fn slack_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Block Kit message: optional header, the content as a section and actions as link buttons
+fn slack_payload(notification: &PendingNotification) -> Value {
+    let mut blocks = Vec::new();
+    if let Some(title) = &notification.title {
+        blocks.push(json!({
+            "type": "header",
+            "text": { "type": "plain_text", "text": clip(title, SLACK_HEADER_MAX) },
+        }));
+    }
+    blocks.push(json!({
+        "type": "section",
+        "text": { "type": "mrkdwn", "text": clip(&slack_escape(&notification.content), SLACK_SECTION_MAX) },
+    }));
+    if !notification.actions().is_empty() {
+        let buttons: Vec<Value> = notification
+            .actions()
+            .iter()
+            .map(|action| json!({
+                "type": "button",
+                "text": { "type": "plain_text", "text": clip(&action.label, SLACK_BUTTON_MAX) },
+                "url": action.url,
+            }))
+            .collect();
+        blocks.push(json!({ "type": "actions", "elements": buttons }));
+    }
+
+    // `text` is what notifications and clients without Block Kit support show
+    let fallback = match &notification.title {
+        Some(title) => format!("{}: {}", title, notification.content),
+        None => notification.content.clone(),
+    };
+    json!({ "text": clip(&slack_escape(&fallback), SLACK_SECTION_MAX), "blocks": blocks })
+}
+
+/// A single embed coloured by category; webhook messages cannot carry buttons, so actions become links
+fn discord_payload(notification: &PendingNotification) -> Value {
+    let color = match notification.category() {
+        Category::Security => 0xE0_1E_5A,
+        Category::Reminders => 0xEC_B2_2E,
+        Category::Marketing => 0x2E_B6_7D,
+        Category::General => 0x36_C5_F0,
+    };
+
+    let mut description = notification.content.clone();
+    if !notification.actions().is_empty() {
+        let links: Vec<String> = notification
+            .actions()
+            .iter()
+            .map(|action| format!("[{}]({})", action.label.replace(['[', ']'], ""), action.url))
+            .collect();
+        description = format!("{}\n\n{}", description, links.join(" · "));
+    }
+
+    let mut embed = json!({
+        "description": clip(&description, DISCORD_DESCRIPTION_MAX),
+        "color": color,
+        "footer": { "text": notification.category },
+    });
+    if let Some(title) = &notification.title {
+        embed["title"] = json!(clip(title, DISCORD_TITLE_MAX));
+    }
+
+    // Never let notification content ping @everyone, roles or users
+    json!({ "embeds": [embed], "allowed_mentions": { "parse": [] } })
+}
+
+/// Adaptive Card wrapped in the message envelope Teams incoming webhooks and workflows expect
+fn teams_payload(notification: &PendingNotification) -> Value {
+    let mut body = Vec::new();
+    if let Some(title) = &notification.title {
+        body.push(json!({
+            "type": "TextBlock",
+            "text": title,
+            "size": "Medium",
+            "weight": "Bolder",
+            "color": if notification.category() == Category::Security { "Attention" } else { "Default" },
+            "wrap": true,
+        }));
+    }
+    body.push(json!({ "type": "TextBlock", "text": notification.content, "wrap": true }));
+
+    let actions: Vec<Value> = notification
+        .actions()
+        .iter()
+        .map(|action| json!({ "type": "Action.OpenUrl", "title": action.label, "url": action.url }))
+        .collect();
+
+    json!({
+        "type": "message",
+        "attachments": [{
+            "contentType": "application/vnd.microsoft.card.adaptive",
+            "contentUrl": null,
+            "content": {
+                "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                "type": "AdaptiveCard",
+                "version": "1.4",
+                "body": body,
+                "actions": actions,
+            },
+        }],
+    })
+}
@@ -0,0 +1,173 @@
+use lettre::{
+    message::{
+        header::{Header, HeaderName, HeaderValue},
+        Mailbox, MultiPart,
+    },
+    Message,
+};
+use sqlx::PgPool;
+
+use crate::auth::unsubscribe;
+use crate::channels::DeliveryError;
+use crate::config::Config;
+use crate::db::models::{Category, DeliveryMethod, PendingNotification, User};
+use crate::services::mailer::Mailer;
+use crate::services::{suppression, tracking};
+
+/// RFC 2369 `List-Unsubscribe` header
+#[derive(Clone)]
+struct ListUnsubscribe(String);
+
+impl Header for ListUnsubscribe {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe")
+    }
+
+    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(ListUnsubscribe(s.to_string()))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), self.0.clone())
+    }
+}
+
+/// RFC 8058 `List-Unsubscribe-Post` header, which tells mail clients the link supports one-click POST
+#[derive(Clone)]
+struct ListUnsubscribePost;
+
+impl Header for ListUnsubscribePost {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe-Post")
+    }
+
+    fn parse(_: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(ListUnsubscribePost)
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), "List-Unsubscribe=One-Click".to_string())
+    }
+}
+
+const SUBJECT: &str = "You have a new notification";
+
+/// An email as it will go out, minus the envelope
+pub struct RenderedEmail {
+    pub subject: String,
+    pub text: String,
+    pub html: Option<String>,
+    pub unsubscribe_url: Option<String>,  // Also advertised through the List-Unsubscribe headers
+}
+
+pub struct EmailChannel {
+    pool: PgPool,
+    mailer: Mailer,
+    public_url: String,
+    token_secret: String,
+    tracking_categories: Vec<Category>,
+}
+
+impl EmailChannel {
+    pub fn new(config: &Config, pool: PgPool, mailer: Mailer) -> Self {
+        EmailChannel {
+            pool,
+            mailer,
+            public_url: config.public_url.trim_end_matches('/').to_string(),
+            token_secret: config.jwt_secret.clone(),
+            tracking_categories: config.tracking_categories.clone(),
+        }
+    }
+
+    /// Whether this user can receive email at all, before anything is rendered
+    pub async fn check(&self, user: &User) -> Result<(), DeliveryError> {
+        // Only deliver to addresses the user has proven they own
+        if !user.email_verified.unwrap_or(false) {
+            return Err(DeliveryError::Unreachable("email address not verified".to_string()));
+        }
+
+        // Sending to an address that bounced or complained only hurts our sender reputation
+        match suppression::find_suppression(&self.pool, &user.email).await {
+            Ok(Some(reason)) => Err(DeliveryError::Unreachable(format!("email address is suppressed ({})", reason))),
+            Ok(None) => Ok(()),
+            Err(e) => Err(DeliveryError::Transient(format!("failed to check suppression list: {}", e))),
+        }
+    }
+
+    /// Builds the subject and bodies exactly as they will be sent, tracking and unsubscribe links included
+    pub fn render(&self, user: &User, notification: &PendingNotification) -> Result<RenderedEmail, DeliveryError> {
+        let mut text = notification.content.clone();
+        let category = notification.category();
+
+        // Links are rewritten before the unsubscribe footer is added so that link is never tracked
+        let mut html = match &notification.html_content {
+            Some(html) if category.is_optional() && self.tracking_categories.contains(&category) => Some(
+                tracking::instrument_html(html, &self.public_url, &self.token_secret, notification.id)
+                    .map_err(|e| DeliveryError::Permanent(format!("failed to sign tracking token: {}", e)))?,
+            ),
+            Some(html) => Some(html.clone()),
+            None => None,
+        };
+
+        let mut unsubscribe_url = None;
+        if category.is_optional() {
+            let token = unsubscribe::sign_token(&self.token_secret, user.id, category, Some(DeliveryMethod::Email))
+                .map_err(|e| DeliveryError::Permanent(format!("failed to sign unsubscribe token: {}", e)))?;
+            let url = format!("{}/api/unsubscribe/{}", self.public_url, token);
+
+            text.push_str(&format!(
+                "\n\nTo stop receiving {} emails, unsubscribe here: {}",
+                category.as_str().to_lowercase(),
+                url
+            ));
+            html = html.map(|html| {
+                let footer = format!("<p><a href=\"{}\">Unsubscribe</a></p>", url);
+                tracking::insert_before_body_end(&html, &footer)
+            });
+            unsubscribe_url = Some(url);
+        }
+
+        Ok(RenderedEmail {
+            subject: SUBJECT.to_string(),
+            text,
+            html,
+            unsubscribe_url,
+        })
+    }
+
+    pub async fn send(&self, user: &User, notification: &PendingNotification) -> Result<(), DeliveryError> {
+        self.check(user).await?;
+        let rendered = self.render(user, notification)?;
+
+        let from = self.mailer.from().clone();
+        let to: Mailbox = user.email.parse()
+            .map_err(|e| DeliveryError::Permanent(format!("invalid recipient address: {}", e)))?;
+
+        // Bounces quote the Message-ID back to us, which is how they are matched to the notification
+        let message_id = format!("<{}@{}>", notification.id, from.email.domain());
+
+        let mut builder = Message::builder()
+            .message_id(Some(message_id))
+            .from(from)
+            .to(to)
+            .subject(rendered.subject);
+
+        if let Some(url) = rendered.unsubscribe_url {
+            builder = builder
+                .header(ListUnsubscribe(format!("<{}>", url)))
+                .header(ListUnsubscribePost);
+        }
+
+        let email = match rendered.html {
+            Some(html) => builder.multipart(MultiPart::alternative_plain_html(rendered.text, html)),
+            None => builder.body(rendered.text),
+        }
+        .map_err(|e| DeliveryError::Permanent(format!("failed to build email: {}", e)))?;
+
+        match self.mailer.send(email).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.is_permanent() => Err(DeliveryError::Permanent(e.to_string())),
+            Err(e) => Err(DeliveryError::Transient(e.to_string())),
+        }
+    }
+}
@@ -0,0 +1,335 @@
+pub mod chat;
+pub mod email;
+pub mod public_addr;
+pub mod push;
+pub mod self_hosted;
+pub mod sms;
+pub mod telegram;
+pub mod voice;
+pub mod webhook;
+
+use std::fmt;
+
+use serde::Serialize;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::config::Config;
+use crate::db::models::{DeliveryMethod, PendingNotification, SmsOverflow, User};
+use crate::services::mailer::Mailer;
+use chat::ChatChannel;
+use email::EmailChannel;
+use push::{PushChannel, PushProvider};
+use self_hosted::SelfHostedChannel;
+use sms::{SmsChannel, SmsPolicy};
+use telegram::TelegramChannel;
+use voice::VoiceChannel;
+use webhook::WebhookChannel;
+
+/// Why a channel could not deliver a notification
+#[derive(Debug)]
+pub enum DeliveryError {
+    /// The recipient has no usable address on this channel (no device, number or verified email)
+    Unreachable(String),
+    /// The channel rejected the message and retrying will not help
+    Permanent(String),
+    /// The channel is temporarily unavailable; the notification should be retried later
+    Transient(String),
+}
+
+impl fmt::Display for DeliveryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeliveryError::Unreachable(reason) => write!(f, "unreachable: {}", reason),
+            DeliveryError::Permanent(reason) => write!(f, "permanent failure: {}", reason),
+            DeliveryError::Transient(reason) => write!(f, "transient failure: {}", reason),
+        }
+    }
+}
+
+/// The error code in a provider's JSON error body, e.g. Twilio's `{"code": 21211, ...}`
+///
+/// Reasons end up in `last_error` and in events the recipient can read, so they carry this code
+/// rather than the body itself.
+pub fn provider_error_code(body: &str) -> Option<String> {
+    let body: serde_json::Value = serde_json::from_str(body).ok()?;
+    ["code", "error_code"].iter().find_map(|field| match &body[field] {
+        serde_json::Value::Number(code) => Some(code.to_string()),
+        serde_json::Value::String(code) if !code.is_empty() && code.len() <= 64 => Some(code.clone()),
+        _ => None,
+    })
+}
+
+/// Whether `host` is one of `allowed` or a subdomain of one
+pub fn host_matches(host: &str, allowed: &[&str]) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    allowed
+        .iter()
+        .any(|allowed| host == *allowed || host.strip_suffix(allowed).is_some_and(|sub| sub.ends_with('.')))
+}
+
+/// A service a channel hands messages to
+///
+/// Rate limits and circuit breakers are kept per provider, so e.g. an APNs outage does not hold
+/// back Android or browser notifications.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Provider {
+    pub key: &'static str,  // e.g. push:fcm or sms:twilio
+    push: Option<PushProvider>,  // Which of the user's devices a push goes to
+}
+
+impl Provider {
+    fn new(key: &'static str) -> Self {
+        Provider { key, push: None }
+    }
+}
+
+/// What a channel would send, as returned by the preview endpoint
+#[derive(Serialize, ToSchema)]
+pub struct RenderedMessage {
+    pub subject: Option<String>,
+    pub text: String,
+    pub html: Option<String>,
+    pub sms_segments: Option<Vec<String>>,
+    pub sms_encoding: Option<String>,  // GSM-7 or UCS-2
+    pub sms_segment_count: Option<i32>,  // What the message would be billed as
+}
+
+/// All configured delivery channels, shared by the dispatcher
+pub struct Channels {
+    email: EmailChannel,
+    sms: Option<SmsChannel>,  // None when no SMS provider is configured
+    push: PushChannel,
+    webhook: WebhookChannel,
+    chat: ChatChannel,
+    self_hosted: SelfHostedChannel,
+    telegram: Option<TelegramChannel>,  // None when no bot is configured
+    voice: Option<VoiceChannel>,  // None when no voice provider is configured
+    sms_policy: SmsPolicy,  // Defaults for notifications that do not set their own
+}
+
+impl Channels {
+    pub fn from_config(config: &Config, pool: PgPool, mailer: Mailer) -> Self {
+        Channels {
+            email: EmailChannel::new(config, pool.clone(), mailer),
+            sms: config.sms.as_ref().map(|sms| SmsChannel::new(sms, pool.clone())),
+            push: PushChannel::new(config, pool.clone()),
+            webhook: WebhookChannel::new(config, pool.clone()),
+            chat: ChatChannel::new(pool.clone()),
+            self_hosted: SelfHostedChannel::new(pool.clone()),
+            telegram: config.telegram.as_ref().map(|telegram| TelegramChannel::new(telegram, pool)),
+            voice: config.voice.as_ref().map(|voice| VoiceChannel::new(config, voice)),
+            sms_policy: SmsPolicy {
+                max_segments: config.sms_max_segments,
+                overflow: config.sms_overflow,
+            },
+        }
+    }
+
+    /// The SMS segment policy for a notification, falling back to the configured defaults
+    pub fn sms_policy(&self, max_segments: Option<i32>, overflow: Option<SmsOverflow>) -> SmsPolicy {
+        SmsPolicy {
+            max_segments: max_segments.unwrap_or(self.sms_policy.max_segments),
+            overflow: overflow.unwrap_or(self.sms_policy.overflow),
+        }
+    }
+
+    /// The SMS text for a notification once its segment policy has been applied
+    pub fn sms_body(&self, notification: &PendingNotification) -> Result<String, DeliveryError> {
+        self.sms_policy(notification.sms_max_segments, notification.sms_overflow())
+            .apply(&notification.content)
+            .map_err(DeliveryError::Permanent)
+    }
+
+    /// Fails when the user cannot be reached on `method`, without sending anything
+    pub async fn check(&self, method: DeliveryMethod, user: &User) -> Result<(), DeliveryError> {
+        match method {
+            DeliveryMethod::Email => self.email.check(user).await,
+            DeliveryMethod::Sms => {
+                sms::verified_number(user)?;
+                match self.sms {
+                    Some(_) => Ok(()),
+                    None => Err(DeliveryError::Permanent("SMS channel is not configured".to_string())),
+                }
+            }
+            DeliveryMethod::Push => self.push.check(user).await,
+            DeliveryMethod::Webhook => self.webhook.check(user).await,
+            DeliveryMethod::Slack | DeliveryMethod::Discord | DeliveryMethod::Teams => self.chat.check(method, user).await,
+            DeliveryMethod::Ntfy | DeliveryMethod::Gotify => self.self_hosted.check(method, user).await,
+            DeliveryMethod::Telegram => match &self.telegram {
+                Some(telegram) => telegram.check(user).await,
+                None => Err(DeliveryError::Permanent("Telegram channel is not configured".to_string())),
+            },
+            DeliveryMethod::Voice => {
+                voice::verified_number(user)?;
+                match self.voice {
+                    Some(_) => Ok(()),
+                    None => Err(DeliveryError::Permanent("Voice channel is not configured".to_string())),
+                }
+            }
+        }
+    }
+
+    /// Renders the message `send` would deliver through `method`
+    pub fn render(
+        &self,
+        method: DeliveryMethod,
+        user: &User,
+        notification: &PendingNotification,
+    ) -> Result<RenderedMessage, DeliveryError> {
+        match method {
+            DeliveryMethod::Email => {
+                let email = self.email.render(user, notification)?;
+                Ok(RenderedMessage {
+                    subject: Some(email.subject),
+                    text: email.text,
+                    html: email.html,
+                    sms_segments: None,
+                    sms_encoding: None,
+                    sms_segment_count: None,
+                })
+            }
+            DeliveryMethod::Sms => {
+                let body = self.sms_body(notification)?;
+                let segments = sms::segments(&body);
+                Ok(RenderedMessage {
+                    subject: None,
+                    sms_encoding: Some(sms::encoding(&body).as_str().to_string()),
+                    sms_segment_count: Some(segments.len() as i32),
+                    sms_segments: Some(segments),
+                    text: body,
+                    html: None,
+                })
+            }
+            DeliveryMethod::Push => Ok(RenderedMessage {
+                subject: None,
+                text: notification.content.clone(),
+                html: None,
+                sms_segments: None,
+                sms_encoding: None,
+                sms_segment_count: None,
+            }),
+            DeliveryMethod::Webhook => Ok(RenderedMessage {
+                subject: None,
+                text: serde_json::to_string_pretty(&WebhookChannel::payload(notification)).unwrap_or_default(),
+                html: None,
+                sms_segments: None,
+                sms_encoding: None,
+                sms_segment_count: None,
+            }),
+            DeliveryMethod::Slack | DeliveryMethod::Discord | DeliveryMethod::Teams => Ok(RenderedMessage {
+                subject: None,
+                text: serde_json::to_string_pretty(&chat::payload(method, notification)).unwrap_or_default(),
+                html: None,
+                sms_segments: None,
+                sms_encoding: None,
+                sms_segment_count: None,
+            }),
+            DeliveryMethod::Ntfy | DeliveryMethod::Gotify => Ok(RenderedMessage {
+                subject: None,
+                text: serde_json::to_string_pretty(&self_hosted::payload(method, notification)).unwrap_or_default(),
+                html: None,
+                sms_segments: None,
+                sms_encoding: None,
+                sms_segment_count: None,
+            }),
+            DeliveryMethod::Telegram => Ok(RenderedMessage {
+                subject: None,
+                text: serde_json::to_string_pretty(&telegram::message(notification)).unwrap_or_default(),
+                html: None,
+                sms_segments: None,
+                sms_encoding: None,
+                sms_segment_count: None,
+            }),
+            DeliveryMethod::Voice => match &self.voice {
+                Some(voice) => Ok(RenderedMessage {
+                    subject: None,
+                    text: voice.render(notification)?,
+                    html: None,
+                    sms_segments: None,
+                    sms_encoding: None,
+                    sms_segment_count: None,
+                }),
+                None => Err(DeliveryError::Permanent("Voice channel is not configured".to_string())),
+            },
+        }
+    }
+
+    pub fn vapid_public_key(&self) -> Option<&str> {
+        self.push.vapid_public_key()
+    }
+
+    pub fn telegram(&self) -> Option<&TelegramChannel> {
+        self.telegram.as_ref()
+    }
+
+    pub fn voice(&self) -> Option<&VoiceChannel> {
+        self.voice.as_ref()
+    }
+
+    pub fn sms_enabled(&self) -> bool {
+        self.sms.is_some()
+    }
+
+    /// Sends a system SMS such as a verification code, skipping the verified-number check
+    pub async fn send_system_sms(&self, to: &str, body: &str) -> Result<(), DeliveryError> {
+        match &self.sms {
+            Some(sms) => sms.send_to(to, body).await,
+            None => Err(DeliveryError::Permanent("SMS channel is not configured".to_string())),
+        }
+    }
+
+    /// The providers a notification on `method` goes out through for this user, one `send` each
+    pub async fn providers(&self, method: DeliveryMethod, user: &User) -> Result<Vec<Provider>, DeliveryError> {
+        let key = match method {
+            DeliveryMethod::Push => {
+                let providers = self.push.providers(user).await?;
+                return Ok(providers
+                    .into_iter()
+                    .map(|push| Provider { key: push.key(), push: Some(push) })
+                    .collect());
+            }
+            DeliveryMethod::Email => "email",
+            DeliveryMethod::Sms => self.sms.as_ref().map_or("sms", SmsChannel::provider_key),
+            DeliveryMethod::Webhook => "webhook",
+            DeliveryMethod::Slack => "slack",
+            DeliveryMethod::Discord => "discord",
+            DeliveryMethod::Teams => "teams",
+            DeliveryMethod::Ntfy => "ntfy",
+            DeliveryMethod::Gotify => "gotify",
+            DeliveryMethod::Telegram => "telegram",
+            DeliveryMethod::Voice => self.voice.as_ref().map_or("voice", VoiceChannel::provider_key),
+        };
+        Ok(vec![Provider::new(key)])
+    }
+
+    pub async fn send(
+        &self,
+        method: DeliveryMethod,
+        provider: Provider,
+        user: &User,
+        notification: &PendingNotification,
+    ) -> Result<(), DeliveryError> {
+        match method {
+            DeliveryMethod::Email => self.email.send(user, notification).await,
+            DeliveryMethod::Sms => match &self.sms {
+                Some(sms) => sms.send(user, &self.sms_body(notification)?).await,
+                None => self.check(method, user).await,
+            },
+            DeliveryMethod::Push => self.push.send(user, notification, provider.push).await,
+            DeliveryMethod::Webhook => self.webhook.send(user, notification).await,
+            DeliveryMethod::Slack | DeliveryMethod::Discord | DeliveryMethod::Teams => {
+                self.chat.send(method, user, notification).await
+            }
+            DeliveryMethod::Ntfy | DeliveryMethod::Gotify => self.self_hosted.send(method, user, notification).await,
+            DeliveryMethod::Telegram => match &self.telegram {
+                Some(telegram) => telegram.send(user, notification).await,
+                None => self.check(method, user).await,
+            },
+            DeliveryMethod::Voice => match &self.voice {
+                Some(voice) => voice.send(user, notification).await,
+                None => self.check(method, user).await,
+            },
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Keeps requests to user-supplied servers from reaching this deployment's own network:
+//! loopback, private ranges, link-local (including cloud metadata services) and the like.
+
+use std::net::{IpAddr, SocketAddr};
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::Url;
+
+/// Whether `ip` is reachable on the public internet rather than a local or reserved address
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                || a >= 240 // Reserved, and the broadcast address
+                || (a == 100 && (64..128).contains(&b)) // Carrier-grade NAT
+                || (a == 192 && b == 0 && c == 0) // IETF protocol assignments
+                || (a == 198 && (b == 18 || b == 19))) // Benchmarking
+        }
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(v4));
+            }
+            let [first, second, ..] = ip.segments();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                || (first & 0xfe00) == 0xfc00 // Unique local
+                || (first & 0xffc0) == 0xfe80 // Link-local
+                || (first == 0x2001 && second == 0x0db8)) // Documentation
+        }
+    }
+}
+
+/// Rejects a URL whose host is a non-public IP literal; such hosts never reach [`PublicResolver`]
+pub fn check_literal(url: &Url) -> Result<(), String> {
+    let host = url.host_str().ok_or_else(|| "URL has no host".to_string())?;
+    // IPv6 literals keep their brackets in `host_str`
+    let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() else {
+        return Ok(());
+    };
+
+    if is_public(ip) {
+        Ok(())
+    } else {
+        Err(format!("{} is not a public address", ip))
+    }
+}
+
+/// Resolves the URL's host and rejects it unless every address it has is public
+pub async fn check_host(url: &Url) -> Result<(), String> {
+    check_literal(url)?;
+
+    let domain = url.host_str().unwrap_or_default();
+    if domain.starts_with('[') || domain.parse::<IpAddr>().is_ok() {
+        return Ok(());
+    }
+    let addrs = tokio::net::lookup_host((domain, 0))
+        .await
+        .map_err(|_| format!("{} could not be resolved", domain))?;
+
+    let mut resolved = false;
+    for addr in addrs {
+        if !is_public(addr.ip()) {
+            return Err(format!("{} resolves to a non-public address", domain));
+        }
+        resolved = true;
+    }
+
+    if resolved {
+        Ok(())
+    } else {
+        Err(format!("{} could not be resolved", domain))
+    }
+}
+
+/// DNS resolver for HTTP clients that talk to user-supplied servers
+///
+/// Drops non-public addresses at connection time, so a name that passed [`check_host`] cannot
+/// later be pointed at an internal address.
+pub struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", host).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_local_and_reserved_addresses() {
+        let local = [
+            "127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0",
+            "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1",
+        ];
+        for ip in local {
+            assert!(!is_public(ip.parse().unwrap()), "{} should not be public", ip);
+        }
+    }
+
+    #[test]
+    fn accepts_public_addresses() {
+        for ip in ["1.1.1.1", "93.184.216.34", "2606:4700:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{} should be public", ip);
+        }
+    }
+
+    #[test]
+    fn checks_ip_literals_without_resolving() {
+        assert!(check_literal(&Url::parse("https://169.254.169.254/latest").unwrap()).is_err());
+        assert!(check_literal(&Url::parse("https://[::1]:8080/").unwrap()).is_err());
+        assert!(check_literal(&Url::parse("https://ntfy.example.com/").unwrap()).is_ok());
+    }
+}
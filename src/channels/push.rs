@@ -0,0 +1,230 @@
+pub mod apns;
+pub mod fcm;
+pub mod web;
+
+use std::time::Duration;
+
+use log::{error, warn};
+use reqwest::Client;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::channels::DeliveryError;
+use crate::config::Config;
+use crate::db::models::{PendingNotification, Platform, User};
+use crate::services::devices::{self, Device};
+use crate::services::web_push::{self, WebPushSubscription};
+use apns::ApnsSender;
+use fcm::FcmSender;
+use web::WebPushSender;
+
+/// Used when the notification has no title of its own
+const TITLE: &str = "You have a new notification";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Why a push service did not accept a message for one device
+#[derive(Debug)]
+pub enum PushError {
+    /// The token or subscription is unknown, expired or belongs to another app; the device should be
+    /// deactivated or the subscription removed
+    InvalidToken(String),
+    Permanent(String),
+    Transient(String),
+}
+
+/// The services pushes go out through; each has its own rate limit and circuit breaker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushProvider {
+    Fcm,
+    Apns,
+    Web,
+}
+
+impl PushProvider {
+    pub fn key(&self) -> &'static str {
+        match self {
+            PushProvider::Fcm => "push:fcm",
+            PushProvider::Apns => "push:apns",
+            PushProvider::Web => "push:web",
+        }
+    }
+
+    fn of(platform: Platform) -> Self {
+        match platform {
+            Platform::Android => PushProvider::Fcm,
+            Platform::Ios => PushProvider::Apns,
+        }
+    }
+}
+
+/// What is sent to each of the user's devices
+pub struct PushMessage<'a> {
+    pub notification_id: Uuid,
+    pub title: &'a str,
+    pub body: &'a str,
+    pub category: &'a str,
+}
+
+pub struct PushChannel {
+    pool: PgPool,
+    fcm: Option<FcmSender>,
+    apns: Option<ApnsSender>,
+    web: Option<WebPushSender>,
+}
+
+impl PushChannel {
+    pub fn new(config: &Config, pool: PgPool) -> Self {
+        // Push services answer directly; following a redirect could only lead somewhere unintended
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("Failed to build HTTP client");
+
+        PushChannel {
+            pool,
+            fcm: config.fcm.as_ref().map(|fcm| FcmSender::new(fcm, client.clone())),
+            apns: config.apns.as_ref().map(|apns| ApnsSender::new(apns, client.clone())),
+            web: config.web_push.as_ref().map(|web_push| WebPushSender::new(web_push, client)),
+        }
+    }
+
+    /// VAPID public key browsers subscribe with, if Web Push is configured
+    pub fn vapid_public_key(&self) -> Option<&str> {
+        self.web.as_ref().map(|web| web.public_key())
+    }
+
+    fn is_configured(&self, platform: Option<Platform>) -> bool {
+        match platform {
+            Some(Platform::Android) => self.fcm.is_some(),
+            Some(Platform::Ios) => self.apns.is_some(),
+            None => false,
+        }
+    }
+
+    async fn targets(&self, user: &User) -> Result<(Vec<Device>, Vec<WebPushSubscription>), DeliveryError> {
+        let devices = devices::active_devices(&self.pool, user.id)
+            .await
+            .map_err(|e| DeliveryError::Transient(format!("failed to load devices: {}", e)))?;
+        let subscriptions = web_push::list_subscriptions(&self.pool, user.id)
+            .await
+            .map_err(|e| DeliveryError::Transient(format!("failed to load browser subscriptions: {}", e)))?;
+
+        if devices.is_empty() && subscriptions.is_empty() {
+            return Err(DeliveryError::Unreachable("no registered push device".to_string()));
+        }
+        Ok((devices, subscriptions))
+    }
+
+    pub async fn check(&self, user: &User) -> Result<(), DeliveryError> {
+        let (devices, subscriptions) = self.targets(user).await?;
+        if !devices.iter().any(|device| self.is_configured(device.platform()))
+            && (subscriptions.is_empty() || self.web.is_none())
+        {
+            return Err(DeliveryError::Permanent("push is not configured for the user's devices".to_string()));
+        }
+        Ok(())
+    }
+
+    /// The providers the user has active devices or browser subscriptions on
+    pub async fn providers(&self, user: &User) -> Result<Vec<PushProvider>, DeliveryError> {
+        let (devices, subscriptions) = self.targets(user).await?;
+
+        let mut providers = Vec::new();
+        for provider in devices.iter().filter_map(|device| device.platform()).map(PushProvider::of) {
+            if !providers.contains(&provider) {
+                providers.push(provider);
+            }
+        }
+        if !subscriptions.is_empty() {
+            providers.push(PushProvider::Web);
+        }
+        Ok(providers)
+    }
+
+    /// Sends to every active device and browser subscription, or only those `provider` serves;
+    /// delivery counts as successful if any of them accepts it
+    pub async fn send(
+        &self,
+        user: &User,
+        notification: &PendingNotification,
+        provider: Option<PushProvider>,
+    ) -> Result<(), DeliveryError> {
+        let (mut devices, mut subscriptions) = self.targets(user).await?;
+        if let Some(provider) = provider {
+            devices.retain(|device| device.platform().map(PushProvider::of) == Some(provider));
+            if provider != PushProvider::Web {
+                subscriptions.clear();
+            }
+            if devices.is_empty() && subscriptions.is_empty() {
+                return Err(DeliveryError::Unreachable(format!("no registered device for {}", provider.key())));
+            }
+        }
+
+        let message = PushMessage {
+            notification_id: notification.id,
+            title: notification.title.as_deref().unwrap_or(TITLE),
+            body: &notification.content,
+            category: &notification.category,
+        };
+
+        let mut delivered = false;
+        let mut transient = None;
+        let mut errors = Vec::new();
+        let mut invalid = 0;
+
+        for device in &devices {
+            let result = match (device.platform(), &self.fcm, &self.apns) {
+                (Some(Platform::Android), Some(fcm), _) => fcm.send(&device.token, &message).await,
+                (Some(Platform::Ios), _, Some(apns)) => apns.send(&device.token, &message).await,
+                (platform, _, _) => Err(PushError::Permanent(format!(
+                    "push is not configured for {}",
+                    platform.map_or("unknown platform", |platform| platform.as_str())
+                ))),
+            };
+
+            match result {
+                Ok(()) => delivered = true,
+                Err(PushError::InvalidToken(reason)) => {
+                    warn!("Deactivating device {}: {}", device.id, reason);
+                    if let Err(e) = devices::deactivate_device(&self.pool, device.id, &reason).await {
+                        error!("Failed to deactivate device {}: {:?}", device.id, e);
+                    }
+                    invalid += 1;
+                }
+                Err(PushError::Transient(reason)) => transient = Some(reason),
+                Err(PushError::Permanent(reason)) => errors.push(reason),
+            }
+        }
+
+        for subscription in &subscriptions {
+            let result = match &self.web {
+                Some(web) => web.send(&subscription.endpoint, &subscription.p256dh, &subscription.auth, &message).await,
+                None => Err(PushError::Permanent("Web Push is not configured".to_string())),
+            };
+
+            match result {
+                Ok(()) => delivered = true,
+                Err(PushError::InvalidToken(reason)) => {
+                    warn!("Removing browser subscription {}: {}", subscription.id, reason);
+                    if let Err(e) = web_push::prune_subscription(&self.pool, subscription.id).await {
+                        error!("Failed to remove browser subscription {}: {:?}", subscription.id, e);
+                    }
+                    invalid += 1;
+                }
+                Err(PushError::Transient(reason)) => transient = Some(reason),
+                Err(PushError::Permanent(reason)) => errors.push(reason),
+            }
+        }
+
+        if delivered {
+            Ok(())
+        } else if let Some(reason) = transient {
+            Err(DeliveryError::Transient(reason))
+        } else if invalid == devices.len() + subscriptions.len() {
+            Err(DeliveryError::Unreachable("every registered push token was rejected".to_string()))
+        } else {
+            Err(DeliveryError::Permanent(errors.join("; ")))
+        }
+    }
+}
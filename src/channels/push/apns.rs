@@ -0,0 +1,110 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use reqwest::{Client, StatusCode};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use super::{PushError, PushMessage};
+use crate::config::ApnsConfig;
+
+/// APNs rejects provider tokens older than an hour and throttles ones refreshed more often than
+/// every 20 minutes
+const JWT_LIFETIME: Duration = Duration::from_secs(40 * 60);
+
+#[derive(Serialize)]
+struct ProviderClaims<'a> {
+    iss: &'a str,
+    iat: i64,
+}
+
+/// Sends through the APNs HTTP/2 API with token-based (JWT) provider authentication
+pub struct ApnsSender {
+    client: Client,
+    base_url: String,
+    topic: String,
+    key_id: String,
+    team_id: String,
+    key: EncodingKey,
+    jwt: Mutex<Option<(String, Instant)>>,  // Provider token and when to replace it
+}
+
+impl ApnsSender {
+    pub fn new(config: &ApnsConfig, client: Client) -> Self {
+        ApnsSender {
+            client,
+            base_url: config.base_url.trim_end_matches('/').to_string(),
+            topic: config.topic.clone(),
+            key_id: config.key_id.clone(),
+            team_id: config.team_id.clone(),
+            key: EncodingKey::from_ec_pem(config.private_key.as_bytes()).expect("Invalid APNs private key"),
+            jwt: Mutex::new(None),
+        }
+    }
+
+    fn provider_token(&self) -> Result<String, PushError> {
+        let mut cached = self.jwt.lock().unwrap();
+        if let Some((jwt, valid_until)) = cached.as_ref() {
+            if Instant::now() < *valid_until {
+                return Ok(jwt.clone());
+            }
+        }
+
+        let mut header = Header::new(Algorithm::ES256);
+        header.kid = Some(self.key_id.clone());
+        let claims = ProviderClaims {
+            iss: &self.team_id,
+            iat: time::OffsetDateTime::now_utc().unix_timestamp(),
+        };
+        let jwt = encode(&header, &claims, &self.key)
+            .map_err(|e| PushError::Permanent(format!("failed to sign APNs provider token: {}", e)))?;
+
+        *cached = Some((jwt.clone(), Instant::now() + JWT_LIFETIME));
+        Ok(jwt)
+    }
+
+    pub async fn send(&self, token: &str, message: &PushMessage<'_>) -> Result<(), PushError> {
+        let jwt = self.provider_token()?;
+
+        let response = self.client
+            .post(format!("{}/3/device/{}", self.base_url, token))
+            .header("authorization", format!("bearer {}", jwt))
+            .header("apns-topic", &self.topic)
+            .header("apns-push-type", "alert")
+            .header("apns-priority", "10")
+            .json(&json!({
+                "aps": {
+                    "alert": { "title": message.title, "body": message.body },
+                    "sound": "default",
+                },
+                "notification_id": message.notification_id,
+                "category": message.category,
+            }))
+            .send()
+            .await
+            .map_err(|e| PushError::Transient(format!("APNs request failed: {}", e)))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+
+        let body: Value = response.json().await.unwrap_or_default();
+        let apns_reason = body["reason"].as_str().unwrap_or_default().to_string();
+        let reason = format!("APNs returned {}: {}", status, apns_reason);
+
+        match (status, apns_reason.as_str()) {
+            (StatusCode::GONE, _) | (_, "BadDeviceToken") | (_, "DeviceTokenNotForTopic") | (_, "Unregistered") => {
+                Err(PushError::InvalidToken(reason))
+            }
+            (_, "ExpiredProviderToken") | (_, "InvalidProviderToken") => {
+                *self.jwt.lock().unwrap() = None;
+                Err(PushError::Transient(reason))
+            }
+            (StatusCode::TOO_MANY_REQUESTS, _) => Err(PushError::Transient(reason)),
+            (status, _) if status.is_server_error() => Err(PushError::Transient(reason)),
+            _ => Err(PushError::Permanent(reason)),
+        }
+    }
+}
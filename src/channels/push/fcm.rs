@@ -0,0 +1,155 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::{PushError, PushMessage};
+use crate::config::FcmConfig;
+
+const SCOPE: &str = "https://www.googleapis.com/auth/firebase.messaging";
+/// Refresh access tokens this long before Google says they expire
+const TOKEN_MARGIN: Duration = Duration::from_secs(60);
+
+#[derive(Serialize)]
+struct AssertionClaims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: i64,
+    exp: i64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// Sends through the FCM HTTP v1 API
+pub struct FcmSender {
+    client: Client,
+    send_url: String,
+    token_url: String,
+    client_email: String,
+    key: EncodingKey,
+    access_token: Mutex<Option<(String, Instant)>>,  // Token and when to stop using it
+}
+
+impl FcmSender {
+    pub fn new(config: &FcmConfig, client: Client) -> Self {
+        FcmSender {
+            client,
+            send_url: format!(
+                "{}/v1/projects/{}/messages:send",
+                config.base_url.trim_end_matches('/'),
+                config.project_id
+            ),
+            token_url: config.token_url.clone(),
+            client_email: config.client_email.clone(),
+            key: EncodingKey::from_rsa_pem(config.private_key.as_bytes()).expect("Invalid FCM private key"),
+            access_token: Mutex::new(None),
+        }
+    }
+
+    /// An OAuth2 access token, exchanged for a signed service account assertion when needed
+    async fn access_token(&self) -> Result<String, PushError> {
+        if let Some((token, valid_until)) = self.access_token.lock().unwrap().as_ref() {
+            if Instant::now() < *valid_until {
+                return Ok(token.clone());
+            }
+        }
+
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        let claims = AssertionClaims {
+            iss: &self.client_email,
+            scope: SCOPE,
+            aud: &self.token_url,
+            iat: now,
+            exp: now + 3600,
+        };
+        let assertion = encode(&Header::new(Algorithm::RS256), &claims, &self.key)
+            .map_err(|e| PushError::Permanent(format!("failed to sign FCM assertion: {}", e)))?;
+
+        let response = self.client
+            .post(&self.token_url)
+            .form(&[("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"), ("assertion", &assertion)])
+            .send()
+            .await
+            .map_err(|e| PushError::Transient(format!("FCM token request failed: {}", e)))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let reason = format!("FCM token endpoint returned {}: {}", status, body.trim());
+            return Err(if status.is_server_error() {
+                PushError::Transient(reason)
+            } else {
+                PushError::Permanent(reason)
+            });
+        }
+
+        let token: TokenResponse = response
+            .json()
+            .await
+            .map_err(|e| PushError::Transient(format!("invalid FCM token response: {}", e)))?;
+
+        let valid_until = Instant::now() + Duration::from_secs(token.expires_in).saturating_sub(TOKEN_MARGIN);
+        *self.access_token.lock().unwrap() = Some((token.access_token.clone(), valid_until));
+        Ok(token.access_token)
+    }
+
+    pub async fn send(&self, token: &str, message: &PushMessage<'_>) -> Result<(), PushError> {
+        let access_token = self.access_token().await?;
+
+        let response = self.client
+            .post(&self.send_url)
+            .bearer_auth(access_token)
+            .json(&json!({
+                "message": {
+                    "token": token,
+                    "notification": { "title": message.title, "body": message.body },
+                    "data": {
+                        "notification_id": message.notification_id.to_string(),
+                        "category": message.category,
+                    },
+                }
+            }))
+            .send()
+            .await
+            .map_err(|e| PushError::Transient(format!("FCM request failed: {}", e)))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+
+        let body: Value = response.json().await.unwrap_or_default();
+        let error_code = body["error"]["details"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find_map(|detail| detail["errorCode"].as_str())
+            .unwrap_or_default()
+            .to_string();
+        let error_message = body["error"]["message"].as_str().unwrap_or_default();
+        let reason = format!("FCM returned {} {}: {}", status, error_code, error_message);
+
+        match (status, error_code.as_str()) {
+            (_, "UNREGISTERED") | (_, "SENDER_ID_MISMATCH") | (StatusCode::NOT_FOUND, _) => {
+                Err(PushError::InvalidToken(reason))
+            }
+            (_, "INVALID_ARGUMENT") if error_message.contains("registration token") => Err(PushError::InvalidToken(reason)),
+            (StatusCode::UNAUTHORIZED, _) => {
+                // Revoked or expired early; fetch a fresh one on the retry
+                *self.access_token.lock().unwrap() = None;
+                Err(PushError::Transient(reason))
+            }
+            (StatusCode::TOO_MANY_REQUESTS, _) => Err(PushError::Transient(reason)),
+            (status, _) if status.is_server_error() => Err(PushError::Transient(reason)),
+            _ => Err(PushError::Permanent(reason)),
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes128Gcm, Nonce};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hkdf::Hkdf;
+use p256::ecdh::diffie_hellman;
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{PublicKey, SecretKey};
+use rand::rngs::OsRng;
+use reqwest::{Client, StatusCode, Url};
+use serde_json::json;
+use sha2::Sha256;
+
+use super::{PushError, PushMessage};
+use crate::channels::host_matches;
+use crate::config::WebPushConfig;
+
+/// Push services refuse VAPID tokens valid for more than 24 hours
+const JWT_LIFETIME: Duration = Duration::from_secs(12 * 60 * 60);
+/// Replace cached tokens well before they expire so a request never carries a stale one
+const JWT_REFRESH: Duration = Duration::from_secs(11 * 60 * 60);
+/// How long the push service keeps an undelivered message for an offline browser
+const MESSAGE_TTL: u32 = 24 * 60 * 60;
+/// The whole message goes in one aes128gcm record; push services accept at most 4096 bytes
+const RECORD_SIZE: u32 = 4096;
+/// Salt (16) + record size (4) + key id length (1) + sender public key (65)
+const HEADER_LEN: usize = 86;
+/// What is left of a record for the payload after the header, the GCM tag and the padding delimiter
+const MAX_PAYLOAD: usize = RECORD_SIZE as usize - HEADER_LEN - 16 - 1;
+
+/// Decodes the base64url values browsers hand out, with or without padding
+pub fn decode_key(value: &str) -> Option<Vec<u8>> {
+    URL_SAFE_NO_PAD.decode(value.trim().trim_end_matches('=')).ok()
+}
+
+/// Hosts of the push services browsers hand out subscriptions for: Chrome, Firefox, Edge and Safari
+const PUSH_SERVICE_HOSTS: [&str; 4] = [
+    "fcm.googleapis.com",
+    "updates.push.services.mozilla.com",
+    "notify.windows.com",
+    "web.push.apple.com",
+];
+
+/// Whether `url` belongs to a browser push service, so subscriptions cannot point the
+/// dispatcher at arbitrary hosts
+pub fn is_push_service(url: &Url) -> bool {
+    url.scheme() == "https" && url.host_str().is_some_and(|host| host_matches(host, &PUSH_SERVICE_HOSTS))
+}
+
+/// Sends to browser push services (RFC 8030) with VAPID authentication (RFC 8292) and
+/// aes128gcm payload encryption (RFC 8291)
+pub struct WebPushSender {
+    client: Client,
+    subject: String,
+    key: SigningKey,
+    public_key: String,  // Uncompressed application server key, base64url
+    jwts: Mutex<HashMap<String, (String, Instant)>>,  // Token per push service origin and when to replace it
+}
+
+impl WebPushSender {
+    pub fn new(config: &WebPushConfig, client: Client) -> Self {
+        let key = decode_key(&config.private_key)
+            .and_then(|bytes| SigningKey::from_slice(&bytes).ok())
+            .expect("Invalid VAPID private key");
+        let public_key = URL_SAFE_NO_PAD.encode(key.verifying_key().to_encoded_point(false).as_bytes());
+
+        WebPushSender {
+            client,
+            subject: config.subject.clone(),
+            key,
+            public_key,
+            jwts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The applicationServerKey browsers must pass to `pushManager.subscribe()`
+    pub fn public_key(&self) -> &str {
+        &self.public_key
+    }
+
+    fn vapid_token(&self, audience: &str) -> String {
+        let mut cached = self.jwts.lock().unwrap();
+        if let Some((jwt, valid_until)) = cached.get(audience) {
+            if Instant::now() < *valid_until {
+                return jwt.clone();
+            }
+        }
+
+        let header = URL_SAFE_NO_PAD.encode(json!({ "typ": "JWT", "alg": "ES256" }).to_string());
+        let claims = URL_SAFE_NO_PAD.encode(
+            json!({
+                "aud": audience,
+                "exp": time::OffsetDateTime::now_utc().unix_timestamp() + JWT_LIFETIME.as_secs() as i64,
+                "sub": self.subject,
+            })
+            .to_string(),
+        );
+        let signing_input = format!("{}.{}", header, claims);
+        let signature: Signature = self.key.sign(signing_input.as_bytes());
+        let jwt = format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature.to_bytes()));
+
+        cached.insert(audience.to_string(), (jwt.clone(), Instant::now() + JWT_REFRESH));
+        jwt
+    }
+
+    pub async fn send(&self, endpoint: &str, p256dh: &str, auth: &str, message: &PushMessage<'_>) -> Result<(), PushError> {
+        let url = Url::parse(endpoint).map_err(|e| PushError::InvalidToken(format!("invalid endpoint: {}", e)))?;
+        if !is_push_service(&url) {
+            return Err(PushError::InvalidToken("endpoint is not a known push service".to_string()));
+        }
+        let audience = url.origin().ascii_serialization();
+
+        let ua_public = decode_key(p256dh)
+            .and_then(|bytes| PublicKey::from_sec1_bytes(&bytes).ok())
+            .ok_or_else(|| PushError::InvalidToken("invalid p256dh key".to_string()))?;
+        let auth = decode_key(auth)
+            .filter(|bytes| bytes.len() == 16)
+            .ok_or_else(|| PushError::InvalidToken("invalid auth secret".to_string()))?;
+
+        let body = encrypt(&ua_public, &auth, &payload(message))?;
+
+        let response = self.client
+            .post(url)
+            .header("authorization", format!("vapid t={}, k={}", self.vapid_token(&audience), self.public_key))
+            .header("content-encoding", "aes128gcm")
+            .header("content-type", "application/octet-stream")
+            .header("ttl", MESSAGE_TTL.to_string())
+            .header("urgency", "high")
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| PushError::Transient(format!("Web Push request failed: {}", e)))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+
+        let reason = format!("Web Push returned {}: {}", status, response.text().await.unwrap_or_default().trim());
+        match status {
+            StatusCode::NOT_FOUND | StatusCode::GONE => Err(PushError::InvalidToken(reason)),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                self.jwts.lock().unwrap().remove(&audience);
+                Err(PushError::Permanent(reason))
+            }
+            StatusCode::TOO_MANY_REQUESTS => Err(PushError::Transient(reason)),
+            status if status.is_server_error() => Err(PushError::Transient(reason)),
+            _ => Err(PushError::Permanent(reason)),
+        }
+    }
+}
+
+/// JSON handed to the service worker's `push` event, with the body shortened to fit one record
+fn payload(message: &PushMessage<'_>) -> Vec<u8> {
+    let mut body = message.body.to_string();
+    loop {
+        let payload = json!({
+            "title": message.title,
+            "body": body,
+            "notification_id": message.notification_id,
+            "category": message.category,
+        })
+        .to_string();
+
+        if payload.len() <= MAX_PAYLOAD || body.is_empty() {
+            return payload.into_bytes();
+        }
+
+        let mut cut = body.trim_end_matches('…').len().saturating_sub(payload.len() - MAX_PAYLOAD + '…'.len_utf8());
+        while !body.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        body.truncate(cut);
+        if !body.is_empty() {
+            body.push('…');
+        }
+    }
+}
+
+/// Encrypts `plaintext` for one browser as a single aes128gcm record (RFC 8291 section 3.4)
+fn encrypt(ua_public: &PublicKey, auth: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, PushError> {
+    encrypt_with(&SecretKey::random(&mut OsRng), &rand::random(), ua_public, auth, plaintext)
+}
+
+/// `encrypt` with the ephemeral key and salt passed in, so the RFC example can be reproduced
+fn encrypt_with(as_secret: &SecretKey, salt: &[u8; 16], ua_public: &PublicKey, auth: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, PushError> {
+    let as_public = as_secret.public_key().to_encoded_point(false);
+    let shared = diffie_hellman(as_secret.to_nonzero_scalar(), ua_public.as_affine());
+
+    let mut key_info = b"WebPush: info\0".to_vec();
+    key_info.extend_from_slice(ua_public.to_encoded_point(false).as_bytes());
+    key_info.extend_from_slice(as_public.as_bytes());
+    let mut ikm = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(auth), shared.raw_secret_bytes().as_slice())
+        .expand(&key_info, &mut ikm)
+        .map_err(|_| PushError::Permanent("failed to derive Web Push key".to_string()))?;
+
+    let hkdf = Hkdf::<Sha256>::new(Some(salt), &ikm);
+    let mut cek = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    hkdf.expand(b"Content-Encoding: aes128gcm\0", &mut cek)
+        .and_then(|_| hkdf.expand(b"Content-Encoding: nonce\0", &mut nonce))
+        .map_err(|_| PushError::Permanent("failed to derive Web Push key".to_string()))?;
+
+    let mut record = plaintext.to_vec();
+    record.push(0x02);  // Delimiter for the last (and only) record, no padding
+    let ciphertext = Aes128Gcm::new(&cek.into())
+        .encrypt(Nonce::from_slice(&nonce), record.as_slice())
+        .map_err(|_| PushError::Permanent("failed to encrypt Web Push payload".to_string()))?;
+
+    let mut body = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+    body.extend_from_slice(salt);
+    body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+    body.push(as_public.as_bytes().len() as u8);
+    body.extend_from_slice(as_public.as_bytes());
+    body.extend_from_slice(&ciphertext);
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(value: &str) -> Vec<u8> {
+        decode_key(value).unwrap()
+    }
+
+    #[test]
+    fn encrypt_matches_rfc_8291_example() {
+        // Section 5 of RFC 8291
+        let as_secret = SecretKey::from_slice(&key("yfWPiYE-n46HLnH0KqZOF1fJJU3MYrct3AELtAQ-oRw")).unwrap();
+        let ua_public = PublicKey::from_sec1_bytes(&key(
+            "BCVxsr7N_eNgVRqvHtD0zTZsEc6-VV-JvLexhqUzORcxaOzi6-AYWXvTBHm4bjyPjs7Vd8pZGH6SRpkNtoIAiw4",
+        ))
+        .unwrap();
+        let salt: [u8; 16] = key("DGv6ra1nlYgDCS1FRnbzlw").try_into().unwrap();
+        let auth = key("BTBZMqHH6r4Tts7J_aSIgg");
+
+        let body = encrypt_with(&as_secret, &salt, &ua_public, &auth, b"When I grow up, I want to be a watermelon").unwrap();
+
+        assert_eq!(
+            URL_SAFE_NO_PAD.encode(body),
+            "DGv6ra1nlYgDCS1FRnbzlwAAEABBBP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A_yl95bQpu6cVPTpK4Mqgkf1CXztLVBSt2Ks3oZwbuwXPXLWyouBWLVWGNWQexSgSxsj_Qulcy4a-fN"
+        );
+    }
+
+    #[test]
+    fn decode_key_accepts_padding() {
+        assert_eq!(decode_key("BTBZMqHH6r4Tts7J_aSIgg=="), decode_key("BTBZMqHH6r4Tts7J_aSIgg"));
+        assert_eq!(decode_key("not base64!"), None);
+    }
+
+    #[test]
+    fn payload_fits_one_record() {
+        let body = "é".repeat(5000);
+        let message = PushMessage { title: "Title", body: &body, notification_id: uuid::Uuid::nil(), category: "general" };
+        let payload = payload(&message);
+
+        assert!(payload.len() <= MAX_PAYLOAD);
+        let json: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+        assert!(json["body"].as_str().unwrap().ends_with('…'));
+        assert_eq!(json["title"], "Title");
+    }
+
+    #[test]
+    fn short_payload_is_left_alone() {
+        let message = PushMessage { title: "Title", body: "Hello", notification_id: uuid::Uuid::nil(), category: "general" };
+        let json: serde_json::Value = serde_json::from_slice(&payload(&message)).unwrap();
+
+        assert_eq!(json["body"], "Hello");
+    }
+
+    #[test]
+    fn only_https_push_services_are_accepted() {
+        let accepted = |url: &str| is_push_service(&Url::parse(url).unwrap());
+
+        assert!(accepted("https://fcm.googleapis.com/fcm/send/abc"));
+        assert!(accepted("https://updates.push.services.mozilla.com/wpush/v2/abc"));
+        assert!(accepted("https://wns2-by3p.notify.windows.com/w/?token=abc"));
+        assert!(!accepted("http://fcm.googleapis.com/fcm/send/abc"));
+        assert!(!accepted("https://fcm.googleapis.com.evil.example/abc"));
+        assert!(!accepted("https://169.254.169.254/latest/meta-data"));
+    }
+}
@@ -0,0 +1,211 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{error, warn};
+use reqwest::{redirect, Client, StatusCode, Url};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+
+use crate::channels::public_addr::{self, PublicResolver};
+use crate::channels::DeliveryError;
+use crate::db::models::{Category, DeliveryMethod, PendingNotification, Priority, User};
+use crate::services::push_servers::{self, PushServerTarget};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// ntfy turns longer messages into attachments, which servers without an attachment cache reject
+const NTFY_MESSAGE_MAX: usize = 4096;
+/// ntfy shows at most three action buttons
+const NTFY_ACTIONS_MAX: usize = 3;
+
+/// Publishes notifications to the ntfy topics and Gotify applications users configured
+pub struct SelfHostedChannel {
+    pool: PgPool,
+    client: Client,
+}
+
+impl SelfHostedChannel {
+    pub fn new(pool: PgPool) -> Self {
+        SelfHostedChannel {
+            pool,
+            // These servers are whatever users point us at, so only public addresses are dialled
+            // and redirects, which could lead anywhere, are not followed
+            client: Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .redirect(redirect::Policy::none())
+                .dns_resolver(Arc::new(PublicResolver))
+                .build()
+                .expect("Failed to build HTTP client"),
+        }
+    }
+
+    async fn target(&self, method: DeliveryMethod, user: &User) -> Result<PushServerTarget, DeliveryError> {
+        push_servers::active_server(&self.pool, user.id, method)
+            .await
+            .map_err(|e| DeliveryError::Transient(format!("failed to load {} server: {}", method, e)))?
+            .ok_or_else(|| DeliveryError::Unreachable(format!("no active {} server", method)))
+    }
+
+    pub async fn check(&self, method: DeliveryMethod, user: &User) -> Result<(), DeliveryError> {
+        self.target(method, user).await.map(|_| ())
+    }
+
+    pub async fn send(&self, method: DeliveryMethod, user: &User, notification: &PendingNotification) -> Result<(), DeliveryError> {
+        let target = self.target(method, user).await?;
+        let server_url = target.server_url.trim_end_matches('/');
+        if let Err(e) = Url::parse(server_url).map_err(|e| e.to_string()).and_then(|url| public_addr::check_literal(&url)) {
+            return Err(DeliveryError::Unreachable(format!("{} server URL is not allowed: {}", method, e)));
+        }
+
+        let mut body = payload(method, notification);
+        let request = match method {
+            // JSON messages go to the server root and name their topic in the body
+            DeliveryMethod::Ntfy => {
+                body["topic"] = json!(target.topic);
+                let request = self.client.post(server_url);
+                match &target.token {
+                    Some(token) => request.bearer_auth(token),
+                    None => request,
+                }
+            }
+            _ => self.client
+                .post(format!("{}/message", server_url))
+                .header("X-Gotify-Key", target.token.as_deref().unwrap_or_default()),
+        };
+
+        let response = request
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| DeliveryError::Transient(format!("{} request failed: {}", method, e)))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+
+        // Only the status is kept: the reason is shown to the server's owner, and the body is
+        // whatever the host they chose answered
+        let reason = format!("{} returned {}", method, status);
+        match status {
+            // The token was revoked or the topic or application deleted; the owner has to fix the settings
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::NOT_FOUND => {
+                warn!("Deactivating {} server {}: {}", method, target.id, reason);
+                if let Err(e) = push_servers::deactivate_server(&self.pool, target.id, &reason).await {
+                    error!("Failed to deactivate {} server {}: {:?}", method, target.id, e);
+                }
+                Err(DeliveryError::Unreachable(reason))
+            }
+            StatusCode::TOO_MANY_REQUESTS => Err(DeliveryError::Transient(reason)),
+            status if status.is_server_error() => Err(DeliveryError::Transient(reason)),
+            _ => Err(DeliveryError::Permanent(reason)),
+        }
+    }
+}
+
+/// The request body for `method`, which must be Ntfy or Gotify; ntfy's topic is added when sending
+pub fn payload(method: DeliveryMethod, notification: &PendingNotification) -> Value {
+    match method {
+        DeliveryMethod::Ntfy => ntfy_payload(notification),
+        _ => gotify_payload(notification),
+    }
+}
+
+/// ntfy priorities run from 1 (min) to 5 (max, which bypasses Do Not Disturb on Android)
+fn ntfy_priority(priority: Priority) -> u8 {
+    match priority {
+        Priority::Low => 2,
+        Priority::Normal => 3,
+        Priority::High => 4,
+        Priority::Critical => 5,
+    }
+}
+
+/// Gotify priorities run from 0 to 10; clients raise a heads-up notification from 8
+fn gotify_priority(priority: Priority) -> u8 {
+    match priority {
+        Priority::Low => 2,
+        Priority::Normal => 5,
+        Priority::High => 7,
+        Priority::Critical => 10,
+    }
+}
+
+/// ntfy shows tags that are emoji short codes as emoji in front of the title, and the rest below it
+fn ntfy_tags(notification: &PendingNotification) -> Vec<&'static str> {
+    let mut tags = Vec::new();
+    match notification.priority() {
+        Priority::Critical => tags.push("rotating_light"),
+        Priority::High => tags.push("warning"),
+        _ => {}
+    }
+    if notification.category() == Category::Security {
+        tags.push("lock");
+    }
+    tags.push(match notification.category() {
+        Category::General => "general",
+        Category::Marketing => "marketing",
+        Category::Reminders => "reminders",
+        Category::Security => "security",
+    });
+    tags
+}
+
+/// JSON publish message: tags, a click URL from the first action and a view button per action
+fn ntfy_payload(notification: &PendingNotification) -> Value {
+    let mut message = notification.content.clone();
+    if message.len() > NTFY_MESSAGE_MAX {
+        let mut end = NTFY_MESSAGE_MAX - '…'.len_utf8();
+        while !message.is_char_boundary(end) {
+            end -= 1;
+        }
+        message = format!("{}…", &message[..end]);
+    }
+
+    let mut body = json!({
+        "message": message,
+        "priority": ntfy_priority(notification.priority()),
+        "tags": ntfy_tags(notification),
+    });
+    if let Some(title) = &notification.title {
+        body["title"] = json!(title);
+    }
+
+    let actions = notification.actions();
+    if let Some(first) = actions.first() {
+        body["click"] = json!(first.url);
+        let buttons: Vec<Value> = actions
+            .iter()
+            .take(NTFY_ACTIONS_MAX)
+            .map(|action| json!({ "action": "view", "label": action.label, "url": action.url }))
+            .collect();
+        body["actions"] = json!(buttons);
+    }
+    body
+}
+
+/// Gotify message; it has no buttons, so actions are listed as links and the first opens on click
+fn gotify_payload(notification: &PendingNotification) -> Value {
+    let actions = notification.actions();
+
+    let mut message = notification.content.clone();
+    if !actions.is_empty() {
+        let links: Vec<String> = actions
+            .iter()
+            .map(|action| format!("{}: {}", action.label, action.url))
+            .collect();
+        message = format!("{}\n\n{}", message, links.join("\n"));
+    }
+
+    let mut extras = json!({ "client::display": { "contentType": "text/plain" } });
+    if let Some(first) = actions.first() {
+        extras["client::notification"] = json!({ "click": { "url": first.url } });
+    }
+
+    json!({
+        // Gotify would otherwise title the message with the application name
+        "title": notification.title.clone().unwrap_or_else(|| notification.category.clone()),
+        "message": message,
+        "priority": gotify_priority(notification.priority()),
+        "extras": extras,
+    })
+}
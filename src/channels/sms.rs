@@ -0,0 +1,374 @@
+pub mod provider;
+
+use sqlx::PgPool;
+
+use crate::channels::DeliveryError;
+use crate::config::SmsConfig;
+use crate::db::models::{SmsOverflow, User};
+use crate::services::phone;
+use provider::SmsProvider;
+
+/// Septets (GSM-7) or UTF-16 code units (UCS-2) per segment, for a single-part message and for
+/// each part of a concatenated one
+const GSM_SINGLE: usize = 160;
+const GSM_MULTIPART: usize = 153;
+const UNICODE_SINGLE: usize = 70;
+const UNICODE_MULTIPART: usize = 67;
+/// How many characters `truncate` will give up to avoid cutting a word in half
+const WORD_BREAK_SLACK: usize = 15;
+
+/// GSM 03.38 default alphabet, one septet each
+const GSM_BASIC: &str = "@£$¥èéùìòÇ\nØø\rÅåΔ_ΦΓΛΩΠΨΣΘΞÆæßÉ !\"#¤%&'()*+,-./0123456789:;<=>?\
+                         ¡ABCDEFGHIJKLMNOPQRSTUVWXYZÄÖÑÜ§¿abcdefghijklmnopqrstuvwxyzäöñüà";
+/// GSM 03.38 extension table, sent as an escape plus the character
+const GSM_EXTENDED: &str = "\u{c}^{}\\[~]|€";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gsm7,
+    Ucs2,
+}
+
+impl Encoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Gsm7 => "GSM-7",
+            Encoding::Ucs2 => "UCS-2",
+        }
+    }
+
+    /// How much of a segment `c` takes up
+    fn width(&self, c: char) -> usize {
+        match self {
+            Encoding::Gsm7 if GSM_EXTENDED.contains(c) => 2,
+            Encoding::Gsm7 => 1,
+            Encoding::Ucs2 => c.len_utf16(),
+        }
+    }
+
+    fn limits(&self) -> (usize, usize) {
+        match self {
+            Encoding::Gsm7 => (GSM_SINGLE, GSM_MULTIPART),
+            Encoding::Ucs2 => (UNICODE_SINGLE, UNICODE_MULTIPART),
+        }
+    }
+
+    /// The most a message of `max_segments` parts can hold
+    fn capacity(&self, max_segments: usize) -> usize {
+        let (single, multipart) = self.limits();
+        single.max(multipart * max_segments)
+    }
+}
+
+fn is_gsm(c: char) -> bool {
+    GSM_BASIC.contains(c) || GSM_EXTENDED.contains(c)
+}
+
+/// A single character outside the GSM alphabet forces the whole message into UCS-2
+pub fn encoding(body: &str) -> Encoding {
+    if body.chars().all(is_gsm) {
+        Encoding::Gsm7
+    } else {
+        Encoding::Ucs2
+    }
+}
+
+/// Splits an SMS body into the segments carriers will bill for
+///
+/// Escaped GSM characters and UTF-16 surrogate pairs are never split across segments.
+pub fn segments(body: &str) -> Vec<String> {
+    let encoding = encoding(body);
+    let (single, multipart) = encoding.limits();
+
+    let length: usize = body.chars().map(|c| encoding.width(c)).sum();
+    if length <= single {
+        return vec![body.to_string()];
+    }
+
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut used = 0;
+    for c in body.chars() {
+        let width = encoding.width(c);
+        if used + width > multipart {
+            parts.push(std::mem::take(&mut part));
+            used = 0;
+        }
+        part.push(c);
+        used += width;
+    }
+    parts.push(part);
+    parts
+}
+
+/// Swaps typographic punctuation, accents and common emoji for GSM-7 lookalikes
+///
+/// Returns `None` when something is left that has no GSM-7 equivalent (e.g. non-Latin scripts),
+/// since a half-transliterated message would still be sent as UCS-2.
+pub fn transliterate(body: &str) -> Option<String> {
+    let mut result = String::with_capacity(body.len());
+
+    for c in body.chars() {
+        if is_gsm(c) {
+            result.push(c);
+            continue;
+        }
+
+        let replacement = match c {
+            '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}' | '\u{2032}' | '`' | '\u{b4}' => "'",
+            '\u{201c}' | '\u{201d}' | '\u{201e}' | '\u{201f}' | '\u{2033}' | '\u{ab}' | '\u{bb}' => "\"",
+            '\u{2010}'..='\u{2015}' | '\u{2212}' => "-",
+            '\u{2026}' => "...",
+            '\u{a0}' | '\u{2000}'..='\u{200a}' | '\u{202f}' | '\u{205f}' | '\t' => " ",
+            '\u{200b}'..='\u{200d}' | '\u{2060}' | '\u{feff}' | '\u{fe0f}' | '\u{fe0e}' => "",
+            '\u{2022}' | '\u{b7}' => "*",
+            '\u{2122}' => "TM",
+            '\u{a9}' => "(c)",
+            '\u{ae}' => "(R)",
+            '\u{b0}' => " deg",
+            'á' | 'â' | 'ã' | 'ā' | 'ą' => "a",
+            'Á' | 'À' | 'Â' | 'Ã' | 'Ā' | 'Ą' => "A",
+            'ç' | 'ć' | 'č' => "c",
+            'Ć' | 'Č' => "C",
+            'ê' | 'ë' | 'ē' | 'ę' | 'ě' => "e",
+            'È' | 'Ê' | 'Ë' | 'Ē' | 'Ę' | 'Ě' => "E",
+            'í' | 'î' | 'ï' | 'ī' => "i",
+            'Í' | 'Ì' | 'Î' | 'Ï' | 'Ī' => "I",
+            'ó' | 'ô' | 'õ' | 'ō' | 'ő' => "o",
+            'Ó' | 'Ò' | 'Ô' | 'Õ' | 'Ō' | 'Ő' => "O",
+            'ú' | 'û' | 'ū' | 'ű' | 'ů' => "u",
+            'Ú' | 'Ù' | 'Û' | 'Ū' | 'Ű' | 'Ů' => "U",
+            'ý' | 'ÿ' => "y",
+            'Ý' | 'Ÿ' => "Y",
+            'ł' => "l",
+            'Ł' => "L",
+            'ń' | 'ň' => "n",
+            'ś' | 'š' => "s",
+            'Ś' | 'Š' => "S",
+            'ź' | 'ż' | 'ž' => "z",
+            'Ź' | 'Ż' | 'Ž' => "Z",
+            '\u{1f642}' | '\u{1f60a}' | '\u{263a}' => ":)",
+            '\u{1f600}' | '\u{1f603}' | '\u{1f604}' | '\u{1f601}' | '\u{1f606}' => ":D",
+            '\u{1f609}' => ";)",
+            '\u{1f641}' | '\u{2639}' | '\u{1f61e}' | '\u{1f622}' => ":(",
+            '\u{1f44d}' => "(y)",
+            '\u{2764}' | '\u{1f499}' | '\u{1f49a}' | '\u{1f49b}' | '\u{1f49c}' => "<3",
+            // Any other emoji or pictograph is dropped
+            '\u{1f000}'..='\u{1faff}' | '\u{2600}'..='\u{27bf}' | '\u{2b00}'..='\u{2bff}' => "",
+            _ => return None,
+        };
+        result.push_str(replacement);
+    }
+
+    // Dropped emoji often leave a dangling space at the end
+    Some(result.trim_end().to_string())
+}
+
+/// Shortens `body` to fit in `max_segments`, ending with an ellipsis
+pub fn truncate(body: &str, max_segments: usize) -> String {
+    let encoding = encoding(body);
+    let capacity = encoding.capacity(max_segments);
+
+    let length: usize = body.chars().map(|c| encoding.width(c)).sum();
+    if length <= capacity {
+        return body.to_string();
+    }
+
+    // "…" is not in the GSM alphabet, and adding it would double the cost of a GSM-7 message
+    let ellipsis = match encoding {
+        Encoding::Gsm7 => "...",
+        Encoding::Ucs2 => "\u{2026}",
+    };
+    let budget = capacity - ellipsis.chars().map(|c| encoding.width(c)).sum::<usize>();
+
+    let mut result = String::new();
+    let mut used = 0;
+    for c in body.chars() {
+        used += encoding.width(c);
+        if used > budget {
+            break;
+        }
+        result.push(c);
+    }
+
+    // Prefer ending on a whole word unless that throws away too much
+    if let Some(space) = result.rfind(char::is_whitespace) {
+        if result[space..].chars().count() <= WORD_BREAK_SLACK {
+            result.truncate(space);
+        }
+    }
+
+    let mut result = result.trim_end().to_string();
+    result.push_str(ellipsis);
+    result
+}
+
+/// The segment budget for one notification and what to do when the body exceeds it
+#[derive(Debug, Clone, Copy)]
+pub struct SmsPolicy {
+    pub max_segments: i32,
+    pub overflow: SmsOverflow,
+}
+
+impl SmsPolicy {
+    /// Turns a notification body into the text that will actually be sent
+    pub fn apply(&self, body: &str) -> Result<String, String> {
+        let max_segments = self.max_segments.max(1) as usize;
+
+        let body = match self.overflow {
+            // Transliterating a message that already fits still saves money when it drops UCS-2
+            SmsOverflow::Transliterate => transliterate(body).unwrap_or_else(|| body.to_string()),
+            _ => body.to_string(),
+        };
+
+        let count = segments(&body).len();
+        if count <= max_segments {
+            return Ok(body);
+        }
+
+        match self.overflow {
+            SmsOverflow::Reject => Err(format!(
+                "SMS needs {} segments ({}), more than the maximum of {}",
+                count,
+                encoding(&body).as_str(),
+                max_segments
+            )),
+            SmsOverflow::Truncate | SmsOverflow::Transliterate => Ok(truncate(&body, max_segments)),
+        }
+    }
+}
+
+/// The user's phone number, if they have verified it and it can receive SMS
+pub fn verified_number(user: &User) -> Result<&str, DeliveryError> {
+    if user.phone_number_type.as_deref().is_some_and(phone::is_landline) {
+        return Err(DeliveryError::Unreachable("phone number is a landline".to_string()));
+    }
+
+    match user.phone_number.as_deref() {
+        Some(number) if user.phone_verified.unwrap_or(false) => Ok(number),
+        _ => Err(DeliveryError::Unreachable("no verified phone number".to_string())),
+    }
+}
+
+pub struct SmsChannel {
+    provider: Box<dyn SmsProvider>,
+    from: String,
+}
+
+impl SmsChannel {
+    pub fn new(config: &SmsConfig, pool: PgPool) -> Self {
+        SmsChannel {
+            provider: provider::from_config(&config.provider, pool),
+            from: config.from.clone(),
+        }
+    }
+
+    pub fn provider_key(&self) -> &'static str {
+        self.provider.key()
+    }
+
+    /// Sends `body`, which should already have had the notification's `SmsPolicy` applied
+    pub async fn send(&self, user: &User, body: &str) -> Result<(), DeliveryError> {
+        let to = verified_number(user)?;
+        self.send_to(to, body).await
+    }
+
+    /// Sends to any number, verified or not; only for system messages such as verification codes
+    pub async fn send_to(&self, to: &str, body: &str) -> Result<(), DeliveryError> {
+        self.provider.send(&self.from, to, body).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gsm_messages_split_at_153_septets_once_over_160() {
+        assert_eq!(segments(&"a".repeat(160)).len(), 1);
+
+        let parts = segments(&"a".repeat(161));
+        assert_eq!(parts.iter().map(|part| part.len()).collect::<Vec<_>>(), vec![153, 8]);
+    }
+
+    #[test]
+    fn extended_characters_take_two_septets_and_are_not_split() {
+        assert_eq!(encoding("Price: 5€ [incl. VAT]"), Encoding::Gsm7);
+        assert_eq!(segments(&"€".repeat(80)).len(), 1);
+
+        // 153 septets hold 76 escaped characters; the 77th would straddle the boundary
+        let parts = segments(&"€".repeat(81));
+        assert_eq!(parts.iter().map(|part| part.chars().count()).collect::<Vec<_>>(), vec![76, 5]);
+    }
+
+    #[test]
+    fn non_gsm_characters_switch_to_ucs2_limits() {
+        assert_eq!(encoding("Привет"), Encoding::Ucs2);
+        assert_eq!(segments(&"ж".repeat(70)).len(), 1);
+
+        let parts = segments(&"ж".repeat(71));
+        assert_eq!(parts.iter().map(|part| part.chars().count()).collect::<Vec<_>>(), vec![67, 4]);
+    }
+
+    #[test]
+    fn surrogate_pairs_count_twice_and_stay_together() {
+        assert_eq!(segments(&"😀".repeat(35)).len(), 1);
+
+        let parts = segments(&"😀".repeat(36));
+        assert_eq!(parts.iter().map(|part| part.chars().count()).collect::<Vec<_>>(), vec![33, 3]);
+    }
+
+    #[test]
+    fn truncate_leaves_short_messages_alone() {
+        assert_eq!(truncate("Your code is 123456", 1), "Your code is 123456");
+    }
+
+    #[test]
+    fn truncate_fits_the_budget_and_ends_on_a_word() {
+        let body = "word ".repeat(50);
+        let truncated = truncate(&body, 1);
+
+        assert!(truncated.ends_with("word..."), "{}", truncated);
+        assert!(truncated.len() <= GSM_SINGLE);
+        assert_eq!(segments(&truncated).len(), 1);
+
+        let truncated = truncate(&"word ".repeat(100), 2);
+        assert!(truncated.len() <= 2 * GSM_MULTIPART);
+        assert_eq!(segments(&truncated).len(), 2);
+    }
+
+    #[test]
+    fn truncate_uses_a_unicode_ellipsis_for_ucs2() {
+        let truncated = truncate(&"ж".repeat(100), 1);
+
+        assert_eq!(truncated.chars().count(), UNICODE_SINGLE);
+        assert!(truncated.ends_with('\u{2026}'));
+    }
+
+    #[test]
+    fn transliterate_replaces_typography_and_emoji() {
+        assert_eq!(
+            transliterate("\u{201c}Hello\u{201d} \u{2013} it\u{2019}s 5€\u{2026}").as_deref(),
+            Some("\"Hello\" - it's 5€...")
+        );
+        assert_eq!(transliterate("Great job \u{1f44d}").as_deref(), Some("Great job (y)"));
+        assert_eq!(transliterate("Party time \u{1f389}").as_deref(), Some("Party time"));
+        assert_eq!(transliterate("Zażółć").as_deref(), Some("Zazolc"));
+    }
+
+    #[test]
+    fn transliterate_gives_up_on_other_scripts() {
+        assert_eq!(transliterate("Привет"), None);
+    }
+
+    #[test]
+    fn policy_rejects_or_truncates_long_messages() {
+        let body = "a".repeat(200);
+
+        let reject = SmsPolicy { max_segments: 1, overflow: SmsOverflow::Reject };
+        assert!(reject.apply(&body).is_err());
+
+        let truncate = SmsPolicy { max_segments: 1, overflow: SmsOverflow::Truncate };
+        assert_eq!(segments(&truncate.apply(&body).unwrap()).len(), 1);
+    }
+}
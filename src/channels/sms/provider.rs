@@ -0,0 +1,175 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use log::warn;
+use reqwest::{Client, Response, StatusCode};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::channels::{provider_error_code, DeliveryError};
+use crate::config::SmsProviderConfig;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An API that can deliver a text message to a phone number
+#[async_trait]
+pub trait SmsProvider: Send + Sync {
+    /// Rate limit and circuit breaker key, e.g. `sms:twilio`
+    fn key(&self) -> &'static str;
+
+    async fn send(&self, from: &str, to: &str, body: &str) -> Result<(), DeliveryError>;
+}
+
+pub fn from_config(config: &SmsProviderConfig, pool: PgPool) -> Box<dyn SmsProvider> {
+    let client = Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("Failed to build HTTP client");
+
+    match config {
+        SmsProviderConfig::Twilio { base_url, account_sid, auth_token } => Box::new(TwilioProvider {
+            client,
+            url: format!("{}/2010-04-01/Accounts/{}/Messages.json", base_url.trim_end_matches('/'), account_sid),
+            account_sid: account_sid.clone(),
+            auth_token: auth_token.clone(),
+        }),
+        SmsProviderConfig::Json { url, api_key } => Box::new(JsonProvider {
+            client,
+            url: url.clone(),
+            api_key: api_key.clone(),
+        }),
+        SmsProviderConfig::Mock => Box::new(MockProvider { pool }),
+    }
+}
+
+/// Sends through Twilio's Messages API with a form-encoded POST and basic auth
+struct TwilioProvider {
+    client: Client,
+    url: String,
+    account_sid: String,
+    auth_token: String,
+}
+
+#[async_trait]
+impl SmsProvider for TwilioProvider {
+    fn key(&self) -> &'static str {
+        "sms:twilio"
+    }
+
+    async fn send(&self, from: &str, to: &str, body: &str) -> Result<(), DeliveryError> {
+        let response = self.client
+            .post(&self.url)
+            .basic_auth(&self.account_sid, Some(&self.auth_token))
+            .form(&[("From", from), ("To", to), ("Body", body)])
+            .send()
+            .await;
+
+        check_response(response).await
+    }
+}
+
+/// Sends `{"to", "from", "body"}` to any HTTP API that accepts it
+struct JsonProvider {
+    client: Client,
+    url: String,
+    api_key: Option<String>,
+}
+
+#[async_trait]
+impl SmsProvider for JsonProvider {
+    fn key(&self) -> &'static str {
+        "sms:json"
+    }
+
+    async fn send(&self, from: &str, to: &str, body: &str) -> Result<(), DeliveryError> {
+        let mut request = self.client
+            .post(&self.url)
+            .json(&json!({ "to": to, "from": from, "body": body }));
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        check_response(request.send().await).await
+    }
+}
+
+/// Rate limiting and server errors are worth retrying; any other rejection is final
+async fn check_response(response: Result<Response, reqwest::Error>) -> Result<(), DeliveryError> {
+    let response = response.map_err(|e| DeliveryError::Transient(format!("SMS provider request failed: {}", e)))?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+
+    let body = response.text().await.unwrap_or_default();
+    warn!("SMS provider returned {}: {}", status, body.trim());
+    let reason = match provider_error_code(&body) {
+        Some(code) => format!("SMS provider returned {} (error code {})", status, code),
+        None => format!("SMS provider returned {}", status),
+    };
+    if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+        Err(DeliveryError::Transient(reason))
+    } else {
+        Err(DeliveryError::Permanent(reason))
+    }
+}
+
+/// Records messages in `mock_sms_messages` instead of sending them
+struct MockProvider {
+    pool: PgPool,
+}
+
+#[async_trait]
+impl SmsProvider for MockProvider {
+    fn key(&self) -> &'static str {
+        "sms:mock"
+    }
+
+    async fn send(&self, from: &str, to: &str, body: &str) -> Result<(), DeliveryError> {
+        sqlx::query!(
+            "INSERT INTO mock_sms_messages (to_number, from_number, body) VALUES ($1, $2, $3)",
+            to,
+            from,
+            body
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DeliveryError::Transient(format!("failed to record mock SMS: {}", e)))?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MockSmsMessage {
+    pub id: Uuid,
+    pub to_number: String,
+    pub from_number: String,
+    pub body: String,
+    pub created_at: OffsetDateTime,
+}
+
+/// Messages recorded by the mock provider, newest first
+pub async fn list_mock_messages(pool: &PgPool, to: Option<&str>, limit: i64) -> Result<Vec<MockSmsMessage>, sqlx::Error> {
+    sqlx::query_as!(
+        MockSmsMessage,
+        "SELECT id, to_number, from_number, body, created_at
+         FROM mock_sms_messages
+         WHERE $1::text IS NULL OR to_number = $1
+         ORDER BY created_at DESC
+         LIMIT $2",
+        to,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn clear_mock_messages(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!("DELETE FROM mock_sms_messages").execute(pool).await?;
+    Ok(result.rows_affected())
+}
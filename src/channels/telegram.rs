@@ -0,0 +1,175 @@
+use std::time::Duration;
+
+use log::{error, warn};
+use reqwest::Client;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+
+use crate::channels::DeliveryError;
+use crate::config::TelegramConfig;
+use crate::db::models::{PendingNotification, User};
+use crate::services::telegram;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest message text sendMessage accepts, counted after entity parsing
+const MESSAGE_MAX: usize = 4096;
+
+/// Characters MarkdownV2 reserves outside of entities
+const MARKDOWN_RESERVED: &[char] = &[
+    '_', '*', '[', ']', '(', ')', '~', '`', '>', '#', '+', '-', '=', '|', '{', '}', '.', '!', '\\',
+];
+
+/// Escapes text for use in a MarkdownV2 message
+pub fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if MARKDOWN_RESERVED.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// What went wrong calling the Bot API
+enum ApiError {
+    /// The chat is gone or blocked the bot; it should be unlinked
+    ChatUnavailable(String),
+    Permanent(String),
+    Transient(String),
+}
+
+impl ApiError {
+    fn into_reason(self) -> String {
+        match self {
+            ApiError::ChatUnavailable(reason) | ApiError::Permanent(reason) | ApiError::Transient(reason) => reason,
+        }
+    }
+}
+
+/// Sends notifications through a Telegram bot to the chats users linked
+pub struct TelegramChannel {
+    pool: PgPool,
+    client: Client,
+    api_url: String,
+    bot_token: String,
+    bot_username: String,
+}
+
+impl TelegramChannel {
+    pub fn new(config: &TelegramConfig, pool: PgPool) -> Self {
+        TelegramChannel {
+            pool,
+            client: Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("Failed to build HTTP client"),
+            api_url: config.api_url.trim_end_matches('/').to_string(),
+            bot_token: config.bot_token.clone(),
+            bot_username: config.bot_username.clone(),
+        }
+    }
+
+    /// The t.me link that opens the bot and sends it `/start <token>`
+    pub fn deep_link(&self, token: &str) -> String {
+        format!("https://t.me/{}?start={}", self.bot_username, token)
+    }
+
+    async fn call(&self, method: &str, body: &Value) -> Result<(), ApiError> {
+        let response = self.client
+            .post(format!("{}/bot{}/{}", self.api_url, self.bot_token, method))
+            .json(body)
+            .send()
+            .await
+            // The request URL carries the bot token, so it must not end up in the reason
+            .map_err(|e| ApiError::Transient(format!("Telegram {} request failed: {}", method, e.without_url())))?;
+
+        let status = response.status();
+        let reply: Value = response.json().await.unwrap_or_default();
+        if reply["ok"].as_bool() == Some(true) {
+            return Ok(());
+        }
+
+        let description = reply["description"].as_str().unwrap_or_default();
+        let reason = format!("Telegram {} returned {}: {}", method, status, description);
+        match status.as_u16() {
+            403 => Err(ApiError::ChatUnavailable(reason)),
+            400 if description.contains("chat not found") => Err(ApiError::ChatUnavailable(reason)),
+            429 => Err(ApiError::Transient(reason)),
+            code if code >= 500 => Err(ApiError::Transient(reason)),
+            _ => Err(ApiError::Permanent(reason)),
+        }
+    }
+
+    /// Points the bot's updates at our webhook endpoint
+    pub async fn set_webhook(&self, url: &str, secret: &str) -> Result<(), String> {
+        let body = json!({ "url": url, "secret_token": secret, "allowed_updates": ["message"] });
+        self.call("setWebhook", &body).await.map_err(ApiError::into_reason)
+    }
+
+    /// Replies to a chat in plain text, e.g. to confirm linking
+    pub async fn reply(&self, chat_id: i64, text: &str) {
+        if let Err(e) = self.call("sendMessage", &json!({ "chat_id": chat_id, "text": text })).await {
+            warn!("Failed to reply to Telegram chat {}: {}", chat_id, e.into_reason());
+        }
+    }
+
+    async fn chat_id(&self, user: &User) -> Result<i64, DeliveryError> {
+        telegram::get_account(&self.pool, user.id)
+            .await
+            .map_err(|e| DeliveryError::Transient(format!("failed to load Telegram account: {}", e)))?
+            .map(|account| account.chat_id)
+            .ok_or_else(|| DeliveryError::Unreachable("no linked Telegram account".to_string()))
+    }
+
+    pub async fn check(&self, user: &User) -> Result<(), DeliveryError> {
+        self.chat_id(user).await.map(|_| ())
+    }
+
+    pub async fn send(&self, user: &User, notification: &PendingNotification) -> Result<(), DeliveryError> {
+        let chat_id = self.chat_id(user).await?;
+
+        let mut body = message(notification);
+        body["chat_id"] = json!(chat_id);
+
+        match self.call("sendMessage", &body).await {
+            Ok(()) => Ok(()),
+            Err(ApiError::ChatUnavailable(reason)) => {
+                warn!("Unlinking Telegram chat {}: {}", chat_id, reason);
+                if let Err(e) = telegram::unlink_chat(&self.pool, chat_id).await {
+                    error!("Failed to unlink Telegram chat {}: {:?}", chat_id, e);
+                }
+                Err(DeliveryError::Unreachable(reason))
+            }
+            Err(ApiError::Permanent(reason)) => Err(DeliveryError::Permanent(reason)),
+            Err(ApiError::Transient(reason)) => Err(DeliveryError::Transient(reason)),
+        }
+    }
+}
+
+/// The sendMessage parameters for a notification, without the chat: a bold title over the
+/// content in MarkdownV2, and one inline URL button per action
+pub fn message(notification: &PendingNotification) -> Value {
+    // The limit applies to the text as displayed, so escapes do not count
+    let room = MESSAGE_MAX.saturating_sub(notification.title.as_ref().map_or(0, |title| title.chars().count() + 2));
+    let mut content = notification.content.clone();
+    if content.chars().count() > room {
+        content = content.chars().take(room.saturating_sub(1)).collect::<String>() + "…";
+    }
+
+    let text = match &notification.title {
+        Some(title) => format!("*{}*\n\n{}", escape_markdown(title), escape_markdown(&content)),
+        None => escape_markdown(&content),
+    };
+
+    let mut message = json!({ "text": text, "parse_mode": "MarkdownV2" });
+    if !notification.actions().is_empty() {
+        let keyboard: Vec<Value> = notification
+            .actions()
+            .iter()
+            .map(|action| json!([{ "text": action.label, "url": action.url }]))
+            .collect();
+        message["reply_markup"] = json!({ "inline_keyboard": keyboard });
+    }
+    message
+}
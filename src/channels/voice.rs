@@ -0,0 +1,112 @@
+pub mod provider;
+
+use crate::auth::acknowledge;
+use crate::channels::DeliveryError;
+use crate::config::{Config, VoiceConfig};
+use crate::db::models::{PendingNotification, Priority, User};
+use provider::VoiceProvider;
+
+/// Most characters read out per call; also keeps the inline TwiML under the 4000 characters
+/// providers accept once escaped
+const SPOKEN_MAX: usize = 600;
+/// Seconds to wait for a keypress after the prompt
+const GATHER_TIMEOUT: u32 = 10;
+
+/// Escapes text for use in TwiML element content and attribute values
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Calls can go to any verified number, landlines included
+pub fn verified_number(user: &User) -> Result<&str, DeliveryError> {
+    match user.phone_number.as_deref() {
+        Some(number) if user.phone_verified.unwrap_or(false) => Ok(number),
+        _ => Err(DeliveryError::Unreachable("no verified phone number".to_string())),
+    }
+}
+
+/// Phones users and reads critical notifications out with text-to-speech
+pub struct VoiceChannel {
+    provider: Box<dyn VoiceProvider>,
+    from: String,
+    tts_voice: Option<String>,
+    language: String,
+    public_url: String,
+    token_secret: String,
+}
+
+impl VoiceChannel {
+    pub fn new(config: &Config, voice: &VoiceConfig) -> Self {
+        VoiceChannel {
+            provider: provider::from_config(&voice.provider),
+            from: voice.from.clone(),
+            tts_voice: voice.tts_voice.clone(),
+            language: voice.language.clone(),
+            public_url: config.public_url.trim_end_matches('/').to_string(),
+            token_secret: config.jwt_secret.clone(),
+        }
+    }
+
+    /// A `<Say>` verb in the configured voice and language
+    fn say(&self, text: &str, repeat: u32) -> String {
+        let voice = match &self.tts_voice {
+            Some(voice) => format!(" voice=\"{}\"", xml_escape(voice)),
+            None => String::new(),
+        };
+        let repeat = if repeat > 1 { format!(" loop=\"{}\"", repeat) } else { String::new() };
+        format!("<Say{} language=\"{}\"{}>{}</Say>", voice, xml_escape(&self.language), repeat, xml_escape(text))
+    }
+
+    /// The TwiML run once the call is answered
+    ///
+    /// With acknowledgement on, the message is read inside a `<Gather>` whose keypress is posted
+    /// to the acknowledge callback; otherwise it is simply read twice.
+    pub fn render(&self, notification: &PendingNotification) -> Result<String, DeliveryError> {
+        if notification.priority() != Priority::Critical {
+            return Err(DeliveryError::Unreachable("voice calls are only placed for critical notifications".to_string()));
+        }
+
+        let mut text = match &notification.title {
+            Some(title) => format!("{}. {}", title.trim_end_matches(['.', '!', '?', ':']), notification.content),
+            None => notification.content.clone(),
+        };
+        if text.chars().count() > SPOKEN_MAX {
+            text = text.chars().take(SPOKEN_MAX).collect();
+        }
+
+        if !notification.voice_acknowledge {
+            return Ok(format!("<Response>{}</Response>", self.say(&text, 2)));
+        }
+
+        let token = acknowledge::sign_token(&self.token_secret, notification.id)
+            .map_err(|e| DeliveryError::Permanent(format!("failed to sign acknowledge callback: {}", e)))?;
+        let action = format!("{}/api/inbound/voice/{}", self.public_url, token);
+
+        Ok(format!(
+            "<Response><Gather numDigits=\"1\" timeout=\"{}\" method=\"POST\" action=\"{}\">{}{}</Gather>{}</Response>",
+            GATHER_TIMEOUT,
+            xml_escape(&action),
+            self.say(&text, 1),
+            self.say("Press 1 to acknowledge.", 1),
+            self.say("No response received. Goodbye.", 1),
+        ))
+    }
+
+    /// The TwiML answering the acknowledge callback
+    pub fn reply(&self, text: &str) -> String {
+        format!("<Response>{}<Hangup/></Response>", self.say(text, 1))
+    }
+
+    pub fn provider_key(&self) -> &'static str {
+        self.provider.key()
+    }
+
+    pub async fn send(&self, user: &User, notification: &PendingNotification) -> Result<(), DeliveryError> {
+        let twiml = self.render(notification)?;
+        let to = verified_number(user)?;
+        self.provider.call(&self.from, to, &twiml).await
+    }
+}
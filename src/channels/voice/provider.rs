@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use log::warn;
+use reqwest::{Client, StatusCode};
+
+use crate::channels::{provider_error_code, DeliveryError};
+use crate::config::VoiceProviderConfig;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An API that can call a phone number and run TwiML once it is answered
+#[async_trait]
+pub trait VoiceProvider: Send + Sync {
+    /// Rate limit and circuit breaker key, e.g. `voice:twilio`
+    fn key(&self) -> &'static str;
+
+    async fn call(&self, from: &str, to: &str, twiml: &str) -> Result<(), DeliveryError>;
+}
+
+pub fn from_config(config: &VoiceProviderConfig) -> Box<dyn VoiceProvider> {
+    let client = Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("Failed to build HTTP client");
+
+    match config {
+        VoiceProviderConfig::Twilio { base_url, account_sid, auth_token } => Box::new(TwilioProvider {
+            client,
+            url: format!("{}/2010-04-01/Accounts/{}/Calls.json", base_url.trim_end_matches('/'), account_sid),
+            account_sid: account_sid.clone(),
+            auth_token: auth_token.clone(),
+        }),
+    }
+}
+
+/// Places calls through Twilio's Calls API, passing the TwiML inline rather than as a URL
+struct TwilioProvider {
+    client: Client,
+    url: String,
+    account_sid: String,
+    auth_token: String,
+}
+
+#[async_trait]
+impl VoiceProvider for TwilioProvider {
+    fn key(&self) -> &'static str {
+        "voice:twilio"
+    }
+
+    async fn call(&self, from: &str, to: &str, twiml: &str) -> Result<(), DeliveryError> {
+        let response = self.client
+            .post(&self.url)
+            .basic_auth(&self.account_sid, Some(&self.auth_token))
+            .form(&[("From", from), ("To", to), ("Twiml", twiml)])
+            .send()
+            .await
+            .map_err(|e| DeliveryError::Transient(format!("voice provider request failed: {}", e)))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+
+        // Rate limiting and server errors are worth retrying; any other rejection is final
+        let body = response.text().await.unwrap_or_default();
+        warn!("Voice provider returned {}: {}", status, body.trim());
+        let reason = match provider_error_code(&body) {
+            Some(code) => format!("voice provider returned {} (error code {})", status, code),
+            None => format!("voice provider returned {}", status),
+        };
+        if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+            Err(DeliveryError::Transient(reason))
+        } else {
+            Err(DeliveryError::Permanent(reason))
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{error, warn};
+use notismart_backend::{sign, DELIVERY_HEADER, SIGNATURE_HEADER};
+use reqwest::{redirect, Client, Url};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use time::OffsetDateTime;
+
+use crate::channels::public_addr::{self, PublicResolver};
+use crate::channels::DeliveryError;
+use crate::config::Config;
+use crate::db::models::{PendingNotification, User};
+use crate::services::outbox;
+use crate::services::webhooks::{self, WebhookTarget};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const USER_AGENT: &str = "NotiSmart-Webhooks/1.0";
+
+/// POSTs notifications as signed JSON to the endpoints users registered
+pub struct WebhookChannel {
+    pool: PgPool,
+    client: Client,
+    failure_limit: i32,
+}
+
+impl WebhookChannel {
+    pub fn new(config: &Config, pool: PgPool) -> Self {
+        // A redirect is a misconfigured endpoint, not a reason to send the payload somewhere else,
+        // and endpoints are user-supplied, so only public addresses are dialled
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .redirect(redirect::Policy::none())
+            .dns_resolver(Arc::new(PublicResolver))
+            .user_agent(USER_AGENT)
+            .build()
+            .expect("Failed to build HTTP client");
+
+        WebhookChannel {
+            pool,
+            client,
+            failure_limit: config.webhook_failure_limit,
+        }
+    }
+
+    pub async fn check(&self, user: &User) -> Result<(), DeliveryError> {
+        let targets = webhooks::active_targets(&self.pool, user.id)
+            .await
+            .map_err(|e| DeliveryError::Transient(format!("failed to load webhook endpoints: {}", e)))?;
+
+        if targets.is_empty() {
+            return Err(DeliveryError::Unreachable("no active webhook endpoint".to_string()));
+        }
+        Ok(())
+    }
+
+    /// The JSON body every endpoint receives
+    pub fn payload(notification: &PendingNotification) -> Value {
+        json!({
+            "type": "notification",
+            "id": notification.id,
+            "user_id": notification.user_id,
+            "category": notification.category,
+            "content": notification.content,
+            "html_content": notification.html_content,
+        })
+    }
+
+    /// Sends to every active endpoint that has not accepted the notification yet
+    ///
+    /// Delivery only succeeds once every endpoint has; until then the failed ones are retried,
+    /// while those that already got it are left alone.
+    pub async fn send(&self, user: &User, notification: &PendingNotification) -> Result<(), DeliveryError> {
+        let targets = webhooks::undelivered_targets(&self.pool, user.id, notification.id)
+            .await
+            .map_err(|e| DeliveryError::Transient(format!("failed to load webhook endpoints: {}", e)))?;
+
+        if targets.is_empty() {
+            // Either every endpoint got it on an earlier attempt, or there is none
+            return self.check(user).await;
+        }
+
+        let body = Self::payload(notification).to_string();
+        let mut errors = Vec::new();
+
+        for target in &targets {
+            match self.post(target, notification, &body).await {
+                Ok(()) => {
+                    if let Err(e) = webhooks::record_success(&self.pool, target.id, notification.id).await {
+                        error!("Failed to record webhook success for endpoint {}: {:?}", target.id, e);
+                    }
+                }
+                Err(reason) => {
+                    if let Err(e) = self.record_failure(user, target, &reason).await {
+                        error!("Failed to record webhook failure for endpoint {}: {:?}", target.id, e);
+                    }
+                    errors.push(reason);
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            // Any non-2xx is worth retrying; endpoints that never recover get disabled instead
+            Err(DeliveryError::Transient(errors.join("; ")))
+        }
+    }
+
+    async fn post(&self, target: &WebhookTarget, notification: &PendingNotification, body: &str) -> Result<(), String> {
+        let url = Url::parse(&target.url).map_err(|e| format!("webhook URL {} is invalid: {}", target.url, e))?;
+        public_addr::check_literal(&url).map_err(|e| format!("webhook URL {} is not allowed: {}", target.url, e))?;
+        let timestamp = OffsetDateTime::now_utc().unix_timestamp();
+
+        let response = self.client
+            .post(url)
+            .header("content-type", "application/json")
+            .header(SIGNATURE_HEADER, sign(&target.secret, timestamp, body.as_bytes()))
+            .header(DELIVERY_HEADER, notification.id.to_string())
+            .body(body.to_string())
+            .send()
+            .await
+            .map_err(|e| format!("webhook request to {} failed: {}", target.url, e))?;
+
+        let status = response.status();
+        if status.is_success() {
+            Ok(())
+        } else {
+            Err(format!("webhook {} returned {}", target.url, status))
+        }
+    }
+
+    /// Counts the failure and, if that disables the endpoint, emails the owner in the same transaction
+    async fn record_failure(&self, user: &User, target: &WebhookTarget, reason: &str) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let failure = webhooks::record_failure(&mut *tx, target.id, reason, self.failure_limit).await?;
+        if let Some(failure) = failure.filter(|failure| failure.disabled) {
+            warn!("Disabled webhook endpoint {} after {} consecutive failures", target.id, failure.consecutive_failures);
+            let body = format!(
+                "We stopped sending notifications to your webhook endpoint\n\n    {}\n\n\
+                 after {} deliveries in a row failed. The last error was:\n\n    {}\n\n\
+                 Once the endpoint is fixed, re-enable it with POST /api/me/webhooks/{}/enable.",
+                failure.url, failure.consecutive_failures, reason, target.id
+            );
+            outbox::enqueue(&mut *tx, &user.email, "Your webhook endpoint was disabled", &body).await?;
+        }
+
+        tx.commit().await
+    }
+}
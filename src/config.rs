@@ -1,5 +1,10 @@
 use dotenv::dotenv;
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::env;
+use std::fs;
+
+use crate::db::models::{Category, DeliveryMethod, SmsOverflow};
 
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -9,6 +14,109 @@ pub struct Config {
     pub smtp_password: String,
     pub smtp_server: String,
     pub smtp_port: u16,
+    pub smtp_pool_size: u32,  // Maximum open SMTP connections shared by the API and the dispatcher
+    pub public_url: String,  // Base URL used in links we send out, e.g. unsubscribe links
+    pub inbound_webhook_secret: Option<String>,  // Shared secret email providers send with bounce webhooks
+    pub tracking_categories: Vec<Category>,  // Categories whose HTML emails get open/click tracking
+    pub rate_limits: HashMap<String, RateLimit>,  // Keyed by channel, e.g. "Email"
+    pub breaker_failure_threshold: i32,  // Consecutive transient failures that open a channel's circuit breaker
+    pub breaker_cooldown_secs: i64,  // How long an open breaker blocks attempts
+    pub sms: Option<SmsConfig>,  // None leaves the SMS channel disabled
+    pub sms_max_segments: i32,  // Default segment budget per SMS
+    pub sms_overflow: SmsOverflow,  // Default handling of SMS bodies over the budget
+    pub default_phone_region: Option<phonenumber::country::Id>,  // Region for phone numbers given without a +country code
+    pub fcm: Option<FcmConfig>,  // None leaves Android push disabled
+    pub apns: Option<ApnsConfig>,  // None leaves iOS push disabled
+    pub web_push: Option<WebPushConfig>,  // None leaves browser push disabled
+    pub webhook_failure_limit: i32,  // Consecutive failed deliveries after which a webhook endpoint is disabled
+    pub webhook_allow_http: bool,  // Accept plain http:// webhook URLs, for local development only
+    pub telegram: Option<TelegramConfig>,  // None leaves the Telegram channel disabled
+    pub voice: Option<VoiceConfig>,  // None leaves the Voice channel disabled
+}
+
+/// Which voice API to place calls through, chosen with VOICE_PROVIDER
+#[derive(Debug, Clone)]
+pub enum VoiceProviderConfig {
+    /// Twilio's Calls API with inline TwiML, or anything that accepts the same form POST
+    Twilio { base_url: String, account_sid: String, auth_token: String },
+}
+
+/// Voice calls that read critical notifications out with text-to-speech
+#[derive(Debug, Clone)]
+pub struct VoiceConfig {
+    pub provider: VoiceProviderConfig,
+    pub from: String,  // Caller ID, a number owned by the provider account
+    pub tts_voice: Option<String>,  // Provider voice name, e.g. Polly.Joanna; the provider default if unset
+    pub language: String,  // BCP 47 tag the text is read in
+}
+
+/// The Telegram bot notifications are sent from and users link their accounts with
+#[derive(Debug, Clone)]
+pub struct TelegramConfig {
+    pub api_url: String,  // Bot API base, overridable to point at a stand-in
+    pub bot_token: String,
+    pub bot_username: String,  // Used to build t.me deep links
+    pub webhook_secret: String,  // Telegram sends it back in X-Telegram-Bot-Api-Secret-Token with every update
+}
+
+/// VAPID (RFC 8292) identity used to sign Web Push requests
+#[derive(Debug, Clone)]
+pub struct WebPushConfig {
+    pub private_key: String,  // Raw 32-byte P-256 private key, base64url
+    pub subject: String,  // mailto: or https: contact for push services
+}
+
+/// Firebase Cloud Messaging HTTP v1, authenticated with a service account
+#[derive(Debug, Clone)]
+pub struct FcmConfig {
+    pub base_url: String,
+    pub token_url: String,  // OAuth2 endpoint the service account JWT is exchanged at
+    pub project_id: String,
+    pub client_email: String,
+    pub private_key: String,  // RSA key in PEM form
+}
+
+/// Apple Push Notification service, authenticated with a provider token (JWT)
+#[derive(Debug, Clone)]
+pub struct ApnsConfig {
+    pub base_url: String,
+    pub key_id: String,
+    pub team_id: String,
+    pub topic: String,  // The app's bundle ID
+    pub private_key: String,  // Contents of the .p8 key file
+}
+
+/// The fields of a Google service account key file we use
+#[derive(Deserialize)]
+struct ServiceAccount {
+    project_id: String,
+    client_email: String,
+    private_key: String,
+    token_uri: String,
+}
+
+/// Which SMS API to send through, chosen with SMS_PROVIDER
+#[derive(Debug, Clone)]
+pub enum SmsProviderConfig {
+    /// Twilio's Messages API, or anything that accepts the same form POST
+    Twilio { base_url: String, account_sid: String, auth_token: String },
+    /// POSTs `{"to", "from", "body"}` as JSON, with an optional bearer token
+    Json { url: String, api_key: Option<String> },
+    /// Records messages in the database instead of sending them
+    Mock,
+}
+
+#[derive(Debug, Clone)]
+pub struct SmsConfig {
+    pub provider: SmsProviderConfig,
+    pub from: String,  // Sender number or alphanumeric sender ID
+}
+
+/// Token bucket settings: refills at `per_second` up to `burst` tokens
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub per_second: f64,
+    pub burst: f64,
 }
 
 pub fn load_config() -> Config {
@@ -21,5 +129,148 @@ pub fn load_config() -> Config {
         smtp_password: env::var("SMTP_PASSWORD").expect("SMTP_PASSWORD must be set"),
         smtp_server: env::var("SMTP_SERVER").expect("SMTP_SERVER must be set"),
         smtp_port: env::var("SMTP_PORT").expect("SMTP_PORT must be set").parse().expect("Invalid SMTP_PORT"),
+        smtp_pool_size: env::var("SMTP_POOL_SIZE")
+            .map(|v| v.parse().expect("Invalid SMTP_POOL_SIZE"))
+            .unwrap_or(10),
+        public_url: env::var("PUBLIC_URL").unwrap_or_else(|_| "http://127.0.0.1:8080".to_string()),
+        inbound_webhook_secret: env::var("INBOUND_WEBHOOK_SECRET").ok(),
+        tracking_categories: parse_tracking_categories(&env::var("EMAIL_TRACKING_CATEGORIES").unwrap_or_default()),
+        rate_limits: parse_rate_limits(&env::var("RATE_LIMITS").unwrap_or_default()),
+        breaker_failure_threshold: env::var("BREAKER_FAILURE_THRESHOLD")
+            .map(|v| v.parse().expect("Invalid BREAKER_FAILURE_THRESHOLD"))
+            .unwrap_or(5),
+        breaker_cooldown_secs: env::var("BREAKER_COOLDOWN_SECS")
+            .map(|v| v.parse().expect("Invalid BREAKER_COOLDOWN_SECS"))
+            .unwrap_or(60),
+        sms: load_sms_config(),
+        sms_max_segments: env::var("SMS_MAX_SEGMENTS")
+            .map(|v| v.parse().ok().filter(|max| *max > 0).expect("Invalid SMS_MAX_SEGMENTS"))
+            .unwrap_or(3),
+        sms_overflow: env::var("SMS_OVERFLOW")
+            .map(|v| v.parse().expect("Invalid SMS_OVERFLOW"))
+            .unwrap_or(SmsOverflow::Transliterate),
+        default_phone_region: env::var("DEFAULT_PHONE_REGION")
+            .ok()
+            .map(|v| v.to_ascii_uppercase().parse().expect("Invalid DEFAULT_PHONE_REGION")),
+        fcm: load_fcm_config(),
+        apns: load_apns_config(),
+        web_push: env::var("VAPID_PRIVATE_KEY").ok().map(|private_key| WebPushConfig {
+            private_key,
+            subject: env::var("VAPID_SUBJECT").expect("VAPID_SUBJECT must be set"),
+        }),
+        webhook_failure_limit: env::var("WEBHOOK_FAILURE_LIMIT")
+            .map(|v| v.parse().ok().filter(|limit| *limit > 0).expect("Invalid WEBHOOK_FAILURE_LIMIT"))
+            .unwrap_or(20),
+        webhook_allow_http: env::var("WEBHOOK_ALLOW_HTTP").is_ok_and(|v| v == "true" || v == "1"),
+        telegram: env::var("TELEGRAM_BOT_TOKEN").ok().map(|bot_token| TelegramConfig {
+            api_url: env::var("TELEGRAM_API_URL").unwrap_or_else(|_| "https://api.telegram.org".to_string()),
+            bot_token,
+            bot_username: env::var("TELEGRAM_BOT_USERNAME").expect("TELEGRAM_BOT_USERNAME must be set"),
+            webhook_secret: env::var("TELEGRAM_WEBHOOK_SECRET").expect("TELEGRAM_WEBHOOK_SECRET must be set"),
+        }),
+        voice: load_voice_config(),
     }
 }
+
+fn load_fcm_config() -> Option<FcmConfig> {
+    let path = env::var("FCM_SERVICE_ACCOUNT_FILE").ok()?;
+    let file = fs::read_to_string(&path).expect("Failed to read FCM_SERVICE_ACCOUNT_FILE");
+    let account: ServiceAccount = serde_json::from_str(&file).expect("Invalid FCM service account file");
+
+    Some(FcmConfig {
+        base_url: env::var("FCM_BASE_URL").unwrap_or_else(|_| "https://fcm.googleapis.com".to_string()),
+        token_url: env::var("FCM_TOKEN_URL").unwrap_or(account.token_uri),
+        project_id: account.project_id,
+        client_email: account.client_email,
+        private_key: account.private_key,
+    })
+}
+
+fn load_apns_config() -> Option<ApnsConfig> {
+    let path = env::var("APNS_KEY_FILE").ok()?;
+
+    Some(ApnsConfig {
+        base_url: env::var("APNS_BASE_URL").unwrap_or_else(|_| "https://api.push.apple.com".to_string()),
+        key_id: env::var("APNS_KEY_ID").expect("APNS_KEY_ID must be set"),
+        team_id: env::var("APNS_TEAM_ID").expect("APNS_TEAM_ID must be set"),
+        topic: env::var("APNS_TOPIC").expect("APNS_TOPIC must be set"),
+        private_key: fs::read_to_string(&path).expect("Failed to read APNS_KEY_FILE"),
+    })
+}
+
+fn load_sms_config() -> Option<SmsConfig> {
+    let provider = match env::var("SMS_PROVIDER").ok()?.as_str() {
+        "twilio" => SmsProviderConfig::Twilio {
+            base_url: env::var("SMS_API_URL").unwrap_or_else(|_| "https://api.twilio.com".to_string()),
+            account_sid: env::var("TWILIO_ACCOUNT_SID").expect("TWILIO_ACCOUNT_SID must be set"),
+            auth_token: env::var("TWILIO_AUTH_TOKEN").expect("TWILIO_AUTH_TOKEN must be set"),
+        },
+        "json" => SmsProviderConfig::Json {
+            url: env::var("SMS_API_URL").expect("SMS_API_URL must be set"),
+            api_key: env::var("SMS_API_KEY").ok(),
+        },
+        "mock" => SmsProviderConfig::Mock,
+        other => panic!("Invalid SMS_PROVIDER: {}", other),
+    };
+
+    Some(SmsConfig {
+        provider,
+        from: env::var("SMS_FROM").expect("SMS_FROM must be set"),
+    })
+}
+
+fn load_voice_config() -> Option<VoiceConfig> {
+    let provider = match env::var("VOICE_PROVIDER").ok()?.as_str() {
+        "twilio" => VoiceProviderConfig::Twilio {
+            base_url: env::var("VOICE_API_URL").unwrap_or_else(|_| "https://api.twilio.com".to_string()),
+            account_sid: env::var("TWILIO_ACCOUNT_SID").expect("TWILIO_ACCOUNT_SID must be set"),
+            auth_token: env::var("TWILIO_AUTH_TOKEN").expect("TWILIO_AUTH_TOKEN must be set"),
+        },
+        other => panic!("Invalid VOICE_PROVIDER: {}", other),
+    };
+
+    Some(VoiceConfig {
+        provider,
+        from: env::var("VOICE_FROM").expect("VOICE_FROM must be set"),
+        tts_voice: env::var("VOICE_TTS_VOICE").ok(),
+        language: env::var("VOICE_LANGUAGE").unwrap_or_else(|_| "en-US".to_string()),
+    })
+}
+
+/// Comma-separated list such as `Marketing,Reminders`; security emails are never tracked
+fn parse_tracking_categories(value: &str) -> Vec<Category> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| name.parse::<Category>().expect("Invalid EMAIL_TRACKING_CATEGORIES"))
+        .filter(|category| category.is_optional())
+        .collect()
+}
+
+/// Comma-separated `channel=count/unit` pairs such as `Email=10/s,SMS=100/min`.
+///
+/// Up to `count` messages can go out at once through each of the channel's providers, after which
+/// they are spread evenly over the unit.
+fn parse_rate_limits(value: &str) -> HashMap<String, RateLimit> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (channel, limit) = entry.split_once('=').expect("Invalid RATE_LIMITS entry");
+            let (count, unit) = limit.split_once('/').expect("Invalid RATE_LIMITS entry");
+            let count: f64 = count.trim().parse().expect("Invalid RATE_LIMITS count");
+            let seconds = match unit.trim() {
+                "s" | "sec" | "second" => 1.0,
+                "m" | "min" | "minute" => 60.0,
+                "h" | "hour" => 3600.0,
+                other => panic!("Invalid RATE_LIMITS unit: {}", other),
+            };
+            assert!(count >= 1.0, "RATE_LIMITS counts must be at least 1");
+            let channel = channel.trim().parse::<DeliveryMethod>().expect("Invalid RATE_LIMITS channel");
+
+            (channel.as_str().to_string(), RateLimit { per_second: count / seconds, burst: count })
+        })
+        .collect()
+}
@@ -1,13 +1,326 @@
+
+use std::fmt;
+use std::str::FromStr;
+
 use uuid::Uuid;
 use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
 use time::OffsetDateTime;
 use utoipa::ToSchema;
 
+/// Channels a notification can be delivered through, stored as TEXT in the database
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+pub enum DeliveryMethod {
+    Email,
+    #[serde(rename = "SMS")]
+    Sms,
+    Push,
+    Webhook,
+    Slack,
+    Discord,
+    Teams,
+    Telegram,
+    Voice,
+    Ntfy,
+    Gotify,
+}
+
+impl DeliveryMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryMethod::Email => "Email",
+            DeliveryMethod::Sms => "SMS",
+            DeliveryMethod::Push => "Push",
+            DeliveryMethod::Webhook => "Webhook",
+            DeliveryMethod::Slack => "Slack",
+            DeliveryMethod::Discord => "Discord",
+            DeliveryMethod::Teams => "Teams",
+            DeliveryMethod::Telegram => "Telegram",
+            DeliveryMethod::Voice => "Voice",
+            DeliveryMethod::Ntfy => "Ntfy",
+            DeliveryMethod::Gotify => "Gotify",
+        }
+    }
+
+    /// Chat services notifications are posted to through an incoming-webhook URL
+    pub fn is_chat(&self) -> bool {
+        matches!(self, DeliveryMethod::Slack | DeliveryMethod::Discord | DeliveryMethod::Teams)
+    }
+
+    /// Self-hosted push servers notifications are published to with a user-supplied URL and token
+    pub fn is_self_hosted(&self) -> bool {
+        matches!(self, DeliveryMethod::Ntfy | DeliveryMethod::Gotify)
+    }
+
+    /// Whether every delivery goes through one provider, so its failures should count towards the
+    /// channel's circuit breaker; webhook endpoints and self-hosted servers belong to individual
+    /// users and fail on their own
+    pub fn uses_shared_provider(&self) -> bool {
+        *self != DeliveryMethod::Webhook && !self.is_self_hosted()
+    }
+}
+
+impl fmt::Display for DeliveryMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DeliveryMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Email" => Ok(DeliveryMethod::Email),
+            "SMS" => Ok(DeliveryMethod::Sms),
+            "Push" => Ok(DeliveryMethod::Push),
+            "Webhook" => Ok(DeliveryMethod::Webhook),
+            "Slack" => Ok(DeliveryMethod::Slack),
+            "Discord" => Ok(DeliveryMethod::Discord),
+            "Teams" => Ok(DeliveryMethod::Teams),
+            "Telegram" => Ok(DeliveryMethod::Telegram),
+            "Voice" => Ok(DeliveryMethod::Voice),
+            "Ntfy" => Ok(DeliveryMethod::Ntfy),
+            "Gotify" => Ok(DeliveryMethod::Gotify),
+            other => Err(format!("Unknown delivery method: {}", other)),
+        }
+    }
+}
+
+/// Classes of notification users can opt out of; security notices are always delivered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+pub enum Category {
+    General,
+    Marketing,
+    Reminders,
+    Security,
+}
+
+impl Category {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Category::General => "General",
+            Category::Marketing => "Marketing",
+            Category::Reminders => "Reminders",
+            Category::Security => "Security",
+        }
+    }
+
+    pub fn is_optional(&self) -> bool {
+        *self != Category::Security
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Category {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "General" => Ok(Category::General),
+            "Marketing" => Ok(Category::Marketing),
+            "Reminders" => Ok(Category::Reminders),
+            "Security" => Ok(Category::Security),
+            other => Err(format!("Unknown category: {}", other)),
+        }
+    }
+}
+
+/// How urgent a notification is, stored as TEXT
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    /// Must not be missed; the only priority voice calls are placed for
+    Critical,
+}
+
+impl Priority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Low => "Low",
+            Priority::Normal => "Normal",
+            Priority::High => "High",
+            Priority::Critical => "Critical",
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Priority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Low" => Ok(Priority::Low),
+            "Normal" => Ok(Priority::Normal),
+            "High" => Ok(Priority::High),
+            "Critical" => Ok(Priority::Critical),
+            other => Err(format!("Unknown priority: {}", other)),
+        }
+    }
+}
+
+/// Mobile platforms a push token can belong to, stored as TEXT
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum Platform {
+    Android,
+    #[serde(rename = "iOS")]
+    Ios,
+}
+
+impl Platform {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Platform::Android => "Android",
+            Platform::Ios => "iOS",
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Android" => Ok(Platform::Android),
+            "iOS" => Ok(Platform::Ios),
+            other => Err(format!("Unknown platform: {}", other)),
+        }
+    }
+}
+
+/// What to do with an SMS body that needs more segments than allowed, stored as TEXT
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum SmsOverflow {
+    /// Cut the text short and end it with an ellipsis
+    Truncate,
+    /// Replace smart quotes, dashes and emoji with GSM-7 equivalents, then truncate if still too long
+    Transliterate,
+    /// Refuse to create the notification
+    Reject,
+}
+
+impl SmsOverflow {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SmsOverflow::Truncate => "Truncate",
+            SmsOverflow::Transliterate => "Transliterate",
+            SmsOverflow::Reject => "Reject",
+        }
+    }
+}
+
+impl fmt::Display for SmsOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for SmsOverflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Truncate" => Ok(SmsOverflow::Truncate),
+            "Transliterate" => Ok(SmsOverflow::Transliterate),
+            "Reject" => Ok(SmsOverflow::Reject),
+            other => Err(format!("Unknown SMS overflow policy: {}", other)),
+        }
+    }
+}
+
+/// A link button shown with a notification by channels that support them
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct NotificationAction {
+    pub label: String,
+    pub url: String,
+}
+
 #[derive(Serialize, Deserialize, Clone, ToSchema)]
 pub struct Notification {
     pub user_id: Uuid,
     pub content: String,
+    pub html_content: Option<String>,  // Email only; plain `content` is the fallback part
+    pub title: Option<String>,  // Headline for push and chat
+    pub actions: Option<Vec<NotificationAction>>,  // Link buttons for chat
     pub send_at: Option<OffsetDateTime>,
+    pub channels: Option<Vec<DeliveryMethod>>,  // Ordered fallback chain, overrides the user's preferences
+    pub category: Category,
+    pub priority: Priority,
+    pub voice_acknowledge: bool,  // Ask the callee to press 1 to acknowledge a voice call
+    pub template: Option<String>,  // Key of the template or copy this was rendered from, for analytics
+    pub experiment_id: Option<String>,
+    pub variant: Option<String>,  // A/B variant of the template that was sent
+    pub sms_max_segments: Option<i32>,  // Overrides SMS_MAX_SEGMENTS
+    pub sms_overflow: Option<SmsOverflow>,  // Overrides SMS_OVERFLOW
+}
+
+/// A due notification picked up by the dispatcher
+#[derive(Debug, Clone)]
+pub struct PendingNotification {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub content: String,
+    pub html_content: Option<String>,
+    pub title: Option<String>,
+    pub actions: Option<Json<Vec<NotificationAction>>>,
+    pub channels: Option<Vec<String>>,
+    pub category: String,
+    pub priority: String,
+    pub voice_acknowledge: bool,
+    pub attempts: i32,
+    pub sms_max_segments: Option<i32>,
+    pub sms_overflow: Option<String>,
+}
+
+impl PendingNotification {
+    pub fn category(&self) -> Category {
+        self.category.parse().unwrap_or(Category::General)
+    }
+
+    pub fn priority(&self) -> Priority {
+        self.priority.parse().unwrap_or_default()
+    }
+
+    pub fn sms_overflow(&self) -> Option<SmsOverflow> {
+        self.sms_overflow.as_deref().and_then(|overflow| overflow.parse().ok())
+    }
+
+    pub fn actions(&self) -> &[NotificationAction] {
+        self.actions.as_ref().map_or(&[], |actions| actions.as_slice())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+pub struct UserPreferences {
+    pub preferred_method: DeliveryMethod,
+    pub fallback_chain: Option<Vec<DeliveryMethod>>,
+}
+
+/// A user's opt-out from a category, on one channel or on all of them when `channel` is `None`
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+pub struct OptOut {
+    pub category: Category,
+    pub channel: Option<DeliveryMethod>,
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -21,4 +334,6 @@ pub struct User {
     pub phone_verified: Option<bool>,
     pub phone_verification_code: Option<String>,
     pub created_at: Option<OffsetDateTime>,
+    pub phone_country_code: Option<i32>,
+    pub phone_number_type: Option<String>,  // Mobile, FixedLine, ... as detected when the number was saved
 }
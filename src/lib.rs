@@ -0,0 +1,149 @@
+//! Helpers for services that receive NotiSmart's outbound webhooks.
+//!
+//! Every webhook request carries a `X-Notismart-Signature: t=<unix seconds>,v1=<hex>` header,
+//! where the hex value is HMAC-SHA256 over `"<t>.<raw request body>"` keyed with the endpoint's
+//! secret. Receivers should check it with [`verify_signature`] before trusting the body, and use
+//! the `X-Notismart-Delivery` header to drop retried deliveries they have already processed.
+
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+pub const SIGNATURE_HEADER: &str = "X-Notismart-Signature";
+pub const DELIVERY_HEADER: &str = "X-Notismart-Delivery";
+
+/// How old a signature [`verify_signature`] callers should usually accept, to limit replays
+pub const DEFAULT_TOLERANCE: Duration = Duration::from_secs(5 * 60);
+
+/// Why a webhook request failed verification
+#[derive(Debug, PartialEq, Eq)]
+pub enum SignatureError {
+    /// The header is missing its `t=` or `v1=` part, or they are not valid
+    Malformed,
+    /// The signature was made more than the allowed tolerance ago (or in the future)
+    Expired,
+    /// No `v1=` signature matches the body and secret
+    Mismatch,
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureError::Malformed => f.write_str("malformed signature header"),
+            SignatureError::Expired => f.write_str("signature timestamp outside the tolerance"),
+            SignatureError::Mismatch => f.write_str("signature does not match"),
+        }
+    }
+}
+
+impl std::error::Error for SignatureError {}
+
+fn mac(secret: &str, timestamp: i64, body: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac
+}
+
+/// Builds the signature header value for `body` sent at `timestamp` (unix seconds)
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    format!("t={},v1={}", timestamp, hex::encode(mac(secret, timestamp, body).finalize().into_bytes()))
+}
+
+/// Checks a signature header against the raw request body
+///
+/// Several `v1=` values are accepted so a sender can sign with both the old and new secret
+/// while it is being rotated.
+pub fn verify_signature(secret: &str, header: &str, body: &[u8], tolerance: Duration) -> Result<(), SignatureError> {
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+            Some(("v1", value)) => signatures.extend(hex::decode(value).ok()),
+            _ => {}
+        }
+    }
+
+    let timestamp = timestamp.ok_or(SignatureError::Malformed)?;
+    if signatures.is_empty() {
+        return Err(SignatureError::Malformed);
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs() as i64);
+    if now.abs_diff(timestamp) > tolerance.as_secs() {
+        return Err(SignatureError::Expired);
+    }
+
+    let mac = mac(secret, timestamp, body);
+    if signatures.iter().any(|signature| mac.clone().verify_slice(signature).is_ok()) {
+        Ok(())
+    } else {
+        Err(SignatureError::Mismatch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "whsec_test";
+    const BODY: &[u8] = br#"{"event":"delivered"}"#;
+
+    fn now() -> i64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
+    }
+
+    #[test]
+    fn sign_matches_known_hmac() {
+        assert_eq!(
+            sign(SECRET, 1_700_000_000, BODY),
+            "t=1700000000,v1=c67c56205dc7bc11756ba99637a8aec7647e01861dbd99bea6427ded8c9117e2"
+        );
+    }
+
+    #[test]
+    fn verifies_own_signature() {
+        let header = sign(SECRET, now(), BODY);
+        assert_eq!(verify_signature(SECRET, &header, BODY, DEFAULT_TOLERANCE), Ok(()));
+    }
+
+    #[test]
+    fn rejects_tampered_body_and_wrong_secret() {
+        let header = sign(SECRET, now(), BODY);
+        assert_eq!(
+            verify_signature(SECRET, &header, br#"{"event":"failed"}"#, DEFAULT_TOLERANCE),
+            Err(SignatureError::Mismatch)
+        );
+        assert_eq!(verify_signature("other", &header, BODY, DEFAULT_TOLERANCE), Err(SignatureError::Mismatch));
+    }
+
+    #[test]
+    fn rejects_old_and_future_timestamps() {
+        let old = sign(SECRET, now() - 10 * 60, BODY);
+        let future = sign(SECRET, now() + 10 * 60, BODY);
+        assert_eq!(verify_signature(SECRET, &old, BODY, DEFAULT_TOLERANCE), Err(SignatureError::Expired));
+        assert_eq!(verify_signature(SECRET, &future, BODY, DEFAULT_TOLERANCE), Err(SignatureError::Expired));
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        let t = now();
+        for header in ["", "v1=abcd", &format!("t={}", t), &format!("t={},v1=zz", t), &format!("t=soon,v1={}", "00".repeat(32))] {
+            assert_eq!(verify_signature(SECRET, header, BODY, DEFAULT_TOLERANCE), Err(SignatureError::Malformed), "{}", header);
+        }
+    }
+
+    #[test]
+    fn accepts_any_of_several_signatures_during_rotation() {
+        let t = now();
+        let old = sign("old_secret", t, BODY);
+        let new = sign(SECRET, t, BODY);
+        let header = format!("{},{}", old, new.split_once(',').unwrap().1);
+        assert_eq!(verify_signature(SECRET, &header, BODY, DEFAULT_TOLERANCE), Ok(()));
+        assert_eq!(verify_signature("old_secret", &header, BODY, DEFAULT_TOLERANCE), Ok(()));
+    }
+}
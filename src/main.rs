@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use actix_web::{web, App, HttpServer};
 use actix_cors::Cors;
 use dotenv::dotenv;
@@ -5,10 +7,10 @@ use env_logger::Env;
 use config::load_config;
 use utoipa_swagger_ui::SwaggerUi;
 use utoipa::OpenApi; // This imports the OpenApi trait that provides the `openapi()` method.
-use swagger::ApiDoc;  // Adjust the module import to where you have defined `ApiDoc`.
 
 mod auth;
 mod api;
+mod channels;
 mod db;
 mod services;
 mod config;
@@ -22,6 +24,29 @@ async fn main() -> std::io::Result<()> {
     let config = load_config();
     let pool = db::connect(&config.database_url).await.expect("Failed to connect to the database");
     
+    let mailer = services::mailer::Mailer::from_config(&config).expect("Invalid SMTP configuration");
+    let channels = Arc::new(channels::Channels::from_config(&config, pool.clone(), mailer.clone()));
+    let throttle = services::throttle::Throttle::from_config(&config);
+    tokio::spawn(services::dispatcher::run(pool.clone(), channels.clone(), throttle));
+    tokio::spawn(services::engagement::run_recompute(pool.clone()));
+    tokio::spawn(services::workflow::run(pool.clone()));
+    tokio::spawn(services::outbox::run(pool.clone(), mailer));
+    tokio::spawn(services::user::normalize_legacy_phone_numbers(pool.clone(), config.default_phone_region));
+
+    if let Some(telegram) = &config.telegram {
+        let channels = channels.clone();
+        let url = format!("{}/api/inbound/telegram", config.public_url.trim_end_matches('/'));
+        let secret = telegram.webhook_secret.clone();
+        tokio::spawn(async move {
+            if let Some(bot) = channels.telegram() {
+                match bot.set_webhook(&url, &secret).await {
+                    Ok(()) => log::info!("Telegram updates will be delivered to {}", url),
+                    Err(e) => log::error!("Failed to register Telegram webhook: {}", e),
+                }
+            }
+        });
+    }
+
     let openapi = swagger::ApiDoc::openapi();  // Generate OpenAPI specification from the new file
 
     log::info!("Starting server on http://127.0.0.1:8080");
@@ -31,6 +56,7 @@ async fn main() -> std::io::Result<()> {
             .wrap(Cors::permissive())
             .app_data(web::Data::new(pool.clone()))
             .app_data(web::Data::new(config.clone()))
+            .app_data(web::Data::from(channels.clone()))
             .service(
                 SwaggerUi::new("/swagger-ui/{_:.*}")
                     .url("/api-doc/openapi.json", openapi.clone())
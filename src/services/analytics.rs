@@ -0,0 +1,323 @@
+use serde::Serialize;
+use sqlx::PgExecutor;
+use time::Date;
+use utoipa::ToSchema;
+
+/// Dimensions delivery analytics can be broken down by
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Grouping {
+    pub channel: bool,
+    pub category: bool,
+    pub template: bool,
+    pub variant: bool,
+    pub day: bool,
+}
+
+impl Grouping {
+    pub fn all() -> Self {
+        Grouping { channel: true, category: true, template: true, variant: true, day: true }
+    }
+
+    /// Parses a comma separated list such as `channel,day`
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut grouping = Grouping::default();
+
+        for dimension in value.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match dimension {
+                "channel" => grouping.channel = true,
+                "category" => grouping.category = true,
+                "template" => grouping.template = true,
+                "variant" => grouping.variant = true,
+                "day" => grouping.day = true,
+                other => return Err(format!("Unknown group_by dimension: {}", other)),
+            }
+        }
+
+        Ok(grouping)
+    }
+}
+
+/// One row of the delivery report; dimensions that were not grouped by are null
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DeliveryStats {
+    pub day: Option<String>,
+    pub channel: Option<String>,  // Channel that delivered the notification, null for undelivered ones
+    pub category: Option<String>,
+    pub template: Option<String>,
+    pub variant: Option<String>,  // A/B variant, null for notifications outside an experiment
+    pub total: i64,
+    pub sent: i64,
+    pub failed: i64,
+    pub bounced: i64,
+    pub opened: i64,
+    pub clicked: i64,
+    pub delivery_rate: Option<f64>,  // sent / (sent + failed)
+    pub bounce_rate: Option<f64>,    // Rates below are relative to sent
+    pub open_rate: Option<f64>,
+    pub click_rate: Option<f64>,
+    pub median_latency_seconds: Option<f64>,  // From creation to sending, including any scheduled delay
+}
+
+pub const CSV_HEADER: &str = "day,channel,category,template,variant,total,sent,failed,bounced,opened,clicked,delivery_rate,bounce_rate,open_rate,click_rate,median_latency_seconds";
+
+impl DeliveryStats {
+    pub fn to_csv_row(&self) -> String {
+        let text = |value: &Option<String>| value.as_deref().map(csv_escape).unwrap_or_default();
+        let number = |value: Option<f64>| value.map(|v| format!("{:.4}", v)).unwrap_or_default();
+
+        [
+            text(&self.day),
+            text(&self.channel),
+            text(&self.category),
+            text(&self.template),
+            text(&self.variant),
+            self.total.to_string(),
+            self.sent.to_string(),
+            self.failed.to_string(),
+            self.bounced.to_string(),
+            self.opened.to_string(),
+            self.clicked.to_string(),
+            number(self.delivery_rate),
+            number(self.bounce_rate),
+            number(self.open_rate),
+            number(self.click_rate),
+            number(self.median_latency_seconds),
+        ]
+        .join(",")
+    }
+}
+
+/// Quotes a CSV field when it contains a delimiter, quote or newline, and
+/// prefixes a leading `=`, `+`, `-` or `@` with `'` so spreadsheets do not
+/// evaluate it as a formula
+fn csv_escape(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn rate(numerator: i64, denominator: i64) -> Option<f64> {
+    (denominator > 0).then(|| numerator as f64 / denominator as f64)
+}
+
+/// Aggregates notifications created between `from` and `to` (inclusive, UTC days)
+pub async fn delivery_stats<'e>(
+    executor: impl PgExecutor<'e>,
+    grouping: Grouping,
+    from: Date,
+    to: Date,
+) -> Result<Vec<DeliveryStats>, sqlx::Error> {
+    // Half-open bounds on the raw column, so the created_at index still applies
+    let start = from.midnight().assume_utc();
+    let end = to.next_day().unwrap_or(Date::MAX).midnight().assume_utc();
+
+    // Dimensions that are not grouped by collapse to NULL, so a single GROUP BY serves every combination
+    let rows = sqlx::query!(
+        r#"WITH scoped AS (
+               SELECT n.*,
+                      EXISTS (SELECT 1 FROM notification_events e WHERE e.notification_id = n.id AND e.event_type = 'Bounced') AS bounced,
+                      EXISTS (SELECT 1 FROM notification_events e WHERE e.notification_id = n.id AND e.event_type = 'Opened') AS opened,
+                      EXISTS (SELECT 1 FROM notification_events e WHERE e.notification_id = n.id AND e.event_type = 'Clicked') AS clicked
+               FROM notifications n
+               WHERE n.created_at >= $5 AND n.created_at < $6
+           )
+           SELECT CASE WHEN $4 THEN to_char(created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD') END AS day,
+                  CASE WHEN $1 THEN delivered_via END AS channel,
+                  CASE WHEN $2 THEN category END AS category,
+                  CASE WHEN $3 THEN template END AS template,
+                  CASE WHEN $7 THEN variant END AS variant,
+                  COUNT(*) AS "total!",
+                  COUNT(*) FILTER (WHERE status = 'Sent') AS "sent!",
+                  COUNT(*) FILTER (WHERE status = 'Failed') AS "failed!",
+                  COUNT(*) FILTER (WHERE bounced) AS "bounced!",
+                  COUNT(*) FILTER (WHERE opened) AS "opened!",
+                  COUNT(*) FILTER (WHERE clicked) AS "clicked!",
+                  percentile_cont(0.5) WITHIN GROUP (ORDER BY EXTRACT(EPOCH FROM sent_at - created_at)::float8)
+                      FILTER (WHERE sent_at IS NOT NULL) AS median_latency_seconds
+           FROM scoped
+           GROUP BY 1, 2, 3, 4, 5
+           ORDER BY 1, 2, 3, 4, 5"#,
+        grouping.channel,
+        grouping.category,
+        grouping.template,
+        grouping.day,
+        start,
+        end,
+        grouping.variant
+    )
+    .fetch_all(executor)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| DeliveryStats {
+            delivery_rate: rate(row.sent, row.sent + row.failed),
+            bounce_rate: rate(row.bounced, row.sent),
+            open_rate: rate(row.opened, row.sent),
+            click_rate: rate(row.clicked, row.sent),
+            day: row.day,
+            channel: row.channel,
+            category: row.category,
+            template: row.template,
+            variant: row.variant,
+            total: row.total,
+            sent: row.sent,
+            failed: row.failed,
+            bounced: row.bounced,
+            opened: row.opened,
+            clicked: row.clicked,
+            median_latency_seconds: row.median_latency_seconds,
+        })
+        .collect())
+}
+
+/// How one variant of an experiment performs against the baseline
+#[derive(Debug, Serialize, ToSchema)]
+pub struct VariantStats {
+    pub variant: String,
+    pub sent: i64,
+    pub opened: i64,
+    pub clicked: i64,
+    pub open_rate: Option<f64>,
+    pub click_rate: Option<f64>,
+    pub click_rate_lift: Option<f64>,  // Relative to the baseline, e.g. 0.12 for 12% more clicks
+    pub open_rate_p_value: Option<f64>,  // Two-proportion z-test against the baseline; below 0.05 is significant
+    pub click_rate_p_value: Option<f64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ExperimentStats {
+    pub experiment_id: String,
+    pub baseline: Option<String>,  // The variant named "control", otherwise the first by name
+    pub variants: Vec<VariantStats>,
+}
+
+pub async fn experiment_stats<'e>(executor: impl PgExecutor<'e>, experiment_id: &str) -> Result<ExperimentStats, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT n.variant AS "variant!",
+                  COUNT(*) FILTER (WHERE n.status = 'Sent') AS "sent!",
+                  COUNT(*) FILTER (WHERE n.status = 'Sent' AND EXISTS (
+                      SELECT 1 FROM notification_events e WHERE e.notification_id = n.id AND e.event_type = 'Opened'
+                  )) AS "opened!",
+                  COUNT(*) FILTER (WHERE n.status = 'Sent' AND EXISTS (
+                      SELECT 1 FROM notification_events e WHERE e.notification_id = n.id AND e.event_type = 'Clicked'
+                  )) AS "clicked!"
+           FROM notifications n
+           WHERE n.experiment_id = $1 AND n.variant IS NOT NULL
+           GROUP BY n.variant
+           ORDER BY n.variant"#,
+        experiment_id
+    )
+    .fetch_all(executor)
+    .await?;
+
+    let baseline = rows
+        .iter()
+        .find(|row| row.variant.eq_ignore_ascii_case("control"))
+        .or(rows.first())
+        .map(|row| (row.variant.clone(), row.sent, row.opened, row.clicked));
+
+    let variants = rows
+        .into_iter()
+        .map(|row| {
+            let (open_rate_p_value, click_rate_p_value, click_rate_lift) = match &baseline {
+                Some((name, sent, opened, clicked)) if *name != row.variant => (
+                    two_proportion_p_value(row.opened, row.sent, *opened, *sent),
+                    two_proportion_p_value(row.clicked, row.sent, *clicked, *sent),
+                    match (rate(row.clicked, row.sent), rate(*clicked, *sent)) {
+                        (Some(rate), Some(base)) if base > 0.0 => Some(rate / base - 1.0),
+                        _ => None,
+                    },
+                ),
+                _ => (None, None, None),
+            };
+
+            VariantStats {
+                open_rate: rate(row.opened, row.sent),
+                click_rate: rate(row.clicked, row.sent),
+                variant: row.variant,
+                sent: row.sent,
+                opened: row.opened,
+                clicked: row.clicked,
+                click_rate_lift,
+                open_rate_p_value,
+                click_rate_p_value,
+            }
+        })
+        .collect();
+
+    Ok(ExperimentStats {
+        experiment_id: experiment_id.to_string(),
+        baseline: baseline.map(|(name, ..)| name),
+        variants,
+    })
+}
+
+/// Two-sided p-value for the difference between two proportions, using the pooled z-test
+fn two_proportion_p_value(successes_a: i64, total_a: i64, successes_b: i64, total_b: i64) -> Option<f64> {
+    if total_a == 0 || total_b == 0 {
+        return None;
+    }
+
+    let (n_a, n_b) = (total_a as f64, total_b as f64);
+    let pooled = (successes_a + successes_b) as f64 / (n_a + n_b);
+    let standard_error = (pooled * (1.0 - pooled) * (1.0 / n_a + 1.0 / n_b)).sqrt();
+    if standard_error == 0.0 {
+        return None;
+    }
+
+    let z = (successes_a as f64 / n_a - successes_b as f64 / n_b) / standard_error;
+    Some(erfc(z.abs() / std::f64::consts::SQRT_2))
+}
+
+/// Complementary error function (Abramowitz & Stegun 7.1.26, accurate to about 1e-7)
+fn erfc(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let poly = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    poly * (-x * x).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_escape_neutralizes_formulas() {
+        assert_eq!(csv_escape("=HYPERLINK(\"x\")"), "\"'=HYPERLINK(\"\"x\"\")\"");
+        assert_eq!(csv_escape("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(csv_escape("-1"), "'-1");
+        assert_eq!(csv_escape("plain"), "plain");
+    }
+
+    #[test]
+    fn erfc_matches_reference_values() {
+        // The approximation's published error bound is 1.5e-7
+        assert!((erfc(0.0) - 1.0).abs() < 1.5e-7);
+        assert!((erfc(0.5) - 0.479_500_122).abs() < 1.5e-7);
+        assert!((erfc(1.0) - 0.157_299_207).abs() < 1.5e-7);
+    }
+
+    #[test]
+    fn z_test_p_value_for_a_known_difference() {
+        // 20% vs 25% click rate on 1000 recipients each: z = -2.677, two-sided p = 0.00742
+        let p = two_proportion_p_value(200, 1000, 250, 1000).unwrap();
+        assert!((p - 0.007_419_649).abs() < 1e-6, "p = {}", p);
+
+        // The test is symmetric, and identical rates are not significant at all
+        assert_eq!(two_proportion_p_value(250, 1000, 200, 1000), Some(p));
+        assert!((two_proportion_p_value(100, 1000, 50, 500).unwrap() - 1.0).abs() < 1.5e-7);
+    }
+
+    #[test]
+    fn z_test_needs_data_and_variance() {
+        assert_eq!(two_proportion_p_value(0, 0, 10, 100), None);
+        assert_eq!(two_proportion_p_value(0, 100, 0, 100), None);
+    }
+}
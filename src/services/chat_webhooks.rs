@@ -0,0 +1,100 @@
+use serde::Serialize;
+use sqlx::PgExecutor;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::db::models::DeliveryMethod;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ChatWebhook {
+    pub id: Uuid,
+    pub provider: String,  // Slack, Discord or Teams
+    pub url: String,
+    pub active: bool,
+    pub last_error: Option<String>,
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+/// Where the dispatcher posts a user's notifications for one chat service
+pub struct ChatTarget {
+    pub id: Uuid,
+    pub url: String,
+}
+
+/// Sets the user's incoming-webhook URL for `provider`, replacing and reactivating any earlier one
+pub async fn set_webhook<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+    provider: DeliveryMethod,
+    url: &str,
+) -> Result<ChatWebhook, sqlx::Error> {
+    sqlx::query_as!(
+        ChatWebhook,
+        "INSERT INTO chat_webhooks (user_id, provider, url)
+         VALUES ($1, $2, $3)
+         ON CONFLICT (user_id, provider) DO UPDATE
+         SET url = EXCLUDED.url, active = TRUE, last_error = NULL, updated_at = NOW()
+         RETURNING id, provider, url, active, last_error, created_at, updated_at",
+        user_id,
+        provider.as_str(),
+        url
+    )
+    .fetch_one(executor)
+    .await
+}
+
+/// Removes the user's webhook for `provider`; returns false if they had none
+pub async fn remove_webhook<'e>(executor: impl PgExecutor<'e>, user_id: Uuid, provider: DeliveryMethod) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM chat_webhooks WHERE user_id = $1 AND provider = $2",
+        user_id,
+        provider.as_str()
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn list_webhooks<'e>(executor: impl PgExecutor<'e>, user_id: Uuid) -> Result<Vec<ChatWebhook>, sqlx::Error> {
+    sqlx::query_as!(
+        ChatWebhook,
+        "SELECT id, provider, url, active, last_error, created_at, updated_at
+         FROM chat_webhooks
+         WHERE user_id = $1
+         ORDER BY provider",
+        user_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+pub async fn active_webhook<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+    provider: DeliveryMethod,
+) -> Result<Option<ChatTarget>, sqlx::Error> {
+    sqlx::query_as!(
+        ChatTarget,
+        "SELECT id, url FROM chat_webhooks WHERE user_id = $1 AND provider = $2 AND active",
+        user_id,
+        provider.as_str()
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// Stops using a webhook the chat service reports as deleted or revoked
+pub async fn deactivate_webhook<'e>(executor: impl PgExecutor<'e>, webhook_id: Uuid, reason: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE chat_webhooks SET active = FALSE, last_error = $2, updated_at = NOW() WHERE id = $1",
+        webhook_id,
+        reason
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
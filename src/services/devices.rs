@@ -0,0 +1,87 @@
+use serde::Serialize;
+use sqlx::PgExecutor;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::db::models::Platform;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Device {
+    pub id: Uuid,
+    pub platform: String,  // Android or iOS
+    pub token: String,
+    pub app_version: Option<String>,
+    pub active: bool,
+    pub last_seen: OffsetDateTime,
+    pub created_at: OffsetDateTime,
+}
+
+impl Device {
+    pub fn platform(&self) -> Option<Platform> {
+        self.platform.parse().ok()
+    }
+}
+
+/// Registers a push token, or refreshes it if it is already known
+///
+/// Re-registering reactivates the token and moves it to `user_id`, since a phone that changes
+/// hands keeps its token.
+pub async fn register_device<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+    platform: Platform,
+    token: &str,
+    app_version: Option<&str>,
+) -> Result<Device, sqlx::Error> {
+    sqlx::query_as!(
+        Device,
+        "INSERT INTO devices (user_id, platform, token, app_version)
+         VALUES ($1, $2, $3, $4)
+         ON CONFLICT (platform, token) DO UPDATE
+         SET user_id = EXCLUDED.user_id, app_version = EXCLUDED.app_version, active = TRUE,
+             last_error = NULL, last_seen = NOW()
+         RETURNING id, platform, token, app_version, active, last_seen, created_at",
+        user_id,
+        platform.as_str(),
+        token,
+        app_version
+    )
+    .fetch_one(executor)
+    .await
+}
+
+/// Removes one of the user's tokens, e.g. on logout; returns false if they had no such token
+pub async fn remove_device<'e>(executor: impl PgExecutor<'e>, user_id: Uuid, token: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!("DELETE FROM devices WHERE user_id = $1 AND token = $2", user_id, token)
+        .execute(executor)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn active_devices<'e>(executor: impl PgExecutor<'e>, user_id: Uuid) -> Result<Vec<Device>, sqlx::Error> {
+    sqlx::query_as!(
+        Device,
+        "SELECT id, platform, token, app_version, active, last_seen, created_at
+         FROM devices
+         WHERE user_id = $1 AND active
+         ORDER BY last_seen DESC",
+        user_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Stops sending to a token the push service has rejected
+pub async fn deactivate_device<'e>(executor: impl PgExecutor<'e>, device_id: Uuid, reason: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE devices SET active = FALSE, last_error = $2 WHERE id = $1",
+        device_id,
+        reason
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,330 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{error, info, warn};
+use sqlx::types::Json;
+use sqlx::{PgExecutor, PgPool};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::channels::{sms, Channels, DeliveryError};
+use crate::db::models::{DeliveryMethod, NotificationAction, PendingNotification, User};
+use crate::services::throttle::{Admission, Blocked, Throttle};
+use crate::services::{notification, preferences, user};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const BATCH_SIZE: i64 = 50;
+const MAX_ATTEMPTS: i32 = 5;
+/// Long enough to walk a whole fallback chain even when every channel times out
+const LEASE: Duration = Duration::from_secs(5 * 60);
+
+/// Polls for due notifications and delivers them until the process exits
+pub async fn run(pool: PgPool, channels: Arc<Channels>, throttle: Throttle) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = dispatch_due(&pool, &channels, &throttle).await {
+            error!("Failed to dispatch pending notifications: {:?}", e);
+        }
+    }
+}
+
+async fn dispatch_due(pool: &PgPool, channels: &Channels, throttle: &Throttle) -> Result<(), sqlx::Error> {
+    // Each notification is claimed and committed on its own, so no row lock or transaction is held
+    // while messages go out and a later error cannot roll back a delivery that already happened
+    for _ in 0..BATCH_SIZE {
+        match claim_next(pool).await? {
+            Some(pending) => deliver(pool, channels, throttle, &pending).await?,
+            None => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// Leases the oldest due notification to this dispatcher
+///
+/// SKIP LOCKED and the lease let several instances run the dispatcher without sending twice; if
+/// this one dies mid-delivery the lease runs out and another instance retries the notification.
+async fn claim_next(pool: &PgPool) -> Result<Option<PendingNotification>, sqlx::Error> {
+    sqlx::query_as!(
+        PendingNotification,
+        r#"UPDATE notifications SET lease_until = NOW() + make_interval(secs => $1)
+           WHERE id = (
+               SELECT id FROM notifications
+               WHERE status = 'Pending' AND user_id IS NOT NULL AND (send_at IS NULL OR send_at <= NOW())
+                     AND (lease_until IS NULL OR lease_until <= NOW())
+               ORDER BY created_at
+               LIMIT 1
+               FOR UPDATE SKIP LOCKED
+           )
+           RETURNING id, user_id AS "user_id!", content, html_content, title, actions AS "actions: Json<Vec<NotificationAction>>",
+                     channels, category, priority, voice_acknowledge, attempts, sms_max_segments, sms_overflow"#,
+        LEASE.as_secs_f64()
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Walks the notification's channel chain until one of them accepts it
+///
+/// Every outcome is written as soon as it is known; the successful send and its `Sent` event are
+/// recorded together so the notification never looks delivered without its history, or vice versa.
+async fn deliver(
+    pool: &PgPool,
+    channels: &Channels,
+    throttle: &Throttle,
+    pending: &PendingNotification,
+) -> Result<(), sqlx::Error> {
+    let recipient = match user::find_user(pool, pending.user_id).await? {
+        Some(recipient) => recipient,
+        None => return mark_failed(pool, pending.id, "Recipient no longer exists").await,
+    };
+
+    let explicit = pending.channels.as_deref().map(preferences::parse_methods);
+    let prefs = preferences::get_preferences(pool, pending.user_id).await?;
+    let chain = preferences::resolve_chain(explicit.as_deref(), prefs.as_ref());
+    let opt_outs = preferences::get_opt_outs(pool, pending.user_id).await?;
+    let category = pending.category();
+
+    let mut errors = Vec::new();
+    let mut opted_out = 0;
+
+    for &method in &chain {
+        if preferences::is_opted_out(&opt_outs, category, method) {
+            let reason = format!("user opted out of {} notifications", category);
+            notification::record_event(pool, pending.id, Some(method), "Skipped", Some(&reason)).await?;
+            opted_out += 1;
+            continue;
+        }
+
+        // A throttled provider defers the whole chain without spending an attempt
+        let result = match attempt(pool, channels, throttle, method, &recipient, pending).await? {
+            Attempt::Blocked(Blocked::RateLimited) => return release_until_next_poll(pool, pending.id).await,
+            Attempt::Blocked(Blocked::BreakerOpen(open_until)) => return defer(pool, pending.id, method, open_until).await,
+            Attempt::Done(result) => result,
+        };
+
+        match result {
+            Ok(()) => {
+                // Recorded for cost tracking; the body is deterministic so recomputing it is safe
+                let sms_segments = match method {
+                    DeliveryMethod::Sms => channels.sms_body(pending).ok().map(|body| sms::segments(&body).len() as i32),
+                    _ => None,
+                };
+
+                let mut tx = pool.begin().await?;
+                notification::record_event(&mut *tx, pending.id, Some(method), "Sent", None).await?;
+                sqlx::query!(
+                    "UPDATE notifications
+                     SET status = 'Sent', delivered_via = $2, sent_at = NOW(), attempts = attempts + 1, last_error = NULL,
+                         sms_segments = $3, lease_until = NULL
+                     WHERE id = $1",
+                    pending.id,
+                    method.as_str(),
+                    sms_segments
+                )
+                .execute(&mut *tx)
+                .await?;
+                tx.commit().await?;
+
+                info!("Notification {} delivered via {}", pending.id, method);
+                return Ok(());
+            }
+            Err(DeliveryError::Transient(reason)) => {
+                // Give the same chain another go later rather than degrading to a worse channel
+                notification::record_event(pool, pending.id, Some(method), "Failed", Some(&reason)).await?;
+                return retry_later(pool, pending, &format!("{}: {}", method, reason)).await;
+            }
+            Err(e) => {
+                let event_type = match e {
+                    DeliveryError::Unreachable(_) => "Skipped",
+                    _ => "Failed",
+                };
+                let reason = e.to_string();
+                warn!("Notification {} not delivered via {} ({}), trying next channel", pending.id, method, reason);
+                notification::record_event(pool, pending.id, Some(method), event_type, Some(&reason)).await?;
+                errors.push(format!("{}: {}", method, reason));
+            }
+        }
+    }
+
+    // Nothing went wrong if the user simply does not want this kind of notification
+    if !chain.is_empty() && opted_out == chain.len() {
+        return mark_suppressed(pool, pending.id, &format!("User opted out of {} notifications", category)).await;
+    }
+
+    let last_error = if errors.is_empty() {
+        "No delivery channels available".to_string()
+    } else {
+        errors.join("; ")
+    };
+    mark_failed(pool, pending.id, &last_error).await
+}
+
+/// What came of trying one channel of the chain
+enum Attempt {
+    Done(Result<(), DeliveryError>),
+    Blocked(Blocked),
+}
+
+/// Sends through each of the channel's providers the user is on, settling every provider's
+/// circuit breaker with the outcome
+///
+/// Delivery succeeds if any provider accepts it; a throttled provider only holds the notification
+/// back when no other provider got it through.
+async fn attempt(
+    pool: &PgPool,
+    channels: &Channels,
+    throttle: &Throttle,
+    method: DeliveryMethod,
+    recipient: &User,
+    pending: &PendingNotification,
+) -> Result<Attempt, sqlx::Error> {
+    let providers = match channels.providers(method, recipient).await {
+        Ok(providers) => providers,
+        Err(e) => return Ok(Attempt::Done(Err(e))),
+    };
+
+    let mut delivered = false;
+    let mut blocked = None;
+    let mut errors = Vec::new();
+
+    for provider in providers {
+        let trial = match throttle.acquire(pool, method, provider.key).await? {
+            Admission::Allowed { trial } => trial,
+            Admission::Blocked(reason) => {
+                blocked.get_or_insert(reason);
+                continue;
+            }
+        };
+
+        match channels.send(method, provider, recipient, pending).await {
+            Ok(()) => {
+                throttle.record_success(pool, provider.key).await?;
+                delivered = true;
+            }
+            Err(e) => {
+                match &e {
+                    DeliveryError::Transient(reason) if method.uses_shared_provider() => {
+                        throttle.record_failure(pool, provider.key, reason).await?;
+                    }
+                    // Nothing was learned about the provider, so the next caller gets to try
+                    _ if trial => throttle.release_trial(pool, provider.key).await?,
+                    _ => {}
+                }
+                errors.push(e);
+            }
+        }
+    }
+
+    if delivered {
+        return Ok(Attempt::Done(Ok(())));
+    }
+    if let Some(blocked) = blocked {
+        return Ok(Attempt::Blocked(blocked));
+    }
+    Ok(Attempt::Done(Err(merge_errors(errors))))
+}
+
+/// Combines the errors of a channel's providers, keeping the one that is most worth retrying
+fn merge_errors(mut errors: Vec<DeliveryError>) -> DeliveryError {
+    if errors.len() <= 1 {
+        return errors
+            .pop()
+            .unwrap_or_else(|| DeliveryError::Unreachable("no provider to send through".to_string()));
+    }
+
+    let reason = errors.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ");
+    if errors.iter().any(|e| matches!(e, DeliveryError::Transient(_))) {
+        DeliveryError::Transient(reason)
+    } else if errors.iter().all(|e| matches!(e, DeliveryError::Unreachable(_))) {
+        DeliveryError::Unreachable(reason)
+    } else {
+        DeliveryError::Permanent(reason)
+    }
+}
+
+async fn retry_later<'e>(
+    executor: impl PgExecutor<'e>,
+    pending: &PendingNotification,
+    reason: &str,
+) -> Result<(), sqlx::Error> {
+    let attempts = pending.attempts + 1;
+
+    if attempts >= MAX_ATTEMPTS {
+        return mark_failed(executor, pending.id, reason).await;
+    }
+
+    // Exponential backoff: 1, 2, 4, 8 minutes
+    let retry_at = OffsetDateTime::now_utc() + time::Duration::minutes(1 << (attempts - 1));
+
+    sqlx::query!(
+        "UPDATE notifications SET attempts = $2, send_at = $3, last_error = $4, lease_until = NULL WHERE id = $1",
+        pending.id,
+        attempts,
+        retry_at,
+        reason
+    )
+    .execute(executor)
+    .await?;
+
+    warn!("Notification {} will be retried at {} ({})", pending.id, retry_at, reason);
+    Ok(())
+}
+
+async fn defer<'e>(
+    executor: impl PgExecutor<'e>,
+    notification_id: Uuid,
+    method: DeliveryMethod,
+    until: OffsetDateTime,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!("UPDATE notifications SET send_at = $2, lease_until = NULL WHERE id = $1", notification_id, until)
+        .execute(executor)
+        .await?;
+
+    info!("Notification {} deferred until {}: {} circuit breaker is open", notification_id, until, method);
+    Ok(())
+}
+
+/// Hands a rate-limited notification back without spending an attempt; the lease keeps this batch
+/// from claiming it again before the limit has had a chance to refill
+async fn release_until_next_poll(pool: &PgPool, notification_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE notifications SET lease_until = NOW() + make_interval(secs => $2) WHERE id = $1",
+        notification_id,
+        POLL_INTERVAL.as_secs_f64()
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+async fn mark_suppressed<'e>(executor: impl PgExecutor<'e>, notification_id: Uuid, reason: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE notifications SET status = 'Suppressed', last_error = $2, lease_until = NULL WHERE id = $1",
+        notification_id,
+        reason
+    )
+    .execute(executor)
+    .await?;
+
+    info!("Notification {} suppressed: {}", notification_id, reason);
+    Ok(())
+}
+
+async fn mark_failed<'e>(executor: impl PgExecutor<'e>, notification_id: Uuid, reason: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE notifications SET status = 'Failed', attempts = attempts + 1, last_error = $2, lease_until = NULL WHERE id = $1",
+        notification_id,
+        reason
+    )
+    .execute(executor)
+    .await?;
+
+    error!("Notification {} failed: {}", notification_id, reason);
+    Ok(())
+}
@@ -0,0 +1,255 @@
+//! Minimal parser for RFC 3464 delivery status notifications (bounce messages).
+//!
+//! Only what is needed to act on a bounce is extracted: each recipient's action and
+//! status code, plus the Message-ID of the original message so the bounce can be tied
+//! back to the notification that caused it.
+
+use uuid::Uuid;
+
+/// One recipient's outcome from a delivery status notification
+#[derive(Debug, Clone)]
+pub struct DsnRecipient {
+    pub recipient: String,
+    pub action: String,
+    pub status: String,
+    pub diagnostic: Option<String>,
+}
+
+impl DsnRecipient {
+    /// 5.x.x statuses are permanent; 4.x.x ones are the remote side asking us to try again
+    pub fn is_hard_bounce(&self) -> bool {
+        self.action.eq_ignore_ascii_case("failed") && self.status.starts_with('5')
+    }
+
+    /// `delayed` reports are only progress updates; the message may still be delivered
+    pub fn is_failure(&self) -> bool {
+        self.action.eq_ignore_ascii_case("failed")
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DeliveryStatusReport {
+    pub recipients: Vec<DsnRecipient>,
+    pub original_message_id: Option<String>,
+}
+
+/// Parses a raw `multipart/report; report-type=delivery-status` message.
+///
+/// Returns `None` when the message holds no per-recipient status fields.
+pub fn parse(raw: &str) -> Option<DeliveryStatusReport> {
+    let text = raw.replace("\r\n", "\n");
+    let mut report = DeliveryStatusReport::default();
+
+    collect_entity(&text, &mut report);
+
+    if report.recipients.is_empty() {
+        None
+    } else {
+        Some(report)
+    }
+}
+
+/// Extracts our notification ID from a Message-ID of the form `<uuid@domain>`
+pub fn notification_id_from_message_id(message_id: &str) -> Option<Uuid> {
+    let local = message_id.trim().trim_start_matches('<').split('@').next()?;
+    Uuid::parse_str(local).ok()
+}
+
+fn collect_entity(entity: &str, report: &mut DeliveryStatusReport) {
+    let (headers, body) = split_headers(entity);
+    let content_type = header_value(&headers, "content-type").unwrap_or_default().to_lowercase();
+
+    if content_type.starts_with("multipart/") {
+        if let Some(boundary) = header_param(header_value(&headers, "content-type").unwrap_or_default(), "boundary") {
+            for part in split_parts(body, &boundary) {
+                collect_entity(part, report);
+            }
+        }
+    } else if content_type.starts_with("message/delivery-status") {
+        collect_status_fields(body, report);
+    } else if content_type.starts_with("text/rfc822-headers") || content_type.starts_with("message/rfc822") {
+        let (original, _) = split_headers(body);
+        if report.original_message_id.is_none() {
+            report.original_message_id = header_value(&original, "message-id").map(str::to_string);
+        }
+    }
+}
+
+/// The delivery-status body is one block of per-message fields followed by one block per recipient
+fn collect_status_fields(body: &str, report: &mut DeliveryStatusReport) {
+    for block in body.split("\n\n") {
+        let fields = unfold(block);
+
+        let recipient = match header_value(&fields, "final-recipient").or_else(|| header_value(&fields, "original-recipient")) {
+            Some(value) => address_from_field(value),
+            None => continue,
+        };
+
+        report.recipients.push(DsnRecipient {
+            recipient,
+            action: header_value(&fields, "action").unwrap_or_default().to_string(),
+            status: header_value(&fields, "status").unwrap_or_default().to_string(),
+            diagnostic: header_value(&fields, "diagnostic-code").map(str::to_string),
+        });
+    }
+}
+
+/// `rfc822; user@example.com` -> `user@example.com`
+fn address_from_field(value: &str) -> String {
+    let address = value.split_once(';').map_or(value, |(_, address)| address);
+    address.trim().trim_start_matches('<').trim_end_matches('>').to_string()
+}
+
+fn split_headers(entity: &str) -> (Vec<(String, String)>, &str) {
+    // An entity that opens with a blank line has no headers at all
+    if let Some(body) = entity.strip_prefix('\n') {
+        return (Vec::new(), body);
+    }
+
+    let (head, body) = entity.split_once("\n\n").unwrap_or((entity, ""));
+    (unfold(head), body)
+}
+
+/// Joins folded header lines and splits them into lowercased names and trimmed values
+fn unfold(block: &str) -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = Vec::new();
+
+    for line in block.lines() {
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some((_, value)) = fields.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            fields.push((name.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    fields
+}
+
+fn header_value<'a>(fields: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    fields.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+}
+
+fn header_param(value: &str, param: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|part| {
+        let (name, v) = part.split_once('=')?;
+        if name.trim().eq_ignore_ascii_case(param) {
+            Some(v.trim().trim_matches('"').to_string())
+        } else {
+            None
+        }
+    })
+}
+
+fn split_parts<'a>(body: &'a str, boundary: &str) -> Vec<&'a str> {
+    let delimiter = format!("--{}", boundary);
+    let body = body.split(&format!("{}--", delimiter)).next().unwrap_or(body);
+
+    body.split(&delimiter)
+        .skip(1) // Preamble
+        .map(|part| part.split_once('\n').map_or("", |(_, rest)| rest)) // Rest of the delimiter line
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Trimmed-down version of the example in RFC 3464 section 10.1, with our Message-ID
+    const BOUNCE: &str = "From: Mail Delivery Subsystem <MAILER-DAEMON@example.com>\r
+To: bounces@notismart.example\r
+Subject: Returned mail: User unknown\r
+MIME-Version: 1.0\r
+Content-Type: multipart/report; report-type=delivery-status;\r
+    boundary=\"RAA14128.773615765/CS.UTK.EDU\"\r
+\r
+--RAA14128.773615765/CS.UTK.EDU\r
+Content-Type: text/plain\r
+\r
+The original message was received but could not be delivered.\r
+\r
+--RAA14128.773615765/CS.UTK.EDU\r
+Content-Type: message/delivery-status\r
+\r
+Reporting-MTA: dns; cs.utk.edu\r
+Arrival-Date: Sat, 1 Jul 1994 16:10:56 -0400\r
+\r
+Original-Recipient: rfc822;arathib@vnet.ibm.com\r
+Final-Recipient: rfc822;arathib@vnet.ibm.com\r
+Action: failed\r
+Status: 5.0.0 (permanent failure)\r
+Diagnostic-Code: smtp; 550 'arathib@vnet.IBM.COM' is not a\r
+    registered gateway user\r
+\r
+Final-Recipient: rfc822; <wsnell@sdcc13.ucsd.edu>\r
+Action: delayed\r
+Status: 4.0.0 (hostname lookup failure)\r
+\r
+--RAA14128.773615765/CS.UTK.EDU\r
+Content-Type: text/rfc822-headers\r
+\r
+Message-ID: <6f1c5c6e-8f2a-4c1e-9a51-0d3b2f4e7a10@notismart.example>\r
+Subject: Your weekly summary\r
+\r
+--RAA14128.773615765/CS.UTK.EDU--\r
+";
+
+    #[test]
+    fn parses_recipients_and_original_message_id() {
+        let report = parse(BOUNCE).expect("a delivery status report");
+
+        assert_eq!(report.recipients.len(), 2);
+
+        let failed = &report.recipients[0];
+        assert_eq!(failed.recipient, "arathib@vnet.ibm.com");
+        assert_eq!(failed.action, "failed");
+        assert_eq!(failed.status, "5.0.0 (permanent failure)");
+        assert_eq!(
+            failed.diagnostic.as_deref(),
+            Some("smtp; 550 'arathib@vnet.IBM.COM' is not a registered gateway user")
+        );
+
+        assert_eq!(report.recipients[1].recipient, "wsnell@sdcc13.ucsd.edu");
+        assert_eq!(
+            report.original_message_id.as_deref(),
+            Some("<6f1c5c6e-8f2a-4c1e-9a51-0d3b2f4e7a10@notismart.example>")
+        );
+    }
+
+    #[test]
+    fn only_permanent_failures_are_hard_bounces() {
+        let report = parse(BOUNCE).unwrap();
+
+        assert!(report.recipients[0].is_failure());
+        assert!(report.recipients[0].is_hard_bounce());
+
+        // A delay is only a progress report
+        assert!(!report.recipients[1].is_failure());
+        assert!(!report.recipients[1].is_hard_bounce());
+
+        let soft = DsnRecipient {
+            recipient: "user@example.com".to_string(),
+            action: "failed".to_string(),
+            status: "4.2.2".to_string(),
+            diagnostic: None,
+        };
+        assert!(soft.is_failure());
+        assert!(!soft.is_hard_bounce());
+    }
+
+    #[test]
+    fn ignores_messages_without_status_fields() {
+        assert!(parse("Subject: Out of office\r\n\r\nI am away until Monday.\r\n").is_none());
+    }
+
+    #[test]
+    fn extracts_notification_id_from_message_id() {
+        assert_eq!(
+            notification_id_from_message_id("<6f1c5c6e-8f2a-4c1e-9a51-0d3b2f4e7a10@notismart.example>"),
+            Some(Uuid::parse_str("6f1c5c6e-8f2a-4c1e-9a51-0d3b2f4e7a10").unwrap())
+        );
+        assert_eq!(notification_id_from_message_id("<CAF=abc123@mail.gmail.com>"), None);
+    }
+}
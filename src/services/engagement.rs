@@ -0,0 +1,421 @@
+//! Send-time and channel optimization from historical engagement.
+//!
+//! Every user gets a score for each of the 168 hours of the week (UTC) describing how
+//! likely they are to engage with a notification delivered in that hour. Scores come from
+//! a histogram of their past opens, clicks and reads, with recent events weighted more
+//! heavily, lightly smoothed across neighbouring hours, and blended with the population
+//! profile so users with little history still get a sensible answer.
+//!
+//! Channels are scored the same way in spirit: the share of notifications delivered on a
+//! channel that the user went on to open, click or read, shrunk towards the population's
+//! rate for that channel.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use log::{error, info};
+use serde::Serialize;
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use time::{OffsetDateTime, UtcOffset};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::db::models::{Category, DeliveryMethod};
+use crate::services::preferences;
+
+pub const HOURS_PER_WEEK: usize = 168;
+
+const RECOMPUTE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const LOOKBACK_DAYS: i64 = 90;
+const HALF_LIFE_DAYS: f64 = 30.0;
+// How many "average user" events are mixed into every personal profile
+const PRIOR_WEIGHT: f64 = 5.0;
+
+/// Profile row for the whole population
+const GLOBAL_PROFILE: Uuid = Uuid::nil();
+
+#[derive(Debug, Clone)]
+pub struct EngagementProfile {
+    pub user_id: Uuid,  // The nil UUID when this is the population profile
+    pub hour_scores: Vec<f64>,
+    pub sample_size: i32,
+    pub computed_at: OffsetDateTime,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ChannelScore {
+    pub channel: DeliveryMethod,
+    pub delivered: i32,
+    pub engaged: i32,
+    pub score: f64,  // Predicted probability the user engages with a notification on this channel
+    pub personalized: bool,  // False when only the population rate is known
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SendTime {
+    pub weekday: String,
+    pub hour: u8,  // UTC
+    pub score: f64,
+}
+
+pub fn hour_of_week(at: OffsetDateTime) -> usize {
+    let at = at.to_offset(UtcOffset::UTC);
+    at.weekday().number_days_from_monday() as usize * 24 + at.hour() as usize
+}
+
+/// Rebuilds every profile on a fixed interval until the process exits
+pub async fn run_recompute(pool: PgPool) {
+    let mut interval = tokio::time::interval(RECOMPUTE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        match recompute_profiles(&pool).await {
+            Ok(count) => info!("Recomputed {} engagement profiles", count),
+            Err(e) => error!("Failed to recompute engagement profiles: {:?}", e),
+        }
+
+        match recompute_channel_scores(&pool).await {
+            Ok(count) => info!("Recomputed channel scores for {} users", count),
+            Err(e) => error!("Failed to recompute channel scores: {:?}", e),
+        }
+    }
+}
+
+pub async fn recompute_profiles(pool: &PgPool) -> Result<usize, sqlx::Error> {
+    // Only the first open/click/read of each notification says when the user chose to engage
+    let rows = sqlx::query!(
+        r#"SELECT DISTINCT ON (e.notification_id, e.event_type)
+               n.user_id AS "user_id!", e.created_at
+           FROM notification_events e
+           JOIN notifications n ON n.id = e.notification_id
+           WHERE e.event_type IN ('Opened', 'Clicked', 'Read')
+             AND e.created_at > NOW() - make_interval(days => $1)
+             AND n.user_id IS NOT NULL
+           ORDER BY e.notification_id, e.event_type, e.created_at"#,
+        LOOKBACK_DAYS as i32
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let now = OffsetDateTime::now_utc();
+    let mut by_user: HashMap<Uuid, Vec<OffsetDateTime>> = HashMap::new();
+    for row in rows {
+        by_user.entry(row.user_id).or_default().push(row.created_at);
+    }
+
+    let histograms: HashMap<Uuid, Vec<f64>> = by_user
+        .iter()
+        .map(|(user_id, timestamps)| (*user_id, smooth(&decayed_histogram(timestamps, now))))
+        .collect();
+
+    let mut population = vec![0.0; HOURS_PER_WEEK];
+    for histogram in histograms.values() {
+        for (total, weight) in population.iter_mut().zip(histogram) {
+            *total += weight;
+        }
+    }
+    let prior = normalize(&population);
+
+    let mut tx = pool.begin().await?;
+    let total_samples = by_user.values().map(Vec::len).sum::<usize>() as i32;
+    save_profile(&mut *tx, GLOBAL_PROFILE, &prior, total_samples).await?;
+
+    for (user_id, histogram) in &histograms {
+        let scores = blend_with_prior(histogram, &prior);
+        save_profile(&mut *tx, *user_id, &scores, by_user[user_id].len() as i32).await?;
+    }
+
+    // NOW() is fixed for the transaction, so anything older was not rewritten above: those
+    // users have no engagement left in the lookback window and fall back to the population
+    sqlx::query!("DELETE FROM engagement_profiles WHERE computed_at < NOW()")
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(histograms.len())
+}
+
+async fn save_profile<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+    scores: &[f64],
+    sample_size: i32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO engagement_profiles (user_id, hour_scores, sample_size, computed_at)
+         VALUES ($1, $2, $3, NOW())
+         ON CONFLICT (user_id) DO UPDATE SET
+             hour_scores = EXCLUDED.hour_scores,
+             sample_size = EXCLUDED.sample_size,
+             computed_at = EXCLUDED.computed_at",
+        user_id,
+        scores,
+        sample_size
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// The user's own profile, or the population profile when they have no history yet
+pub async fn get_profile<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+) -> Result<Option<EngagementProfile>, sqlx::Error> {
+    let rows = sqlx::query_as!(
+        EngagementProfile,
+        "SELECT user_id, hour_scores, sample_size, computed_at FROM engagement_profiles
+         WHERE user_id = $1 OR user_id = $2
+         ORDER BY user_id = $1 DESC
+         LIMIT 1",
+        user_id,
+        GLOBAL_PROFILE
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(rows.filter(|profile| profile.hour_scores.len() == HOURS_PER_WEEK))
+}
+
+/// Picks the highest-scoring moment in `[start, end)`, preferring the earliest on ties.
+///
+/// Candidates are `start` itself and every following top of the hour.
+pub fn best_time_in_window(hour_scores: &[f64], start: OffsetDateTime, end: OffsetDateTime) -> OffsetDateTime {
+    // Step through UTC hours so offsets like +05:30 do not shift the hour boundaries
+    let start = start.to_offset(UtcOffset::UTC);
+    let mut best = start;
+    let mut best_score = f64::MIN;
+    let mut candidate = start;
+
+    while candidate < end {
+        let score = hour_scores.get(hour_of_week(candidate)).copied().unwrap_or(0.0);
+        if score > best_score {
+            best = candidate;
+            best_score = score;
+        }
+
+        let hour_start = candidate.replace_minute(0).and_then(|t| t.replace_second(0)).and_then(|t| t.replace_nanosecond(0));
+        candidate = match hour_start {
+            Ok(hour_start) => hour_start + time::Duration::HOUR,
+            Err(_) => break,
+        };
+    }
+
+    best
+}
+
+/// Schedules a notification at the user's predicted best time within the window
+pub async fn optimal_send_time<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+) -> Result<OffsetDateTime, sqlx::Error> {
+    Ok(match get_profile(executor, user_id).await? {
+        Some(profile) => best_time_in_window(&profile.hour_scores, start, end),
+        None => start,
+    })
+}
+
+/// The `limit` best hours of the week, best first
+pub fn top_send_times(hour_scores: &[f64], limit: usize) -> Vec<SendTime> {
+    const WEEKDAYS: [&str; 7] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+
+    let mut ranked: Vec<(usize, f64)> = hour_scores.iter().copied().enumerate().collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    ranked
+        .into_iter()
+        .take(limit)
+        .map(|(bucket, score)| SendTime {
+            weekday: WEEKDAYS[bucket / 24].to_string(),
+            hour: (bucket % 24) as u8,
+            score,
+        })
+        .collect()
+}
+
+pub async fn recompute_channel_scores(pool: &PgPool) -> Result<usize, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT n.user_id AS "user_id!", n.delivered_via AS "channel!",
+                  COUNT(*)::INTEGER AS "delivered!",
+                  (COUNT(*) FILTER (WHERE EXISTS (
+                      SELECT 1 FROM notification_events e
+                      WHERE e.notification_id = n.id AND e.event_type IN ('Opened', 'Clicked', 'Read')
+                  )))::INTEGER AS "engaged!"
+           FROM notifications n
+           WHERE n.status = 'Sent'
+             AND n.user_id IS NOT NULL
+             AND n.delivered_via IS NOT NULL
+             AND n.sent_at > NOW() - make_interval(days => $1)
+           GROUP BY n.user_id, n.delivered_via"#,
+        LOOKBACK_DAYS as i32
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut population: HashMap<String, (i32, i32)> = HashMap::new();
+    for row in &rows {
+        let totals = population.entry(row.channel.clone()).or_default();
+        totals.0 += row.delivered;
+        totals.1 += row.engaged;
+    }
+
+    // Laplace-smoothed population rate, used as the prior for every user
+    let population_rate: HashMap<String, f64> = population
+        .iter()
+        .map(|(channel, (delivered, engaged))| (channel.clone(), (*engaged as f64 + 1.0) / (*delivered as f64 + 2.0)))
+        .collect();
+
+    let mut tx = pool.begin().await?;
+
+    for (channel, (delivered, engaged)) in &population {
+        save_channel_score(&mut *tx, GLOBAL_PROFILE, channel, *delivered, *engaged, population_rate[channel]).await?;
+    }
+
+    let mut users = std::collections::HashSet::new();
+    for row in &rows {
+        let prior = population_rate[&row.channel];
+        let score = (row.engaged as f64 + PRIOR_WEIGHT * prior) / (row.delivered as f64 + PRIOR_WEIGHT);
+        save_channel_score(&mut *tx, row.user_id, &row.channel, row.delivered, row.engaged, score).await?;
+        users.insert(row.user_id);
+    }
+
+    // Drop scores for channels that saw no deliveries in the lookback window
+    sqlx::query!("DELETE FROM channel_scores WHERE computed_at < NOW()")
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(users.len())
+}
+
+async fn save_channel_score<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+    channel: &str,
+    delivered: i32,
+    engaged: i32,
+    score: f64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO channel_scores (user_id, channel, delivered, engaged, score, computed_at)
+         VALUES ($1, $2, $3, $4, $5, NOW())
+         ON CONFLICT (user_id, channel) DO UPDATE SET
+             delivered = EXCLUDED.delivered,
+             engaged = EXCLUDED.engaged,
+             score = EXCLUDED.score,
+             computed_at = EXCLUDED.computed_at",
+        user_id,
+        channel,
+        delivered,
+        engaged,
+        score
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Scores for every channel, falling back to the population rate where the user has no history
+pub async fn get_channel_scores<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+) -> Result<Vec<ChannelScore>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT user_id, channel, delivered, engaged, score FROM channel_scores
+         WHERE user_id = $1 OR user_id = $2",
+        user_id,
+        GLOBAL_PROFILE
+    )
+    .fetch_all(executor)
+    .await?;
+
+    let mut scores: HashMap<DeliveryMethod, ChannelScore> = HashMap::new();
+    for row in rows {
+        let channel = match row.channel.parse::<DeliveryMethod>() {
+            Ok(channel) => channel,
+            Err(_) => continue,
+        };
+        let personalized = row.user_id == user_id;
+
+        // A personal row always beats the population row for the same channel
+        if scores.get(&channel).is_some_and(|existing| existing.personalized) {
+            continue;
+        }
+        scores.insert(channel, ChannelScore {
+            channel,
+            delivered: if personalized { row.delivered } else { 0 },
+            engaged: if personalized { row.engaged } else { 0 },
+            score: row.score,
+            personalized,
+        });
+    }
+
+    let mut scores: Vec<ChannelScore> = scores.into_values().collect();
+    scores.sort_by(|a, b| b.score.total_cmp(&a.score));
+    Ok(scores)
+}
+
+/// Orders the enabled channels by predicted engagement; unscored channels keep their preference order at the end
+pub fn rank_channels(enabled: &[DeliveryMethod], scores: &[ChannelScore]) -> Vec<DeliveryMethod> {
+    let score_of = |method: &DeliveryMethod| {
+        scores.iter().find(|s| s.channel == *method).map_or(f64::MIN, |s| s.score)
+    };
+
+    let mut ranked = enabled.to_vec();
+    // Stable sort keeps the user's own order between equally scored channels
+    ranked.sort_by(|a, b| score_of(b).total_cmp(&score_of(a)));
+    ranked
+}
+
+/// Resolves `channel: "auto"`: the user's enabled channels, best predicted engagement first
+pub async fn auto_channels(conn: &mut PgConnection, user_id: Uuid, category: Category) -> Result<Vec<DeliveryMethod>, sqlx::Error> {
+    let prefs = preferences::get_preferences(&mut *conn, user_id).await?;
+    let opt_outs = preferences::get_opt_outs(&mut *conn, user_id).await?;
+    let enabled = preferences::enabled_channels(prefs.as_ref(), &opt_outs, category);
+    let scores = get_channel_scores(&mut *conn, user_id).await?;
+
+    Ok(rank_channels(&enabled, &scores))
+}
+
+/// Each event counts for less the older it is, halving every HALF_LIFE_DAYS
+fn decayed_histogram(timestamps: &[OffsetDateTime], now: OffsetDateTime) -> Vec<f64> {
+    let mut histogram = vec![0.0; HOURS_PER_WEEK];
+
+    for at in timestamps {
+        let age_days = (now - *at).as_seconds_f64().max(0.0) / 86_400.0;
+        histogram[hour_of_week(*at)] += 0.5f64.powf(age_days / HALF_LIFE_DAYS);
+    }
+
+    histogram
+}
+
+/// Spreads each hour a little into its neighbours (wrapping Sunday night into Monday morning)
+fn smooth(histogram: &[f64]) -> Vec<f64> {
+    let n = histogram.len();
+    (0..n)
+        .map(|i| 0.25 * histogram[(i + n - 1) % n] + 0.5 * histogram[i] + 0.25 * histogram[(i + 1) % n])
+        .collect()
+}
+
+fn normalize(histogram: &[f64]) -> Vec<f64> {
+    let total: f64 = histogram.iter().sum();
+    if total <= 0.0 {
+        return vec![1.0 / HOURS_PER_WEEK as f64; HOURS_PER_WEEK];
+    }
+    histogram.iter().map(|weight| weight / total).collect()
+}
+
+/// Additive smoothing towards the population profile
+fn blend_with_prior(histogram: &[f64], prior: &[f64]) -> Vec<f64> {
+    let total: f64 = histogram.iter().sum();
+    histogram
+        .iter()
+        .zip(prior)
+        .map(|(weight, p)| (weight + PRIOR_WEIGHT * p) / (total + PRIOR_WEIGHT))
+        .collect()
+}
@@ -0,0 +1,100 @@
+use std::fmt;
+
+use lettre::{
+    address::AddressError,
+    message::Mailbox,
+    transport::smtp::{authentication::Credentials, PoolConfig},
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+
+use crate::config::Config;
+
+/// Why an email could not be sent
+#[derive(Debug)]
+pub enum MailError {
+    Address(AddressError),
+    Message(lettre::error::Error),
+    Smtp(lettre::transport::smtp::Error),
+}
+
+impl MailError {
+    /// Whether sending again later cannot help
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            MailError::Smtp(e) => e.is_permanent(),
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MailError::Address(e) => write!(f, "invalid address: {}", e),
+            MailError::Message(e) => write!(f, "failed to build email: {}", e),
+            MailError::Smtp(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<AddressError> for MailError {
+    fn from(e: AddressError) -> Self {
+        MailError::Address(e)
+    }
+}
+
+impl From<lettre::error::Error> for MailError {
+    fn from(e: lettre::error::Error) -> Self {
+        MailError::Message(e)
+    }
+}
+
+impl From<lettre::transport::smtp::Error> for MailError {
+    fn from(e: lettre::transport::smtp::Error) -> Self {
+        MailError::Smtp(e)
+    }
+}
+
+/// The process-wide SMTP connection pool. Clones share the same pool.
+#[derive(Clone)]
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl Mailer {
+    pub fn from_config(config: &Config) -> Result<Self, MailError> {
+        let creds = Credentials::new(config.smtp_username.clone(), config.smtp_password.clone());
+
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_server)?
+            .port(config.smtp_port)
+            .credentials(creds)
+            .pool_config(PoolConfig::new().max_size(config.smtp_pool_size))
+            .build();
+
+        Ok(Mailer {
+            transport,
+            from: config.smtp_username.parse()?,
+        })
+    }
+
+    pub fn from(&self) -> &Mailbox {
+        &self.from
+    }
+
+    pub async fn send(&self, message: Message) -> Result<(), MailError> {
+        self.transport.send(message).await?;
+        Ok(())
+    }
+
+    /// Sends a plain text email from the configured sender
+    pub async fn send_text(&self, to: &str, subject: &str, body: String) -> Result<(), MailError> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to.parse()?)
+            .subject(subject)
+            .body(body)?;
+
+        self.send(message).await
+    }
+}
@@ -1,2 +1,24 @@
+pub mod analytics;
+pub mod chat_webhooks;
+pub mod devices;
+pub mod dispatcher;
+pub mod dsn;
+pub mod engagement;
+pub mod mailer;
 pub mod notification;
-pub mod user;
\ No newline at end of file
+pub mod outbox;
+pub mod phone;
+pub mod phone_verification;
+pub mod preferences;
+pub mod preview;
+pub mod push_servers;
+pub mod rules;
+pub mod suppression;
+pub mod telegram;
+pub mod template;
+pub mod throttle;
+pub mod tracking;
+pub mod user;
+pub mod web_push;
+pub mod webhooks;
+pub mod workflow;
@@ -1,27 +1,66 @@
-use crate::db::models::Notification;
+use crate::db::models::{DeliveryMethod, Notification, Priority};
 use log::{error, info};
-use sqlx::PgPool;
+use sqlx::types::Json;
+use sqlx::{PgExecutor, PgPool};
+use time::{OffsetDateTime, Time};
+use uuid::Uuid;
 
-pub async fn create_notification(
-    pool: &PgPool,
+/// When the dispatcher will first pick a notification up; `None` means right away
+///
+/// An explicit send time always wins. Otherwise Low and Normal notifications wait for the next
+/// occurrence of the user's preferred time of day (UTC), while High and Critical ones never wait.
+pub fn resolve_send_at(
+    requested: Option<OffsetDateTime>,
+    priority: Priority,
+    preferred_time: Option<Time>,
+    now: OffsetDateTime,
+) -> Option<OffsetDateTime> {
+    if requested.is_some() || priority >= Priority::High {
+        return requested;
+    }
+
+    let today = now.to_offset(time::UtcOffset::UTC).replace_time(preferred_time?);
+    Some(if today >= now { today } else { today + time::Duration::DAY })
+}
+
+pub async fn create_notification<'e>(
+    executor: impl PgExecutor<'e>,
     notification: Notification,
-) -> Result<(), sqlx::Error> {
+) -> Result<Uuid, sqlx::Error> {
     info!("Creating notification for user: {}", notification.user_id);
 
+    let channels = notification
+        .channels
+        .as_ref()
+        .map(|chain| chain.iter().map(|m| m.as_str().to_string()).collect::<Vec<_>>());
+
     let result = sqlx::query!(
-        "INSERT INTO notifications (user_id, content, send_at, status) 
-         VALUES ($1, $2, $3, 'Pending')",
+        "INSERT INTO notifications (user_id, content, html_content, send_at, channels, category, template, experiment_id, variant, sms_max_segments, sms_overflow, title, actions, priority, voice_acknowledge, status) 
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, 'Pending')
+         RETURNING id",
         notification.user_id,
         notification.content,
-        notification.send_at
+        notification.html_content,
+        notification.send_at,
+        channels.as_deref(),
+        notification.category.as_str(),
+        notification.template,
+        notification.experiment_id,
+        notification.variant,
+        notification.sms_max_segments,
+        notification.sms_overflow.map(|overflow| overflow.as_str()),
+        notification.title,
+        notification.actions.as_ref().map(Json) as _,
+        notification.priority.as_str(),
+        notification.voice_acknowledge
     )
-    .execute(pool)
+    .fetch_one(executor)
     .await;
 
     match result {
-        Ok(_) => {
+        Ok(row) => {
             info!("Notification created successfully for user: {}", notification.user_id);
-            Ok(())
+            Ok(row.id)
         }
         Err(e) => {
             error!("Failed to create notification for user {}: {:?}", notification.user_id, e);
@@ -29,3 +68,93 @@ pub async fn create_notification(
         }
     }
 }
+
+/// Appends an entry to the notification's delivery history
+pub async fn record_event<'e>(
+    executor: impl PgExecutor<'e>,
+    notification_id: Uuid,
+    channel: Option<DeliveryMethod>,
+    event_type: &str,
+    detail: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO notification_events (notification_id, channel, event_type, detail)
+         VALUES ($1, $2, $3, $4)",
+        notification_id,
+        channel.map(|c| c.as_str()),
+        event_type,
+        detail
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Records that the recipient read the notification; returns false if it is not theirs
+pub async fn mark_read(pool: &PgPool, notification_id: Uuid, user_id: Uuid) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT delivered_via FROM notifications WHERE id = $1 AND user_id = $2",
+        notification_id,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    match row {
+        Some(row) => {
+            let channel = row.delivered_via.and_then(|c| c.parse::<DeliveryMethod>().ok());
+            record_event(pool, notification_id, channel, "Read", None).await?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Records that the recipient acknowledged the notification, e.g. by pressing 1 during a voice
+/// call; returns false if it had already been acknowledged or does not exist
+pub async fn acknowledge(pool: &PgPool, notification_id: Uuid, channel: DeliveryMethod) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query!(
+        "UPDATE notifications SET acknowledged_at = NOW() WHERE id = $1 AND acknowledged_at IS NULL",
+        notification_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    record_event(&mut *tx, notification_id, Some(channel), "Acknowledged", None).await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::{datetime, time};
+
+    const NOW: OffsetDateTime = datetime!(2024-03-10 15:30 UTC);
+
+    #[test]
+    fn explicit_send_time_wins() {
+        let requested = Some(datetime!(2024-03-11 08:00 UTC));
+        assert_eq!(resolve_send_at(requested, Priority::Normal, Some(time!(9:00)), NOW), requested);
+    }
+
+    #[test]
+    fn routine_notifications_wait_for_the_preferred_time() {
+        assert_eq!(resolve_send_at(None, Priority::Normal, Some(time!(18:00)), NOW), Some(datetime!(2024-03-10 18:00 UTC)));
+        assert_eq!(resolve_send_at(None, Priority::Low, Some(time!(9:00)), NOW), Some(datetime!(2024-03-11 9:00 UTC)));
+        assert_eq!(resolve_send_at(None, Priority::Normal, None, NOW), None);
+    }
+
+    #[test]
+    fn urgent_notifications_never_wait() {
+        assert_eq!(resolve_send_at(None, Priority::High, Some(time!(9:00)), NOW), None);
+        assert_eq!(resolve_send_at(None, Priority::Critical, Some(time!(9:00)), NOW), None);
+    }
+}
@@ -0,0 +1,152 @@
+use std::time::Duration;
+
+use log::{error, info, warn};
+use sqlx::{PgExecutor, PgPool};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::services::mailer::Mailer;
+use crate::services::suppression;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const BATCH_SIZE: i64 = 20;
+const MAX_ATTEMPTS: i32 = 8;
+/// Comfortably longer than an SMTP exchange, including connecting and the server's timeouts
+const LEASE: Duration = Duration::from_secs(5 * 60);
+
+/// Queues a plain text email for the outbox worker
+///
+/// Pass the transaction that makes the change the email is about, so the email exists if and
+/// only if that change is committed.
+pub async fn enqueue<'e>(
+    executor: impl PgExecutor<'e>,
+    recipient: &str,
+    subject: &str,
+    body: &str,
+) -> Result<Uuid, sqlx::Error> {
+    sqlx::query_scalar!(
+        "INSERT INTO email_outbox (recipient, subject, body) VALUES ($1, $2, $3) RETURNING id",
+        recipient,
+        subject,
+        body
+    )
+    .fetch_one(executor)
+    .await
+}
+
+/// Polls the outbox and sends due emails until the process exits
+pub async fn run(pool: PgPool, mailer: Mailer) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = send_due(&pool, &mailer).await {
+            error!("Failed to process email outbox: {:?}", e);
+        }
+    }
+}
+
+struct OutboxEmail {
+    id: Uuid,
+    recipient: String,
+    subject: String,
+    body: String,
+    attempts: i32,
+}
+
+async fn send_due(pool: &PgPool, mailer: &Mailer) -> Result<(), sqlx::Error> {
+    // Each email is claimed and settled in its own statements, so no transaction is open during
+    // the SMTP exchange and a later database error cannot undo the record of a sent email
+    for _ in 0..BATCH_SIZE {
+        let email = match claim_next(pool).await? {
+            Some(email) => email,
+            None => break,
+        };
+
+        // Bounced and complained addresses stay off limits for account mail too
+        if let Some(reason) = suppression::find_suppression(pool, &email.recipient).await? {
+            mark_failed(pool, &email, &format!("email address is suppressed ({})", reason)).await?;
+            continue;
+        }
+
+        match mailer.send_text(&email.recipient, &email.subject, email.body.clone()).await {
+            Ok(()) => mark_sent(pool, email.id).await?,
+            Err(e) if e.is_permanent() => mark_failed(pool, &email, &e.to_string()).await?,
+            Err(e) => retry_later(pool, &email, &e.to_string()).await?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Takes the oldest due email by pushing its next attempt out by LEASE
+///
+/// SKIP LOCKED and the lease let several instances run the worker without sending twice; if this
+/// one dies before recording the outcome, the email comes due again once the lease runs out.
+async fn claim_next(pool: &PgPool) -> Result<Option<OutboxEmail>, sqlx::Error> {
+    sqlx::query_as!(
+        OutboxEmail,
+        "UPDATE email_outbox SET next_attempt_at = NOW() + make_interval(secs => $1)
+         WHERE id = (
+             SELECT id FROM email_outbox
+             WHERE status = 'Pending' AND next_attempt_at <= NOW()
+             ORDER BY next_attempt_at
+             LIMIT 1
+             FOR UPDATE SKIP LOCKED
+         )
+         RETURNING id, recipient, subject, body, attempts",
+        LEASE.as_secs_f64()
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+async fn mark_sent<'e>(executor: impl PgExecutor<'e>, id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE email_outbox SET status = 'Sent', attempts = attempts + 1, sent_at = NOW(), last_error = NULL WHERE id = $1",
+        id
+    )
+    .execute(executor)
+    .await?;
+
+    info!("Outbox email {} sent", id);
+    Ok(())
+}
+
+async fn retry_later<'e>(executor: impl PgExecutor<'e>, email: &OutboxEmail, reason: &str) -> Result<(), sqlx::Error> {
+    let attempts = email.attempts + 1;
+
+    if attempts >= MAX_ATTEMPTS {
+        return mark_failed(executor, email, reason).await;
+    }
+
+    // Exponential backoff: 1, 2, 4 ... 64 minutes
+    let retry_at = OffsetDateTime::now_utc() + time::Duration::minutes(1 << (attempts - 1));
+
+    sqlx::query!(
+        "UPDATE email_outbox SET attempts = $2, next_attempt_at = $3, last_error = $4 WHERE id = $1",
+        email.id,
+        attempts,
+        retry_at,
+        reason
+    )
+    .execute(executor)
+    .await?;
+
+    warn!("Outbox email {} will be retried at {} ({})", email.id, retry_at, reason);
+    Ok(())
+}
+
+async fn mark_failed<'e>(executor: impl PgExecutor<'e>, email: &OutboxEmail, reason: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE email_outbox SET status = 'Failed', attempts = attempts + 1, last_error = $2 WHERE id = $1",
+        email.id,
+        reason
+    )
+    .execute(executor)
+    .await?;
+
+    error!("Outbox email {} to {} failed: {}", email.id, email.recipient, reason);
+    Ok(())
+}
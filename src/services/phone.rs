@@ -0,0 +1,99 @@
+use phonenumber::{country, metadata::DATABASE, Mode, ParseError, PhoneNumber, Type};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// A phone number in E.164 form with what we know about it
+pub struct NormalizedPhone {
+    pub e164: String,
+    pub country_code: i32,
+    pub region: Option<String>,
+    pub number_type: &'static str,
+}
+
+/// Why a phone number was rejected, returned as the body of a 400
+#[derive(Debug, Serialize, ToSchema)]
+pub struct InvalidPhoneNumber {
+    pub error: &'static str,  // Always "invalid_phone_number"
+    pub reason: &'static str,  // not_a_number, invalid_region, missing_country_code, too_short, too_long or invalid_number
+    pub message: String,
+}
+
+impl InvalidPhoneNumber {
+    fn new(reason: &'static str, message: impl Into<String>) -> Self {
+        InvalidPhoneNumber {
+            error: "invalid_phone_number",
+            reason,
+            message: message.into(),
+        }
+    }
+}
+
+/// Number types that are known to be landlines and cannot receive SMS
+pub fn is_landline(number_type: &str) -> bool {
+    number_type == "FixedLine"
+}
+
+/// Parses an ISO 3166-1 alpha-2 region such as "GB"
+pub fn parse_region(region: &str) -> Result<country::Id, InvalidPhoneNumber> {
+    region
+        .trim()
+        .to_ascii_uppercase()
+        .parse()
+        .map_err(|_| InvalidPhoneNumber::new("invalid_region", format!("Unknown region: {}", region)))
+}
+
+/// Parses `input` and normalizes it to E.164
+///
+/// Numbers without a `+` country code are read as national numbers in `region`.
+pub fn normalize(input: &str, region: Option<country::Id>) -> Result<NormalizedPhone, InvalidPhoneNumber> {
+    let number = phonenumber::parse(region, input).map_err(|e| match e {
+        ParseError::InvalidCountryCode if region.is_none() && !input.trim_start().starts_with('+') => {
+            InvalidPhoneNumber::new(
+                "missing_country_code",
+                "Phone number needs a +country code, or a region to read it in",
+            )
+        }
+        ParseError::InvalidCountryCode => InvalidPhoneNumber::new("invalid_number", "Unknown country code"),
+        ParseError::TooShortAfterIdd | ParseError::TooShortNsn => {
+            InvalidPhoneNumber::new("too_short", "Phone number is too short")
+        }
+        ParseError::TooLong => InvalidPhoneNumber::new("too_long", "Phone number is too long"),
+        _ => InvalidPhoneNumber::new("not_a_number", "Not a phone number"),
+    })?;
+
+    if !number.is_valid() {
+        return Err(InvalidPhoneNumber::new(
+            "invalid_number",
+            "Phone number is not valid for its region",
+        ));
+    }
+
+    Ok(NormalizedPhone {
+        e164: number.format().mode(Mode::E164).to_string(),
+        country_code: i32::from(number.country().code()),
+        region: number.country().id().map(|id| id.as_ref().to_string()),
+        number_type: type_name(&number),
+    })
+}
+
+fn type_name(number: &PhoneNumber) -> &'static str {
+    match number.number_type(&DATABASE) {
+        Type::FixedLine => "FixedLine",
+        Type::Mobile => "Mobile",
+        Type::FixedLineOrMobile => "FixedLineOrMobile",
+        Type::TollFree => "TollFree",
+        Type::PremiumRate => "PremiumRate",
+        Type::SharedCost => "SharedCost",
+        Type::PersonalNumber => "PersonalNumber",
+        Type::Voip => "Voip",
+        Type::Pager => "Pager",
+        Type::Uan => "Uan",
+        Type::Emergency => "Emergency",
+        Type::Voicemail => "Voicemail",
+        Type::ShortCode => "ShortCode",
+        Type::StandardRate => "StandardRate",
+        Type::Carrier => "Carrier",
+        Type::NoInternational => "NoInternational",
+        Type::Unknown => "Unknown",
+    }
+}
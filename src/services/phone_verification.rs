@@ -0,0 +1,153 @@
+use std::fmt;
+
+use bcrypt::{hash, verify, DEFAULT_COST};
+use sqlx::PgPool;
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+/// How long a code can be used after it is sent
+pub const CODE_TTL: Duration = Duration::minutes(10);
+/// Wrong guesses allowed per code
+pub const MAX_ATTEMPTS: i32 = 5;
+/// Minimum time between two codes for the same user
+pub const RESEND_COOLDOWN: Duration = Duration::seconds(60);
+
+pub enum StartError {
+    TooSoon,
+    Database(sqlx::Error),
+    Hash(bcrypt::BcryptError),
+}
+
+pub enum ConfirmError {
+    NoActiveCode,
+    Expired,
+    TooManyAttempts,
+    Mismatch { attempts_left: i32 },
+    Database(sqlx::Error),
+    Hash(bcrypt::BcryptError),
+}
+
+impl fmt::Display for StartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StartError::TooSoon => f.write_str("a code was sent too recently"),
+            StartError::Database(e) => write!(f, "database error: {}", e),
+            StartError::Hash(e) => write!(f, "failed to hash code: {}", e),
+        }
+    }
+}
+
+impl fmt::Display for ConfirmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfirmError::NoActiveCode => f.write_str("no code has been requested"),
+            ConfirmError::Expired => f.write_str("the code has expired"),
+            ConfirmError::TooManyAttempts => f.write_str("too many attempts"),
+            ConfirmError::Mismatch { attempts_left } => write!(f, "wrong code, {} attempts left", attempts_left),
+            ConfirmError::Database(e) => write!(f, "database error: {}", e),
+            ConfirmError::Hash(e) => write!(f, "failed to check code: {}", e),
+        }
+    }
+}
+
+impl From<sqlx::Error> for StartError {
+    fn from(e: sqlx::Error) -> Self {
+        StartError::Database(e)
+    }
+}
+
+impl From<sqlx::Error> for ConfirmError {
+    fn from(e: sqlx::Error) -> Self {
+        ConfirmError::Database(e)
+    }
+}
+
+/// A random six-digit code; v4 UUIDs come from the OS CSPRNG
+fn generate_code() -> String {
+    format!("{:06}", Uuid::new_v4().as_u128() % 1_000_000)
+}
+
+/// Replaces any pending code with a new one and returns it in plain text for sending
+pub async fn issue_code(pool: &PgPool, user_id: Uuid) -> Result<String, StartError> {
+    let code = generate_code();
+    let code_hash = hash(&code, DEFAULT_COST).map_err(StartError::Hash)?;
+    let now = OffsetDateTime::now_utc();
+
+    // The cooldown check and the write are one statement so parallel requests cannot both pass
+    let result = sqlx::query!(
+        "UPDATE users
+         SET phone_verification_code = $2, phone_verification_expires_at = $3,
+             phone_verification_sent_at = $4, phone_verification_attempts = 0
+         WHERE id = $1 AND (phone_verification_sent_at IS NULL OR phone_verification_sent_at <= $5)",
+        user_id,
+        code_hash,
+        now + CODE_TTL,
+        now,
+        now - RESEND_COOLDOWN
+    )
+    .execute(pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(StartError::TooSoon);
+    }
+    Ok(code)
+}
+
+/// Forgets a code that could not be delivered, so the user can ask again straight away
+pub async fn revoke_code(pool: &PgPool, user_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE users
+         SET phone_verification_code = NULL, phone_verification_expires_at = NULL, phone_verification_sent_at = NULL
+         WHERE id = $1",
+        user_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Checks `code` against the pending one and marks the phone number verified if it matches
+pub async fn confirm_code(pool: &PgPool, user_id: Uuid, code: &str) -> Result<(), ConfirmError> {
+    // Count the attempt before checking it, so concurrent guesses cannot exceed the limit
+    let pending = sqlx::query!(
+        r#"UPDATE users
+           SET phone_verification_attempts = phone_verification_attempts + 1
+           WHERE id = $1 AND phone_verification_code IS NOT NULL
+           RETURNING phone_verification_code AS "code_hash!", phone_verification_expires_at, phone_verification_attempts"#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or(ConfirmError::NoActiveCode)?;
+
+    if pending.phone_verification_attempts > MAX_ATTEMPTS {
+        return Err(ConfirmError::TooManyAttempts);
+    }
+    if pending.phone_verification_expires_at.is_none_or(|expires_at| expires_at <= OffsetDateTime::now_utc()) {
+        return Err(ConfirmError::Expired);
+    }
+    if !verify(code.trim(), &pending.code_hash).map_err(ConfirmError::Hash)? {
+        return Err(ConfirmError::Mismatch {
+            attempts_left: MAX_ATTEMPTS - pending.phone_verification_attempts,
+        });
+    }
+
+    // Changing the number clears the code, so a number changed since the check is never marked verified
+    let result = sqlx::query!(
+        "UPDATE users
+         SET phone_verified = TRUE, phone_verification_code = NULL, phone_verification_expires_at = NULL,
+             phone_verification_attempts = 0
+         WHERE id = $1 AND phone_verification_code = $2",
+        user_id,
+        pending.code_hash
+    )
+    .execute(pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(ConfirmError::NoActiveCode);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use log::warn;
+use sqlx::PgExecutor;
+use time::Time;
+use uuid::Uuid;
+
+use crate::db::models::{Category, DeliveryMethod, OptOut, UserPreferences};
+
+pub async fn get_preferences<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+) -> Result<Option<UserPreferences>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT preferred_method, fallback_chain FROM user_preferences WHERE user_id = $1",
+        user_id
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(row.map(|row| UserPreferences {
+        preferred_method: row.preferred_method.parse().unwrap_or(DeliveryMethod::Email),
+        fallback_chain: row.fallback_chain.map(|chain| parse_methods(&chain)),
+    }))
+}
+
+/// The time of day (UTC) the user would like routine notifications delivered at, if they set one
+pub async fn get_preferred_time<'e>(executor: impl PgExecutor<'e>, user_id: Uuid) -> Result<Option<Time>, sqlx::Error> {
+    let row = sqlx::query!("SELECT preferred_time FROM user_preferences WHERE user_id = $1", user_id)
+        .fetch_optional(executor)
+        .await?;
+
+    Ok(row.and_then(|row| row.preferred_time))
+}
+
+pub async fn upsert_preferences<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+    preferred_method: Option<DeliveryMethod>,
+    fallback_chain: Option<Vec<DeliveryMethod>>,
+) -> Result<UserPreferences, sqlx::Error> {
+    let preferred_method = preferred_method.map(|m| m.as_str().to_string());
+    let fallback_chain = fallback_chain.map(|chain| chain.iter().map(|m| m.as_str().to_string()).collect::<Vec<_>>());
+
+    let row = sqlx::query!(
+        "INSERT INTO user_preferences (user_id, preferred_method, fallback_chain)
+         VALUES ($1, COALESCE($2, 'Email'), $3)
+         ON CONFLICT (user_id) DO UPDATE SET
+             preferred_method = COALESCE($2, user_preferences.preferred_method),
+             fallback_chain = COALESCE($3, user_preferences.fallback_chain)
+         RETURNING preferred_method, fallback_chain",
+        user_id,
+        preferred_method,
+        fallback_chain.as_deref()
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(UserPreferences {
+        preferred_method: row.preferred_method.parse().unwrap_or(DeliveryMethod::Email),
+        fallback_chain: row.fallback_chain.map(|chain| parse_methods(&chain)),
+    })
+}
+
+pub async fn get_opt_outs<'e>(executor: impl PgExecutor<'e>, user_id: Uuid) -> Result<Vec<OptOut>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT category, channel FROM notification_opt_outs WHERE user_id = $1 ORDER BY created_at",
+        user_id
+    )
+    .fetch_all(executor)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| {
+            Some(OptOut {
+                category: row.category.parse().ok()?,
+                channel: match row.channel {
+                    Some(channel) => Some(channel.parse().ok()?),
+                    None => None,
+                },
+            })
+        })
+        .collect())
+}
+
+/// Records an opt-out; callers must reject non-optional categories first
+pub async fn add_opt_out<'e>(executor: impl PgExecutor<'e>, user_id: Uuid, opt_out: &OptOut) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO notification_opt_outs (user_id, category, channel)
+         VALUES ($1, $2, $3)
+         ON CONFLICT (user_id, category, COALESCE(channel, '')) DO NOTHING",
+        user_id,
+        opt_out.category.as_str(),
+        opt_out.channel.map(|c| c.as_str())
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn remove_opt_out<'e>(executor: impl PgExecutor<'e>, user_id: Uuid, opt_out: &OptOut) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM notification_opt_outs
+         WHERE user_id = $1 AND category = $2 AND channel IS NOT DISTINCT FROM $3",
+        user_id,
+        opt_out.category.as_str(),
+        opt_out.channel.map(|c| c.as_str())
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Whether the user has opted out of `category` on `method`, either directly or on all channels
+pub fn is_opted_out(opt_outs: &[OptOut], category: Category, method: DeliveryMethod) -> bool {
+    category.is_optional()
+        && opt_outs
+            .iter()
+            .any(|o| o.category == category && o.channel.is_none_or(|channel| channel == method))
+}
+
+/// Channels the user accepts this category on: their configured chain (or every channel
+/// if they never set preferences), minus any they opted out of
+pub fn enabled_channels(
+    preferences: Option<&UserPreferences>,
+    opt_outs: &[OptOut],
+    category: Category,
+) -> Vec<DeliveryMethod> {
+    let candidates = match preferences {
+        Some(prefs) => resolve_chain(None, Some(prefs)),
+        None => vec![
+            DeliveryMethod::Email,
+            DeliveryMethod::Push,
+            DeliveryMethod::Sms,
+            DeliveryMethod::Webhook,
+            DeliveryMethod::Slack,
+            DeliveryMethod::Discord,
+            DeliveryMethod::Teams,
+            DeliveryMethod::Telegram,
+            DeliveryMethod::Ntfy,
+            DeliveryMethod::Gotify,
+        ],
+    };
+
+    candidates
+        .into_iter()
+        .filter(|method| !is_opted_out(opt_outs, category, *method))
+        .collect()
+}
+
+/// Parses stored channel names, dropping any this build does not know about
+pub fn parse_methods(values: &[String]) -> Vec<DeliveryMethod> {
+    values
+        .iter()
+        .filter_map(|value| match value.parse() {
+            Ok(method) => Some(method),
+            Err(e) => {
+                warn!("Ignoring stored channel: {}", e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Works out the ordered list of channels to try for a notification.
+///
+/// An explicit chain on the notification wins, then the user's fallback chain,
+/// then their single preferred method, and finally plain email.
+pub fn resolve_chain(explicit: Option<&[DeliveryMethod]>, preferences: Option<&UserPreferences>) -> Vec<DeliveryMethod> {
+    let chain: Vec<DeliveryMethod> = match (explicit, preferences) {
+        (Some(chain), _) if !chain.is_empty() => chain.to_vec(),
+        (_, Some(prefs)) => match &prefs.fallback_chain {
+            Some(chain) if !chain.is_empty() => chain.clone(),
+            _ => vec![prefs.preferred_method],
+        },
+        _ => vec![DeliveryMethod::Email],
+    };
+
+    // A channel listed twice would only be retried pointlessly
+    let mut resolved = Vec::with_capacity(chain.len());
+    for method in chain {
+        if !resolved.contains(&method) {
+            resolved.push(method);
+        }
+    }
+    resolved
+}
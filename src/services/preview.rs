@@ -0,0 +1,89 @@
+use serde::Serialize;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::channels::{Channels, DeliveryError, RenderedMessage};
+use crate::db::models::{DeliveryMethod, PendingNotification, User};
+use crate::services::preferences;
+
+/// What would happen on one channel of the fallback chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+pub enum PreviewOutcome {
+    Deliverable,
+    OptedOut,
+    Unreachable,
+    Failed,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ChannelPreview {
+    pub channel: DeliveryMethod,
+    pub outcome: PreviewOutcome,
+    pub reason: Option<String>,
+    pub message: Option<RenderedMessage>,  // Absent when opted out or rendering failed
+}
+
+/// Walks the chain the way the dispatcher would, rendering instead of sending.
+///
+/// Returns the channel the dispatcher would try to deliver through first, if any, and
+/// what every channel in the chain would do.
+pub async fn preview_chain(
+    pool: &PgPool,
+    channels: &Channels,
+    recipient: &User,
+    pending: &PendingNotification,
+) -> Result<(Option<DeliveryMethod>, Vec<ChannelPreview>), sqlx::Error> {
+    let explicit = pending.channels.as_deref().map(preferences::parse_methods);
+    let prefs = preferences::get_preferences(pool, pending.user_id).await?;
+    let chain = preferences::resolve_chain(explicit.as_deref(), prefs.as_ref());
+    let opt_outs = preferences::get_opt_outs(pool, pending.user_id).await?;
+    let category = pending.category();
+
+    let mut resolved = None;
+    let mut previews = Vec::with_capacity(chain.len());
+
+    for method in chain {
+        if preferences::is_opted_out(&opt_outs, category, method) {
+            previews.push(ChannelPreview {
+                channel: method,
+                outcome: PreviewOutcome::OptedOut,
+                reason: Some(format!("user opted out of {} notifications", category)),
+                message: None,
+            });
+            continue;
+        }
+
+        // Render even when the channel would fail so template authors still see the output
+        let (outcome, mut reason) = match channels.check(method, recipient).await {
+            Ok(()) => (PreviewOutcome::Deliverable, None),
+            Err(e @ DeliveryError::Unreachable(_)) => (PreviewOutcome::Unreachable, Some(e.to_string())),
+            Err(e) => (PreviewOutcome::Failed, Some(e.to_string())),
+        };
+
+        let message = match channels.render(method, recipient, pending) {
+            Ok(message) => Some(message),
+            Err(e) => {
+                reason.get_or_insert(e.to_string());
+                None
+            }
+        };
+
+        let outcome = if outcome == PreviewOutcome::Deliverable && message.is_none() {
+            PreviewOutcome::Failed
+        } else {
+            outcome
+        };
+        if outcome == PreviewOutcome::Deliverable {
+            resolved.get_or_insert(method);
+        }
+
+        previews.push(ChannelPreview {
+            channel: method,
+            outcome,
+            reason,
+            message,
+        });
+    }
+
+    Ok((resolved, previews))
+}
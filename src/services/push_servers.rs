@@ -0,0 +1,112 @@
+use serde::Serialize;
+use sqlx::PgExecutor;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::db::models::DeliveryMethod;
+
+/// A user's ntfy or Gotify server; the token is never returned
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PushServer {
+    pub id: Uuid,
+    pub provider: String,  // Ntfy or Gotify
+    pub server_url: String,
+    pub topic: Option<String>,
+    pub has_token: bool,
+    pub active: bool,
+    pub last_error: Option<String>,
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+/// Where the dispatcher publishes a user's notifications for one self-hosted service
+pub struct PushServerTarget {
+    pub id: Uuid,
+    pub server_url: String,
+    pub topic: Option<String>,
+    pub token: Option<String>,
+}
+
+/// Sets the user's server for `provider`, replacing and reactivating any earlier one
+pub async fn set_server<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+    provider: DeliveryMethod,
+    server_url: &str,
+    topic: Option<&str>,
+    token: Option<&str>,
+) -> Result<PushServer, sqlx::Error> {
+    sqlx::query_as!(
+        PushServer,
+        r#"INSERT INTO push_servers (user_id, provider, server_url, topic, token)
+           VALUES ($1, $2, $3, $4, $5)
+           ON CONFLICT (user_id, provider) DO UPDATE
+           SET server_url = EXCLUDED.server_url, topic = EXCLUDED.topic, token = EXCLUDED.token,
+               active = TRUE, last_error = NULL, updated_at = NOW()
+           RETURNING id, provider, server_url, topic, token IS NOT NULL AS "has_token!", active, last_error,
+                     created_at, updated_at"#,
+        user_id,
+        provider.as_str(),
+        server_url,
+        topic,
+        token
+    )
+    .fetch_one(executor)
+    .await
+}
+
+/// Removes the user's server for `provider`; returns false if they had none
+pub async fn remove_server<'e>(executor: impl PgExecutor<'e>, user_id: Uuid, provider: DeliveryMethod) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM push_servers WHERE user_id = $1 AND provider = $2",
+        user_id,
+        provider.as_str()
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn list_servers<'e>(executor: impl PgExecutor<'e>, user_id: Uuid) -> Result<Vec<PushServer>, sqlx::Error> {
+    sqlx::query_as!(
+        PushServer,
+        r#"SELECT id, provider, server_url, topic, token IS NOT NULL AS "has_token!", active, last_error,
+                  created_at, updated_at
+           FROM push_servers
+           WHERE user_id = $1
+           ORDER BY provider"#,
+        user_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+pub async fn active_server<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+    provider: DeliveryMethod,
+) -> Result<Option<PushServerTarget>, sqlx::Error> {
+    sqlx::query_as!(
+        PushServerTarget,
+        "SELECT id, server_url, topic, token FROM push_servers WHERE user_id = $1 AND provider = $2 AND active",
+        user_id,
+        provider.as_str()
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// Stops publishing to a server that rejected the token or no longer has the topic or application
+pub async fn deactivate_server<'e>(executor: impl PgExecutor<'e>, server_id: Uuid, reason: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE push_servers SET active = FALSE, last_error = $2, updated_at = NOW() WHERE id = $1",
+        server_id,
+        reason
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
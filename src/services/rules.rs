@@ -0,0 +1,231 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::PgPool;
+use sqlx::types::Json;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::db::models::Category;
+use crate::services::template::{self, InstantiateError, Template};
+use crate::services::{notification, workflow};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ConditionOp {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    In,        // `value` is an array containing the property
+    Contains,  // The property is a string or array containing `value`
+    Exists,
+}
+
+/// A test against one event property; `property` may be a dotted path
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Condition {
+    pub property: String,
+    pub op: ConditionOp,
+    #[schema(value_type = Object)]
+    #[serde(default)]
+    pub value: Value,
+}
+
+impl Condition {
+    pub fn matches(&self, properties: &Value) -> bool {
+        let actual = template::lookup(properties, &self.property);
+
+        match (self.op, actual) {
+            (ConditionOp::Exists, actual) => actual.is_some_and(|v| !v.is_null()),
+            (ConditionOp::Ne, actual) => actual != Some(&self.value),
+            (_, None) => false,
+            (ConditionOp::Eq, Some(actual)) => *actual == self.value,
+            (ConditionOp::Gt, Some(actual)) => compare(actual, &self.value).is_some_and(|o| o.is_gt()),
+            (ConditionOp::Gte, Some(actual)) => compare(actual, &self.value).is_some_and(|o| o.is_ge()),
+            (ConditionOp::Lt, Some(actual)) => compare(actual, &self.value).is_some_and(|o| o.is_lt()),
+            (ConditionOp::Lte, Some(actual)) => compare(actual, &self.value).is_some_and(|o| o.is_le()),
+            (ConditionOp::In, Some(actual)) => self.value.as_array().is_some_and(|values| values.contains(actual)),
+            (ConditionOp::Contains, Some(Value::Array(items))) => items.contains(&self.value),
+            (ConditionOp::Contains, Some(Value::String(s))) => self.value.as_str().is_some_and(|needle| s.contains(needle)),
+            (ConditionOp::Contains, Some(_)) => false,
+        }
+    }
+}
+
+/// Numbers compare numerically and strings lexically; anything else is incomparable
+fn compare(a: &Value, b: &Value) -> Option<std::cmp::Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Rule {
+    pub id: Uuid,
+    pub name: String,
+    pub event_type: String,
+    pub conditions: Vec<Condition>,
+    pub template_key: String,
+    pub channel: Option<String>,  // A delivery method, "auto", or null for the user's preferences
+    pub delay_seconds: i32,
+    pub enabled: bool,
+}
+
+pub struct NewRule {
+    pub name: String,
+    pub event_type: String,
+    pub conditions: Vec<Condition>,
+    pub template_key: String,
+    pub channel: Option<String>,
+    pub delay_seconds: i32,
+}
+
+pub async fn list_rules(pool: &PgPool) -> Result<Vec<Rule>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT id, name, event_type, conditions AS "conditions: Json<Vec<Condition>>", template_key, channel, delay_seconds, enabled
+           FROM notification_rules
+           ORDER BY event_type, created_at"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| Rule {
+            id: row.id,
+            name: row.name,
+            event_type: row.event_type,
+            conditions: row.conditions.0,
+            template_key: row.template_key,
+            channel: row.channel,
+            delay_seconds: row.delay_seconds,
+            enabled: row.enabled,
+        })
+        .collect())
+}
+
+pub async fn create_rule(pool: &PgPool, rule: NewRule) -> Result<Rule, sqlx::Error> {
+    let row = sqlx::query!(
+        "INSERT INTO notification_rules (name, event_type, conditions, template_key, channel, delay_seconds)
+         VALUES ($1, $2, $3, $4, $5, $6)
+         RETURNING id",
+        rule.name,
+        rule.event_type,
+        Json(&rule.conditions) as _,
+        rule.template_key,
+        rule.channel,
+        rule.delay_seconds
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(Rule {
+        id: row.id,
+        name: rule.name,
+        event_type: rule.event_type,
+        conditions: rule.conditions,
+        template_key: rule.template_key,
+        channel: rule.channel,
+        delay_seconds: rule.delay_seconds,
+        enabled: true,
+    })
+}
+
+pub async fn set_rule_enabled(pool: &PgPool, rule_id: Uuid, enabled: bool) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!("UPDATE notification_rules SET enabled = $2 WHERE id = $1", rule_id, enabled)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn delete_rule(pool: &PgPool, rule_id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!("DELETE FROM notification_rules WHERE id = $1", rule_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// What ingesting one event produced
+pub struct IngestedEvent {
+    pub event_id: Uuid,
+    pub notifications: Vec<Uuid>,
+    pub workflow_runs: Vec<Uuid>,
+}
+
+/// Stores an event, creates a notification for every enabled rule it satisfies and starts or
+/// exits the workflows it is a trigger or exit event for.
+///
+/// Everything happens in one transaction, so a failure leaves nothing behind and the producer
+/// can safely retry the event.
+pub async fn ingest_event(
+    pool: &PgPool,
+    event_type: &str,
+    user_id: Uuid,
+    properties: &Value,
+) -> Result<IngestedEvent, InstantiateError> {
+    let mut tx = pool.begin().await?;
+
+    let event = sqlx::query!(
+        "INSERT INTO events (event_type, user_id, properties) VALUES ($1, $2, $3) RETURNING id",
+        event_type,
+        user_id,
+        properties
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let rules = sqlx::query!(
+        r#"SELECT r.id, r.conditions AS "conditions: Json<Vec<Condition>>", r.channel, r.delay_seconds,
+                  t.key, t.content, t.html_content, t.category, t.experiment_id, t.updated_at
+           FROM notification_rules r
+           JOIN templates t ON t.key = r.template_key
+           WHERE r.enabled AND r.event_type = $1
+           ORDER BY r.created_at"#,
+        event_type
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut notifications = Vec::new();
+
+    for rule in rules {
+        if !rule.conditions.iter().all(|condition| condition.matches(properties)) {
+            continue;
+        }
+
+        let template = Template {
+            key: rule.key,
+            content: rule.content,
+            html_content: rule.html_content,
+            category: rule.category.parse().unwrap_or(Category::General),
+            experiment_id: rule.experiment_id,
+            updated_at: rule.updated_at,
+        };
+        let send_at = (rule.delay_seconds > 0)
+            .then(|| OffsetDateTime::now_utc() + time::Duration::seconds(rule.delay_seconds.into()));
+
+        let new_notification =
+            template::instantiate(&mut tx, &template, user_id, properties, rule.channel.as_deref(), send_at).await?;
+
+        notifications.push(notification::create_notification(&mut *tx, new_notification).await?);
+        info!("Rule {} matched event {} for user {}", rule.id, event.id, user_id);
+    }
+
+    let workflow_runs = workflow::handle_event(&mut tx, event_type, user_id, properties).await?;
+
+    tx.commit().await?;
+
+    Ok(IngestedEvent {
+        event_id: event.id,
+        notifications,
+        workflow_runs,
+    })
+}
@@ -0,0 +1,144 @@
+use log::{info, warn};
+use serde::Serialize;
+use sqlx::{PgExecutor, PgPool};
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::db::models::DeliveryMethod;
+use crate::services::notification;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuppressionReason {
+    HardBounce,
+    Complaint,
+}
+
+impl SuppressionReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuppressionReason::HardBounce => "HardBounce",
+            SuppressionReason::Complaint => "Complaint",
+        }
+    }
+
+    fn event_type(&self) -> &'static str {
+        match self {
+            SuppressionReason::HardBounce => "Bounced",
+            SuppressionReason::Complaint => "Complained",
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Suppression {
+    pub email: String,
+    pub reason: String,
+    pub detail: Option<String>,
+    pub notification_id: Option<Uuid>,
+    pub created_at: OffsetDateTime,
+}
+
+fn normalize(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Returns the reason an address is suppressed, if it is
+pub async fn find_suppression<'e>(executor: impl PgExecutor<'e>, email: &str) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!("SELECT reason FROM suppressions WHERE email = $1", normalize(email))
+        .fetch_optional(executor)
+        .await?;
+
+    Ok(row.map(|row| row.reason))
+}
+
+/// Records a bounce or complaint against the notification it quotes.
+///
+/// Feedback only counts when its Message-ID names a notification we emailed to that same address;
+/// backscatter and misattributed reports are logged and dropped rather than suppressing someone.
+/// Hard bounces and complaints suppress the address and are added to the notification's history;
+/// soft bounces are only logged, since the message may still get through on a later attempt.
+pub async fn record_email_feedback(
+    pool: &PgPool,
+    email: &str,
+    reason: Option<SuppressionReason>,
+    detail: Option<&str>,
+    notification_id: Option<Uuid>,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let notification_id = match notification_id {
+        Some(id) => sqlx::query!(
+            "SELECT n.id FROM notifications n
+             JOIN users u ON u.id = n.user_id
+             WHERE n.id = $1 AND n.delivered_via = $2 AND LOWER(TRIM(u.email)) = $3",
+            id,
+            DeliveryMethod::Email.as_str(),
+            normalize(email)
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .map(|row| row.id),
+        None => None,
+    };
+
+    let notification_id = match notification_id {
+        Some(id) => id,
+        None => {
+            warn!("Ignoring feedback for {} that does not match an email we sent to it", email);
+            return Ok(());
+        }
+    };
+
+    if let Some(reason) = reason {
+        // A complaint is the stronger signal, so it replaces an earlier bounce but not the other way round
+        sqlx::query!(
+            "INSERT INTO suppressions (email, reason, detail, notification_id)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (email) DO UPDATE SET
+                 reason = EXCLUDED.reason,
+                 detail = EXCLUDED.detail,
+                 notification_id = EXCLUDED.notification_id
+             WHERE suppressions.reason <> 'Complaint'",
+            normalize(email),
+            reason.as_str(),
+            detail,
+            notification_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        notification::record_event(&mut *tx, notification_id, Some(DeliveryMethod::Email), reason.event_type(), detail).await?;
+        info!("Suppressed {} ({})", email, reason.as_str());
+    } else {
+        warn!("Soft bounce for {}: {}", email, detail.unwrap_or("no diagnostic"));
+    }
+
+    tx.commit().await
+}
+
+pub async fn list_suppressions<'e>(
+    executor: impl PgExecutor<'e>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<Suppression>, sqlx::Error> {
+    sqlx::query_as!(
+        Suppression,
+        "SELECT email, reason, detail, notification_id, created_at
+         FROM suppressions
+         ORDER BY created_at DESC
+         LIMIT $1 OFFSET $2",
+        limit,
+        offset
+    )
+    .fetch_all(executor)
+    .await
+}
+
+pub async fn remove_suppression<'e>(executor: impl PgExecutor<'e>, email: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!("DELETE FROM suppressions WHERE email = $1", normalize(email))
+        .execute(executor)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
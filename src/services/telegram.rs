@@ -0,0 +1,104 @@
+use rand::RngCore;
+use serde::Serialize;
+use sqlx::{PgExecutor, PgPool};
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// How long a deep link can be used to link an account
+const LINK_TOKEN_TTL: time::Duration = time::Duration::minutes(15);
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TelegramAccount {
+    pub chat_id: i64,
+    pub username: Option<String>,
+    pub linked_at: OffsetDateTime,
+}
+
+/// Issues a one-time link token for the user, replacing any they were issued before
+///
+/// Telegram passes at most 64 characters from `[A-Za-z0-9_-]` as the /start parameter.
+pub async fn issue_link_token<'e>(executor: impl PgExecutor<'e>, user_id: Uuid) -> Result<(String, OffsetDateTime), sqlx::Error> {
+    let mut bytes = [0u8; 24];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token = hex::encode(bytes);
+
+    let expires_at = sqlx::query_scalar!(
+        "WITH cleared AS (DELETE FROM telegram_link_tokens WHERE user_id = $1)
+         INSERT INTO telegram_link_tokens (token, user_id, expires_at)
+         VALUES ($2, $1, $3)
+         RETURNING expires_at",
+        user_id,
+        token,
+        OffsetDateTime::now_utc() + LINK_TOKEN_TTL
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok((token, expires_at))
+}
+
+/// Redeems a link token for `chat_id`; returns the linked user, or None if the token is unknown or expired
+///
+/// A chat belongs to one user at a time, so linking it again moves it to the new user.
+pub async fn link_chat(pool: &PgPool, token: &str, chat_id: i64, username: Option<&str>) -> Result<Option<Uuid>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let user_id = sqlx::query_scalar!(
+        "DELETE FROM telegram_link_tokens WHERE token = $1 AND expires_at > NOW() RETURNING user_id",
+        token
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(user_id) = user_id else {
+        return Ok(None);
+    };
+
+    sqlx::query!("DELETE FROM telegram_accounts WHERE chat_id = $1 AND user_id <> $2", chat_id, user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query!(
+        "INSERT INTO telegram_accounts (user_id, chat_id, username)
+         VALUES ($1, $2, $3)
+         ON CONFLICT (user_id) DO UPDATE
+         SET chat_id = EXCLUDED.chat_id, username = EXCLUDED.username, linked_at = NOW()",
+        user_id,
+        chat_id,
+        username
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(Some(user_id))
+}
+
+pub async fn get_account<'e>(executor: impl PgExecutor<'e>, user_id: Uuid) -> Result<Option<TelegramAccount>, sqlx::Error> {
+    sqlx::query_as!(
+        TelegramAccount,
+        "SELECT chat_id, username, linked_at FROM telegram_accounts WHERE user_id = $1",
+        user_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// Unlinks the user's chat; returns false if none was linked
+pub async fn unlink_user<'e>(executor: impl PgExecutor<'e>, user_id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!("DELETE FROM telegram_accounts WHERE user_id = $1", user_id)
+        .execute(executor)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Unlinks a chat, e.g. after /stop or when the user blocked the bot; returns false if it was not linked
+pub async fn unlink_chat<'e>(executor: impl PgExecutor<'e>, chat_id: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!("DELETE FROM telegram_accounts WHERE chat_id = $1", chat_id)
+        .execute(executor)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
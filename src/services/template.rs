@@ -0,0 +1,357 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::db::models::{Category, DeliveryMethod, Notification, Priority};
+use crate::services::{engagement, notification, preferences};
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Template {
+    pub key: String,
+    pub content: String,
+    pub html_content: Option<String>,
+    pub category: Category,
+    pub experiment_id: Option<String>,  // Set while the template has variants
+    pub updated_at: OffsetDateTime,
+}
+
+/// One arm of an A/B test on a template
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Variant {
+    pub name: String,
+    pub weight: i32,  // Relative share of recipients
+    pub content: String,
+    pub html_content: Option<String>,
+}
+
+pub async fn get_template<'e>(executor: impl PgExecutor<'e>, key: &str) -> Result<Option<Template>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT key, content, html_content, category, experiment_id, updated_at FROM templates WHERE key = $1",
+        key
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(row.map(|row| Template {
+        key: row.key,
+        content: row.content,
+        html_content: row.html_content,
+        category: row.category.parse().unwrap_or(Category::General),
+        experiment_id: row.experiment_id,
+        updated_at: row.updated_at,
+    }))
+}
+
+pub async fn list_templates<'e>(executor: impl PgExecutor<'e>) -> Result<Vec<Template>, sqlx::Error> {
+    let rows = sqlx::query!("SELECT key, content, html_content, category, experiment_id, updated_at FROM templates ORDER BY key")
+        .fetch_all(executor)
+        .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| Template {
+            key: row.key,
+            content: row.content,
+            html_content: row.html_content,
+            category: row.category.parse().unwrap_or(Category::General),
+            experiment_id: row.experiment_id,
+            updated_at: row.updated_at,
+        })
+        .collect())
+}
+
+pub async fn upsert_template<'e>(
+    executor: impl PgExecutor<'e>,
+    key: &str,
+    content: &str,
+    html_content: Option<&str>,
+    category: Category,
+) -> Result<Template, sqlx::Error> {
+    let row = sqlx::query!(
+        "INSERT INTO templates (key, content, html_content, category)
+         VALUES ($1, $2, $3, $4)
+         ON CONFLICT (key) DO UPDATE SET
+             content = EXCLUDED.content,
+             html_content = EXCLUDED.html_content,
+             category = EXCLUDED.category,
+             updated_at = NOW()
+         RETURNING experiment_id, updated_at",
+        key,
+        content,
+        html_content,
+        category.as_str()
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(Template {
+        key: key.to_string(),
+        content: content.to_string(),
+        html_content: html_content.map(str::to_string),
+        category,
+        experiment_id: row.experiment_id,
+        updated_at: row.updated_at,
+    })
+}
+
+pub async fn get_variants<'e>(executor: impl PgExecutor<'e>, key: &str) -> Result<Vec<Variant>, sqlx::Error> {
+    sqlx::query_as!(
+        Variant,
+        "SELECT name, weight, content, html_content FROM template_variants WHERE template_key = $1 ORDER BY name",
+        key
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Replaces a template's variants; an empty list ends the experiment
+pub async fn replace_variants(
+    pool: &PgPool,
+    key: &str,
+    experiment_id: Option<&str>,
+    variants: &[Variant],
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!("DELETE FROM template_variants WHERE template_key = $1", key)
+        .execute(&mut *tx)
+        .await?;
+
+    for variant in variants {
+        sqlx::query!(
+            "INSERT INTO template_variants (template_key, name, weight, content, html_content) VALUES ($1, $2, $3, $4, $5)",
+            key,
+            variant.name,
+            variant.weight,
+            variant.content,
+            variant.html_content
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let experiment_id = if variants.is_empty() { None } else { Some(experiment_id.unwrap_or(key)) };
+    sqlx::query!("UPDATE templates SET experiment_id = $2, updated_at = NOW() WHERE key = $1", key, experiment_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await
+}
+
+/// Picks a user's variant. The same user always lands in the same variant of an experiment,
+/// and a new experiment ID reshuffles everyone.
+pub fn assign_variant<'a>(experiment_id: &str, user_id: Uuid, variants: &'a [Variant]) -> Option<&'a Variant> {
+    let total: u64 = variants.iter().map(|v| v.weight.max(0) as u64).sum();
+    if total == 0 {
+        return None;
+    }
+
+    let mut point = fnv1a(format!("{}:{}", experiment_id, user_id).as_bytes()) % total;
+    variants.iter().find(|variant| {
+        let weight = variant.weight.max(0) as u64;
+        if point < weight {
+            true
+        } else {
+            point -= weight;
+            false
+        }
+    })
+}
+
+/// 64-bit FNV-1a, chosen because it is stable across Rust releases unlike `DefaultHasher`
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Deletes a template; fails with a foreign key violation while rules still use it
+pub async fn delete_template<'e>(executor: impl PgExecutor<'e>, key: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!("DELETE FROM templates WHERE key = $1", key)
+        .execute(executor)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub enum InstantiateError {
+    /// The rule or workflow step names a channel that does not exist or cannot be used
+    InvalidChannel(String),
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for InstantiateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstantiateError::InvalidChannel(e) => f.write_str(e),
+            InstantiateError::Database(e) => write!(f, "database error: {}", e),
+        }
+    }
+}
+
+impl From<sqlx::Error> for InstantiateError {
+    fn from(e: sqlx::Error) -> Self {
+        InstantiateError::Database(e)
+    }
+}
+
+/// Renders a template for one user into a notification ready to be stored.
+///
+/// `channel` is a delivery method name, "auto", or `None` to follow the user's preferences.
+pub async fn instantiate(
+    conn: &mut PgConnection,
+    template: &Template,
+    user_id: Uuid,
+    properties: &Value,
+    channel: Option<&str>,
+    send_at: Option<OffsetDateTime>,
+) -> Result<Notification, InstantiateError> {
+    // The notification is Normal priority, which Voice does not call for
+    let channels = match channel {
+        Some("auto") => {
+            let mut ranked = engagement::auto_channels(&mut *conn, user_id, template.category).await?;
+            ranked.retain(|method| *method != DeliveryMethod::Voice);
+            Some(ranked).filter(|c| !c.is_empty())
+        }
+        Some(name) => match name.parse::<DeliveryMethod>().map_err(InstantiateError::InvalidChannel)? {
+            DeliveryMethod::Voice => {
+                return Err(InstantiateError::InvalidChannel("Voice is only allowed for critical notifications".to_string()))
+            }
+            method => Some(vec![method]),
+        },
+        None => None,
+    };
+
+    let preferred_time = preferences::get_preferred_time(&mut *conn, user_id).await?;
+    let send_at = notification::resolve_send_at(send_at, Priority::Normal, preferred_time, OffsetDateTime::now_utc());
+
+    let variants = get_variants(&mut *conn, &template.key).await?;
+    let experiment_id = template.experiment_id.as_deref().unwrap_or(&template.key);
+    let variant = assign_variant(experiment_id, user_id, &variants);
+
+    let (content, html_content) = match variant {
+        Some(variant) => (&variant.content, variant.html_content.as_ref()),
+        None => (&template.content, template.html_content.as_ref()),
+    };
+
+    Ok(Notification {
+        user_id,
+        content: render(content, properties, false),
+        html_content: html_content.map(|html| render(html, properties, true)),
+        title: None,
+        actions: None,
+        send_at,
+        channels,
+        category: template.category,
+        priority: Priority::Normal,
+        voice_acknowledge: true,
+        template: Some(template.key.clone()),
+        experiment_id: variant.map(|_| experiment_id.to_string()),
+        variant: variant.map(|variant| variant.name.clone()),
+        sms_max_segments: None,
+        sms_overflow: None,
+    })
+}
+
+/// Replaces `{{ path.to.property }}` placeholders with values from `properties`.
+///
+/// Missing properties render as empty strings. Pass `escape_html` for HTML bodies so
+/// event data cannot inject markup.
+pub fn render(text: &str, properties: &Value, escape_html: bool) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else { break };
+
+        output.push_str(&rest[..start]);
+        let path = rest[start + 2..start + end].trim();
+        let value = lookup(properties, path).map(value_to_string).unwrap_or_default();
+        output.push_str(&if escape_html { html_escape(&value) } else { value });
+
+        rest = &rest[start + end + 2..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Resolves a dotted path such as `order.total` against a JSON object
+pub fn lookup<'a>(properties: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(properties, |value, segment| value.get(segment))
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn variant(name: &str, weight: i32) -> Variant {
+        Variant {
+            name: name.to_string(),
+            weight,
+            content: format!("Content {}", name),
+            html_content: None,
+        }
+    }
+
+    #[test]
+    fn fnv1a_matches_reference_values() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn assignment_is_stable_per_user() {
+        let variants = [variant("A", 1), variant("B", 1)];
+        let user_id = Uuid::from_u128(42);
+
+        let first = assign_variant("welcome", user_id, &variants).map(|v| &v.name);
+        for _ in 0..10 {
+            assert_eq!(assign_variant("welcome", user_id, &variants).map(|v| &v.name), first);
+        }
+    }
+
+    #[test]
+    fn assignment_follows_weights() {
+        let variants = [variant("A", 3), variant("B", 1), variant("Off", 0)];
+        let mut counts = HashMap::new();
+
+        for i in 0..10_000 {
+            let picked = assign_variant("welcome", Uuid::from_u128(i), &variants).unwrap();
+            *counts.entry(picked.name.as_str()).or_insert(0) += 1;
+        }
+
+        let share_a = counts["A"] as f64 / 10_000.0;
+        assert!((0.72..0.78).contains(&share_a), "A got {}", share_a);
+        assert!(!counts.contains_key("Off"));
+    }
+
+    #[test]
+    fn no_variants_or_weights_means_no_experiment() {
+        assert!(assign_variant("welcome", Uuid::from_u128(1), &[]).is_none());
+        assert!(assign_variant("welcome", Uuid::from_u128(1), &[variant("A", 0), variant("B", -5)]).is_none());
+    }
+}
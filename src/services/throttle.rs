@@ -0,0 +1,226 @@
+//! Provider protection shared by every dispatcher replica through Postgres: token-bucket
+//! rate limits and circuit breakers that stop attempts on a provider that keeps failing.
+//!
+//! Both are keyed by provider, e.g. `push:fcm` or `sms:twilio`, so an outage at one provider does
+//! not hold back the other providers of the same channel.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use log::{info, warn};
+use serde::Serialize;
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+use crate::config::{Config, RateLimit};
+use crate::db::models::DeliveryMethod;
+
+/// How long other callers are held off while a trial attempt is in flight; longer than any
+/// channel's request timeout, so an unanswered trial does not block the channel for good
+pub const TRIAL_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct Throttle {
+    rate_limits: HashMap<String, RateLimit>,
+    failure_threshold: i32,
+    cooldown_secs: i64,
+}
+
+/// Whether a provider may be attempted right now
+pub enum Admission {
+    /// Go ahead; a `trial` attempt decides whether an open breaker closes and must be settled
+    /// with [`Throttle::record_success`], [`Throttle::record_failure`] or [`Throttle::release_trial`]
+    Allowed { trial: bool },
+    Blocked(Blocked),
+}
+
+/// Why a provider cannot be attempted right now
+pub enum Blocked {
+    RateLimited,
+    BreakerOpen(OffsetDateTime),  // Until when
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CircuitBreaker {
+    pub key: String,
+    pub state: String,  // Closed, Open, or HalfOpen once the cooldown is over and the next attempt is a trial
+    pub consecutive_failures: i32,
+    pub open_until: Option<OffsetDateTime>,
+    pub last_error: Option<String>,
+    pub updated_at: OffsetDateTime,
+}
+
+impl Throttle {
+    pub fn from_config(config: &Config) -> Self {
+        Throttle {
+            rate_limits: config.rate_limits.clone(),
+            failure_threshold: config.breaker_failure_threshold.max(1),
+            cooldown_secs: config.breaker_cooldown_secs.max(1),
+        }
+    }
+
+    /// Checks the provider's breaker, then takes a token from its bucket if `method` is rate limited
+    ///
+    /// Once an open breaker's cooldown is over, exactly one caller across all replicas is let
+    /// through as the trial: it pushes `open_until` out by [`TRIAL_TIMEOUT`] so everyone else keeps
+    /// waiting until the trial's success closes the breaker or its failure reopens it.
+    pub async fn acquire(&self, pool: &PgPool, method: DeliveryMethod, key: &str) -> Result<Admission, sqlx::Error> {
+        // The row lock makes concurrent callers re-check open_until, so only the first one matches
+        let trial = sqlx::query_scalar!(
+            "UPDATE circuit_breakers SET open_until = NOW() + make_interval(secs => $2), updated_at = NOW()
+             WHERE key = $1 AND state = 'Open' AND open_until <= NOW()
+             RETURNING key",
+            key,
+            TRIAL_TIMEOUT.as_secs_f64()
+        )
+        .fetch_optional(pool)
+        .await?
+        .is_some();
+
+        if trial {
+            info!("Circuit breaker for {} half-open, letting one trial attempt through", key);
+        } else {
+            let open_until = sqlx::query_scalar!(
+                "SELECT open_until FROM circuit_breakers WHERE key = $1 AND state = 'Open' AND open_until > NOW()",
+                key
+            )
+            .fetch_optional(pool)
+            .await?
+            .flatten();
+
+            if let Some(open_until) = open_until {
+                return Ok(Admission::Blocked(Blocked::BreakerOpen(open_until)));
+            }
+        }
+
+        // Every provider of a channel gets its own bucket with the channel's limit
+        let limit = match self.rate_limits.get(method.as_str()) {
+            Some(limit) => limit,
+            None => return Ok(Admission::Allowed { trial }),
+        };
+
+        // Refill for the time since the last take, capped at the burst size, and take one token if there is one
+        let taken = sqlx::query_scalar!(
+            "INSERT INTO rate_limit_buckets (key, tokens) VALUES ($1, $3::float8 - 1)
+             ON CONFLICT (key) DO UPDATE SET
+                 tokens = LEAST($3, rate_limit_buckets.tokens + EXTRACT(EPOCH FROM NOW() - rate_limit_buckets.updated_at)::float8 * $2) - 1,
+                 updated_at = NOW()
+             WHERE LEAST($3, rate_limit_buckets.tokens + EXTRACT(EPOCH FROM NOW() - rate_limit_buckets.updated_at)::float8 * $2) >= 1
+             RETURNING tokens",
+            key,
+            limit.per_second,
+            limit.burst
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        if taken.is_some() {
+            return Ok(Admission::Allowed { trial });
+        }
+        if trial {
+            self.release_trial(pool, key).await?;
+        }
+        Ok(Admission::Blocked(Blocked::RateLimited))
+    }
+
+    /// Hands back a trial that ended without saying anything about the provider, e.g. because the
+    /// recipient had no address on it, so the next caller becomes the trial instead
+    pub async fn release_trial(&self, pool: &PgPool, key: &str) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE circuit_breakers SET open_until = NOW(), updated_at = NOW() WHERE key = $1 AND state = 'Open'",
+            key
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn record_success(&self, pool: &PgPool, key: &str) -> Result<(), sqlx::Error> {
+        // Only write when there is something to reset, so healthy channels cost a single read
+        sqlx::query!(
+            "UPDATE circuit_breakers
+             SET state = 'Closed', consecutive_failures = 0, open_until = NULL, updated_at = NOW()
+             WHERE key = $1 AND (state <> 'Closed' OR consecutive_failures > 0)",
+            key
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Counts a transient failure and opens the breaker once there are too many in a row.
+    ///
+    /// A failed trial after the cooldown reopens it straight away.
+    pub async fn record_failure(&self, pool: &PgPool, key: &str, error: &str) -> Result<(), sqlx::Error> {
+        let row = sqlx::query!(
+            "INSERT INTO circuit_breakers (key, state, consecutive_failures, open_until, last_error)
+             VALUES ($1, CASE WHEN $3 <= 1 THEN 'Open' ELSE 'Closed' END, 1,
+                     CASE WHEN $3 <= 1 THEN NOW() + make_interval(secs => $4) END, $2)
+             ON CONFLICT (key) DO UPDATE SET
+                 consecutive_failures = circuit_breakers.consecutive_failures + 1,
+                 state = CASE WHEN circuit_breakers.consecutive_failures + 1 >= $3 THEN 'Open' ELSE 'Closed' END,
+                 open_until = CASE WHEN circuit_breakers.consecutive_failures + 1 >= $3
+                                   THEN NOW() + make_interval(secs => $4)
+                                   ELSE circuit_breakers.open_until END,
+                 last_error = $2,
+                 updated_at = NOW()
+             RETURNING state, consecutive_failures, open_until",
+            key,
+            error,
+            self.failure_threshold,
+            self.cooldown_secs as f64
+        )
+        .fetch_one(pool)
+        .await?;
+
+        if row.state == "Open" {
+            warn!(
+                "Circuit breaker for {} open until {:?} after {} consecutive failures",
+                key, row.open_until, row.consecutive_failures
+            );
+        }
+
+        Ok(())
+    }
+}
+
+pub async fn list_breakers(pool: &PgPool) -> Result<Vec<CircuitBreaker>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT key,
+                  CASE WHEN state = 'Open' AND open_until <= NOW() THEN 'HalfOpen' ELSE state END AS "state!",
+                  consecutive_failures, open_until, last_error, updated_at
+           FROM circuit_breakers
+           ORDER BY key"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| CircuitBreaker {
+            key: row.key,
+            state: row.state,
+            consecutive_failures: row.consecutive_failures,
+            open_until: row.open_until,
+            last_error: row.last_error,
+            updated_at: row.updated_at,
+        })
+        .collect())
+}
+
+/// Closes a breaker by hand, e.g. once the provider confirms an incident is over
+pub async fn reset_breaker(pool: &PgPool, key: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE circuit_breakers
+         SET state = 'Closed', consecutive_failures = 0, open_until = NULL, updated_at = NOW()
+         WHERE key = $1",
+        key
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
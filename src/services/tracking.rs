@@ -0,0 +1,78 @@
+use uuid::Uuid;
+
+use crate::auth::tracking;
+
+/// 1x1 transparent GIF served for the open pixel
+pub const PIXEL_GIF: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+/// Routes every http(s) link in an HTML email through the click redirect and embeds the open pixel
+pub fn instrument_html(
+    html: &str,
+    public_url: &str,
+    secret: &str,
+    notification_id: Uuid,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    // ASCII lowercasing keeps byte offsets identical, so indices found here are valid in `html`
+    let lower = html.to_ascii_lowercase();
+    let mut out = String::with_capacity(html.len() + 512);
+    let mut copied = 0;
+    let mut search = 0;
+
+    while let Some(found) = lower[search..].find("href=") {
+        let attr_start = search + found;
+        let value_start = attr_start + "href=".len();
+        search = value_start;
+
+        // Skip things like data-href=
+        if attr_start > 0 && !lower.as_bytes()[attr_start - 1].is_ascii_whitespace() {
+            continue;
+        }
+
+        let quote = match html[value_start..].chars().next() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => continue,
+        };
+        let url_start = value_start + 1;
+        let url_end = match html[url_start..].find(quote) {
+            Some(len) => url_start + len,
+            None => break,
+        };
+        search = url_end;
+
+        let url = html[url_start..url_end].replace("&amp;", "&");
+        if !is_trackable(&url) {
+            continue;
+        }
+
+        let token = tracking::sign_token(secret, notification_id, Some(&url))?;
+        out.push_str(&html[copied..url_start]);
+        out.push_str(&format!("{}/t/c/{}", public_url, token));
+        copied = url_end;
+    }
+    out.push_str(&html[copied..]);
+
+    let token = tracking::sign_token(secret, notification_id, None)?;
+    let pixel = format!(
+        "<img src=\"{}/t/o/{}.gif\" width=\"1\" height=\"1\" alt=\"\" style=\"display:none\">",
+        public_url, token
+    );
+
+    Ok(insert_before_body_end(&out, &pixel))
+}
+
+/// Inserts `fragment` just before `</body>`, or appends it when the HTML has no body tag
+pub fn insert_before_body_end(html: &str, fragment: &str) -> String {
+    match html.to_ascii_lowercase().rfind("</body>") {
+        Some(pos) => format!("{}{}{}", &html[..pos], fragment, &html[pos..]),
+        None => format!("{}{}", html, fragment),
+    }
+}
+
+fn is_trackable(url: &str) -> bool {
+    let lower = url.to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
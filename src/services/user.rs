@@ -0,0 +1,142 @@
+use log::{error, info, warn};
+use phonenumber::country;
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::db::models::User;
+use crate::services::{outbox, phone};
+
+const BACKFILL_BATCH_SIZE: i64 = 500;
+
+pub async fn find_user<'e>(executor: impl PgExecutor<'e>, user_id: Uuid) -> Result<Option<User>, sqlx::Error> {
+    sqlx::query_as!(
+        User,
+        "SELECT id, email, password_hash, phone_number, email_verified, verification_token,
+                phone_verified, phone_verification_code, created_at, phone_country_code, phone_number_type
+         FROM users WHERE id = $1",
+        user_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// The link in verification emails; `public_url` is where this API is reachable from outside
+fn verification_link(public_url: &str, verification_token: Uuid) -> String {
+    format!("{}/api/verify?token={}", public_url.trim_end_matches('/'), verification_token)
+}
+
+/// Inserts a new user and queues their verification email in one transaction
+pub async fn register(
+    pool: &PgPool,
+    public_url: &str,
+    email: &str,
+    password_hash: &str,
+    verification_token: Uuid,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        "INSERT INTO users (email, password_hash, verification_token) VALUES ($1, $2, $3)",
+        email,
+        password_hash,
+        verification_token
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let body = format!("Please verify your email by clicking the link: {}", verification_link(public_url, verification_token));
+    outbox::enqueue(&mut *tx, email, "Verify your email", &body).await?;
+
+    tx.commit().await
+}
+
+/// Stores the user's verification token and queues another verification email in one transaction
+pub async fn resend_verification(
+    pool: &PgPool,
+    public_url: &str,
+    user_id: Uuid,
+    email: &str,
+    verification_token: Uuid,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!("UPDATE users SET verification_token = $1 WHERE id = $2", verification_token, user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let body = format!("Please verify your email by clicking this link: {}", verification_link(public_url, verification_token));
+    outbox::enqueue(&mut *tx, email, "Resend Verification Email", &body).await?;
+
+    tx.commit().await
+}
+
+/// Normalizes phone numbers stored before numbers were kept in E.164, once at startup
+///
+/// Numbers without a country code are read in `region`. A number that cannot be parsed is removed
+/// and the user flagged to enter it again, rather than left where lookups and the landline check
+/// cannot see it.
+pub async fn normalize_legacy_phone_numbers(pool: PgPool, region: Option<country::Id>) {
+    let mut normalized = 0;
+    let mut cleared = 0;
+
+    loop {
+        let rows = match sqlx::query!(
+            r#"SELECT id, phone_number AS "phone_number!" FROM users
+               WHERE phone_number IS NOT NULL AND phone_country_code IS NULL
+               LIMIT $1"#,
+            BACKFILL_BATCH_SIZE
+        )
+        .fetch_all(&pool)
+        .await
+        {
+            Ok(rows) => rows,
+            Err(e) => {
+                error!("Failed to load phone numbers to normalize: {:?}", e);
+                return;
+            }
+        };
+        if rows.is_empty() {
+            break;
+        }
+
+        for row in rows {
+            let result = match phone::normalize(&row.phone_number, region) {
+                Ok(phone) => {
+                    normalized += 1;
+                    sqlx::query!(
+                        "UPDATE users SET phone_number = $2, phone_country_code = $3, phone_region = $4, phone_number_type = $5
+                         WHERE id = $1",
+                        row.id,
+                        phone.e164,
+                        phone.country_code,
+                        phone.region,
+                        phone.number_type
+                    )
+                    .execute(&pool)
+                    .await
+                }
+                Err(e) => {
+                    warn!("Removing phone number of user {} that cannot be normalized: {}", row.id, e.message);
+                    cleared += 1;
+                    sqlx::query!(
+                        "UPDATE users
+                         SET phone_number = NULL, phone_verified = FALSE, phone_verification_code = NULL,
+                             phone_reentry_required = TRUE
+                         WHERE id = $1",
+                        row.id
+                    )
+                    .execute(&pool)
+                    .await
+                }
+            };
+            if let Err(e) = result {
+                error!("Failed to normalize phone number of user {}: {:?}", row.id, e);
+                return;
+            }
+        }
+    }
+
+    if normalized + cleared > 0 {
+        info!("Normalized {} stored phone numbers and removed {} invalid ones", normalized, cleared);
+    }
+}
@@ -0,0 +1,81 @@
+use serde::Serialize;
+use sqlx::PgExecutor;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebPushSubscription {
+    pub id: Uuid,
+    pub endpoint: String,
+    pub p256dh: String,
+    pub auth: String,
+    pub user_agent: Option<String>,
+    pub created_at: OffsetDateTime,
+    pub last_seen: OffsetDateTime,
+}
+
+/// Saves a browser subscription; an endpoint seen again is refreshed and moved to `user_id`
+pub async fn subscribe<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+    endpoint: &str,
+    p256dh: &str,
+    auth: &str,
+    user_agent: Option<&str>,
+) -> Result<WebPushSubscription, sqlx::Error> {
+    sqlx::query_as!(
+        WebPushSubscription,
+        "INSERT INTO web_push_subscriptions (user_id, endpoint, p256dh, auth, user_agent)
+         VALUES ($1, $2, $3, $4, $5)
+         ON CONFLICT (endpoint) DO UPDATE
+         SET user_id = EXCLUDED.user_id, p256dh = EXCLUDED.p256dh, auth = EXCLUDED.auth,
+             user_agent = EXCLUDED.user_agent, last_seen = NOW()
+         RETURNING id, endpoint, p256dh, auth, user_agent, created_at, last_seen",
+        user_id,
+        endpoint,
+        p256dh,
+        auth,
+        user_agent
+    )
+    .fetch_one(executor)
+    .await
+}
+
+/// Removes one of the user's subscriptions; returns false if they had no such endpoint
+pub async fn unsubscribe<'e>(executor: impl PgExecutor<'e>, user_id: Uuid, endpoint: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM web_push_subscriptions WHERE user_id = $1 AND endpoint = $2",
+        user_id,
+        endpoint
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn list_subscriptions<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+) -> Result<Vec<WebPushSubscription>, sqlx::Error> {
+    sqlx::query_as!(
+        WebPushSubscription,
+        "SELECT id, endpoint, p256dh, auth, user_agent, created_at, last_seen
+         FROM web_push_subscriptions
+         WHERE user_id = $1
+         ORDER BY last_seen DESC",
+        user_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Forgets a subscription the push service reports as expired (404/410)
+pub async fn prune_subscription<'e>(executor: impl PgExecutor<'e>, subscription_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM web_push_subscriptions WHERE id = $1", subscription_id)
+        .execute(executor)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,178 @@
+use rand::RngCore;
+use serde::Serialize;
+use sqlx::PgExecutor;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebhookEndpoint {
+    pub id: Uuid,
+    pub url: String,
+    pub description: Option<String>,
+    pub active: bool,
+    pub consecutive_failures: i32,
+    pub last_error: Option<String>,
+    pub last_success_at: Option<OffsetDateTime>,
+    pub disabled_at: Option<OffsetDateTime>,
+    pub created_at: OffsetDateTime,
+}
+
+/// Where and how the dispatcher delivers to an active endpoint
+pub struct WebhookTarget {
+    pub id: Uuid,
+    pub url: String,
+    pub secret: String,
+}
+
+/// Why an endpoint's failure was recorded, and whether that disabled it
+pub struct RecordedFailure {
+    pub url: String,
+    pub consecutive_failures: i32,
+    pub disabled: bool,
+}
+
+/// A fresh signing secret, given to the owner once when the endpoint is created
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("whsec_{}", hex::encode(bytes))
+}
+
+pub async fn create_endpoint<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+    url: &str,
+    secret: &str,
+    description: Option<&str>,
+) -> Result<WebhookEndpoint, sqlx::Error> {
+    sqlx::query_as!(
+        WebhookEndpoint,
+        "INSERT INTO webhook_endpoints (user_id, url, secret, description)
+         VALUES ($1, $2, $3, $4)
+         RETURNING id, url, description, active, consecutive_failures, last_error, last_success_at, disabled_at, created_at",
+        user_id,
+        url,
+        secret,
+        description
+    )
+    .fetch_one(executor)
+    .await
+}
+
+pub async fn list_endpoints<'e>(executor: impl PgExecutor<'e>, user_id: Uuid) -> Result<Vec<WebhookEndpoint>, sqlx::Error> {
+    sqlx::query_as!(
+        WebhookEndpoint,
+        "SELECT id, url, description, active, consecutive_failures, last_error, last_success_at, disabled_at, created_at
+         FROM webhook_endpoints
+         WHERE user_id = $1
+         ORDER BY created_at",
+        user_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Deletes one of the user's endpoints; returns false if they have no such endpoint
+pub async fn delete_endpoint<'e>(executor: impl PgExecutor<'e>, user_id: Uuid, endpoint_id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM webhook_endpoints WHERE id = $1 AND user_id = $2",
+        endpoint_id,
+        user_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Turns a disabled endpoint back on with a clean failure count, e.g. once the owner fixed it
+pub async fn enable_endpoint<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+    endpoint_id: Uuid,
+) -> Result<Option<WebhookEndpoint>, sqlx::Error> {
+    sqlx::query_as!(
+        WebhookEndpoint,
+        "UPDATE webhook_endpoints
+         SET active = TRUE, consecutive_failures = 0, disabled_at = NULL
+         WHERE id = $1 AND user_id = $2
+         RETURNING id, url, description, active, consecutive_failures, last_error, last_success_at, disabled_at, created_at",
+        endpoint_id,
+        user_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+pub async fn active_targets<'e>(executor: impl PgExecutor<'e>, user_id: Uuid) -> Result<Vec<WebhookTarget>, sqlx::Error> {
+    sqlx::query_as!(
+        WebhookTarget,
+        "SELECT id, url, secret FROM webhook_endpoints WHERE user_id = $1 AND active ORDER BY created_at",
+        user_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Active endpoints that have not accepted `notification_id` yet
+pub async fn undelivered_targets<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+    notification_id: Uuid,
+) -> Result<Vec<WebhookTarget>, sqlx::Error> {
+    sqlx::query_as!(
+        WebhookTarget,
+        "SELECT id, url, secret FROM webhook_endpoints e
+         WHERE user_id = $1 AND active
+               AND NOT EXISTS (SELECT 1 FROM webhook_deliveries d WHERE d.endpoint_id = e.id AND d.notification_id = $2)
+         ORDER BY created_at",
+        user_id,
+        notification_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Records that the endpoint accepted the notification and resets its failure count
+pub async fn record_success<'e>(executor: impl PgExecutor<'e>, endpoint_id: Uuid, notification_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "WITH delivered AS (
+             INSERT INTO webhook_deliveries (notification_id, endpoint_id) VALUES ($2, $1) ON CONFLICT DO NOTHING
+         )
+         UPDATE webhook_endpoints SET consecutive_failures = 0, last_error = NULL, last_success_at = NOW() WHERE id = $1",
+        endpoint_id,
+        notification_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Counts a failed delivery, disabling the endpoint once `failure_limit` failures happened in a row
+///
+/// Returns None if the endpoint was deleted or already disabled meanwhile, so only one caller
+/// ever sees `disabled` and notifies the owner.
+pub async fn record_failure<'e>(
+    executor: impl PgExecutor<'e>,
+    endpoint_id: Uuid,
+    error: &str,
+    failure_limit: i32,
+) -> Result<Option<RecordedFailure>, sqlx::Error> {
+    sqlx::query_as!(
+        RecordedFailure,
+        r#"UPDATE webhook_endpoints
+           SET consecutive_failures = consecutive_failures + 1,
+               last_error = $2,
+               active = consecutive_failures + 1 < $3,
+               disabled_at = CASE WHEN consecutive_failures + 1 < $3 THEN NULL ELSE NOW() END
+           WHERE id = $1 AND active
+           RETURNING url, consecutive_failures, NOT active AS "disabled!""#,
+        endpoint_id,
+        error,
+        failure_limit
+    )
+    .fetch_optional(executor)
+    .await
+}
@@ -0,0 +1,502 @@
+use std::time::Duration;
+
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::types::Json;
+use sqlx::{PgConnection, PgPool};
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::db::models::DeliveryMethod;
+use crate::services::template::InstantiateError;
+use crate::services::{notification, template, user};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const BATCH_SIZE: i64 = 50;
+/// Attempts at a step that keeps failing before its run is marked Failed
+const MAX_ATTEMPTS: i32 = 5;
+/// Longest single delay step; also keeps resume times far from `OffsetDateTime`'s range
+const MAX_DELAY: time::Duration = time::Duration::days(365);
+
+/// User fields condition steps can test, besides `event.*` properties of the triggering event
+const USER_FIELDS: [&str; 3] = ["email_verified", "phone_verified", "has_phone"];
+
+/// What a condition step does when the condition does not hold
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Otherwise {
+    #[default]
+    Skip,  // Skip the next `skip` steps
+    Exit,  // End the run
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Step {
+    /// Create a notification from a template
+    Send {
+        template: String,
+        channel: Option<String>,  // A delivery method, "auto", or null for the user's preferences
+    },
+    /// Wait before moving on; the parts are added together and may total at most 365 days
+    Delay {
+        #[serde(default)]
+        days: i64,
+        #[serde(default)]
+        hours: i64,
+        #[serde(default)]
+        minutes: i64,
+        #[serde(default)]
+        seconds: i64,
+    },
+    /// Continue only if `field` equals `equals`, e.g. `{"field": "email_verified", "equals": false}`
+    Condition {
+        field: String,
+        #[schema(value_type = Object)]
+        equals: Value,
+        #[serde(default)]
+        otherwise: Otherwise,
+        #[serde(default = "default_skip")]
+        skip: usize,
+    },
+}
+
+fn default_skip() -> usize {
+    1
+}
+
+impl Step {
+    /// The step's wait, or `None` for other steps and delays too long to represent
+    fn delay(&self) -> Option<time::Duration> {
+        match self {
+            Step::Delay { days, hours, minutes, seconds } => days
+                .checked_mul(86_400)
+                .and_then(|total| total.checked_add(hours.checked_mul(3_600)?))
+                .and_then(|total| total.checked_add(minutes.checked_mul(60)?))
+                .and_then(|total| total.checked_add(*seconds))
+                .map(time::Duration::seconds),
+            _ => None,
+        }
+    }
+}
+
+/// Checks a definition for mistakes that would only surface once runs reach them
+pub fn validate_steps(steps: &[Step]) -> Result<(), String> {
+    if steps.is_empty() {
+        return Err("A workflow needs at least one step".to_string());
+    }
+
+    for (index, step) in steps.iter().enumerate() {
+        match step {
+            Step::Send { channel: Some(channel), .. } if channel != "auto" => {
+                // Workflows send Normal notifications, which are never called
+                if channel.parse::<DeliveryMethod>().map_err(|e| format!("Step {}: {}", index, e))? == DeliveryMethod::Voice {
+                    return Err(format!("Step {}: Voice is only allowed for critical notifications", index));
+                }
+            }
+            Step::Send { .. } => {}
+            Step::Delay { .. } => {
+                if step.delay().is_none_or(|delay| delay <= time::Duration::ZERO || delay > MAX_DELAY) {
+                    return Err(format!("Step {}: delay must be positive and at most {} days", index, MAX_DELAY.whole_days()));
+                }
+            }
+            Step::Condition { field, skip, .. } => {
+                if !field.starts_with("event.") && !USER_FIELDS.contains(&field.as_str()) {
+                    return Err(format!(
+                        "Step {}: unknown field {} (expected one of {} or event.<property>)",
+                        index,
+                        field,
+                        USER_FIELDS.join(", ")
+                    ));
+                }
+                if *skip == 0 {
+                    return Err(format!("Step {}: skip must be at least 1", index));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Template keys referenced by send steps
+pub fn template_keys(steps: &[Step]) -> Vec<&str> {
+    steps
+        .iter()
+        .filter_map(|step| match step {
+            Step::Send { template, .. } => Some(template.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Workflow {
+    pub id: Uuid,
+    pub key: String,
+    pub name: String,
+    pub steps: Vec<Step>,
+    pub trigger_event: Option<String>,
+    pub exit_events: Vec<String>,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WorkflowRun {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub current_step: i32,
+    pub status: String,
+    pub next_run_at: OffsetDateTime,
+    pub exit_reason: Option<String>,
+    pub created_at: OffsetDateTime,
+    pub finished_at: Option<OffsetDateTime>,
+}
+
+pub struct NewWorkflow {
+    pub name: String,
+    pub steps: Vec<Step>,
+    pub trigger_event: Option<String>,
+    pub exit_events: Vec<String>,
+    pub enabled: bool,
+}
+
+pub async fn list_workflows(pool: &PgPool) -> Result<Vec<Workflow>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT id, key, name, steps AS "steps: Json<Vec<Step>>", trigger_event, exit_events, enabled
+           FROM workflows
+           ORDER BY key"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| Workflow {
+            id: row.id,
+            key: row.key,
+            name: row.name,
+            steps: row.steps.0,
+            trigger_event: row.trigger_event,
+            exit_events: row.exit_events,
+            enabled: row.enabled,
+        })
+        .collect())
+}
+
+pub async fn find_workflow_id(pool: &PgPool, key: &str) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!("SELECT id FROM workflows WHERE key = $1", key)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(row.map(|row| row.id))
+}
+
+/// Creates or replaces a workflow; runs already in flight keep the steps they started with
+pub async fn upsert_workflow(pool: &PgPool, key: &str, workflow: NewWorkflow) -> Result<Workflow, sqlx::Error> {
+    let row = sqlx::query!(
+        "INSERT INTO workflows (key, name, steps, trigger_event, exit_events, enabled)
+         VALUES ($1, $2, $3, $4, $5, $6)
+         ON CONFLICT (key) DO UPDATE SET
+             name = EXCLUDED.name,
+             steps = EXCLUDED.steps,
+             trigger_event = EXCLUDED.trigger_event,
+             exit_events = EXCLUDED.exit_events,
+             enabled = EXCLUDED.enabled,
+             updated_at = NOW()
+         RETURNING id",
+        key,
+        workflow.name,
+        Json(&workflow.steps) as _,
+        workflow.trigger_event,
+        &workflow.exit_events,
+        workflow.enabled
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(Workflow {
+        id: row.id,
+        key: key.to_string(),
+        name: workflow.name,
+        steps: workflow.steps,
+        trigger_event: workflow.trigger_event,
+        exit_events: workflow.exit_events,
+        enabled: workflow.enabled,
+    })
+}
+
+pub async fn list_runs(
+    pool: &PgPool,
+    workflow_id: Uuid,
+    status: Option<&str>,
+    limit: i64,
+) -> Result<Vec<WorkflowRun>, sqlx::Error> {
+    sqlx::query_as!(
+        WorkflowRun,
+        "SELECT id, user_id, current_step, status, next_run_at, exit_reason, created_at, finished_at
+         FROM workflow_runs
+         WHERE workflow_id = $1 AND ($2::TEXT IS NULL OR status = $2)
+         ORDER BY created_at DESC
+         LIMIT $3",
+        workflow_id,
+        status,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Starts a run now; returns `None` if the user already has an active run of this workflow
+pub async fn start_run(pool: &PgPool, workflow_id: Uuid, user_id: Uuid, context: &Value) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
+        "INSERT INTO workflow_runs (workflow_id, user_id, steps, context)
+         SELECT id, $2, steps, $3 FROM workflows WHERE id = $1
+         ON CONFLICT (workflow_id, user_id) WHERE status = 'Active' DO NOTHING
+         RETURNING id",
+        workflow_id,
+        user_id,
+        context
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| row.id))
+}
+
+pub async fn cancel_run(pool: &PgPool, run_id: Uuid, reason: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE workflow_runs SET status = 'Cancelled', exit_reason = $2, finished_at = NOW()
+         WHERE id = $1 AND status = 'Active'",
+        run_id,
+        reason
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Cancels runs the event is an exit event for, then starts runs it triggers.
+///
+/// Returns the IDs of the runs that were started.
+pub async fn handle_event(conn: &mut PgConnection, event_type: &str, user_id: Uuid, properties: &Value) -> Result<Vec<Uuid>, sqlx::Error> {
+    let cancelled = sqlx::query!(
+        "UPDATE workflow_runs r
+         SET status = 'Cancelled', exit_reason = 'Exit event ' || $2, finished_at = NOW()
+         FROM workflows w
+         WHERE w.id = r.workflow_id AND r.user_id = $1 AND r.status = 'Active' AND $2 = ANY(w.exit_events)",
+        user_id,
+        event_type
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    if cancelled.rows_affected() > 0 {
+        info!("Event {} cancelled {} workflow run(s) for user {}", event_type, cancelled.rows_affected(), user_id);
+    }
+
+    let started = sqlx::query!(
+        "INSERT INTO workflow_runs (workflow_id, user_id, steps, context)
+         SELECT id, $1, steps, $3 FROM workflows WHERE enabled AND trigger_event = $2
+         ON CONFLICT (workflow_id, user_id) WHERE status = 'Active' DO NOTHING
+         RETURNING id",
+        user_id,
+        event_type,
+        properties
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(started.into_iter().map(|row| row.id).collect())
+}
+
+/// Advances due workflow runs until the process exits
+pub async fn run(pool: PgPool) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = advance_due(&pool).await {
+            error!("Failed to advance workflow runs: {:?}", e);
+        }
+    }
+}
+
+struct DueRun {
+    id: Uuid,
+    user_id: Uuid,
+    steps: Json<Vec<Step>>,
+    current_step: i32,
+    context: Value,
+    attempts: i32,
+}
+
+async fn advance_due(pool: &PgPool) -> Result<(), sqlx::Error> {
+    // Each run is claimed and committed on its own, so a run that fails neither rolls back nor
+    // holds up the others
+    for _ in 0..BATCH_SIZE {
+        let mut tx = pool.begin().await?;
+        let Some(run) = claim_next(&mut tx).await? else {
+            return Ok(());
+        };
+
+        match advance(&mut tx, &run).await {
+            Ok(()) => tx.commit().await?,
+            Err(e) => {
+                tx.rollback().await?;
+                error!("Failed to advance workflow run {}: {:?}", run.id, e);
+                retry_later(pool, &run, &e.to_string()).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Locks the run that has been due longest; SKIP LOCKED lets several instances run the worker
+/// without executing a step twice
+async fn claim_next(conn: &mut PgConnection) -> Result<Option<DueRun>, sqlx::Error> {
+    sqlx::query_as!(
+        DueRun,
+        r#"SELECT id, user_id, steps AS "steps: Json<Vec<Step>>", current_step, context, attempts
+           FROM workflow_runs
+           WHERE status = 'Active' AND next_run_at <= NOW()
+           ORDER BY next_run_at
+           LIMIT 1
+           FOR UPDATE SKIP LOCKED"#
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+/// Puts a run whose step failed back with a backoff, or marks it Failed once it keeps failing
+async fn retry_later(pool: &PgPool, run: &DueRun, reason: &str) -> Result<(), sqlx::Error> {
+    let attempts = run.attempts + 1;
+
+    if attempts >= MAX_ATTEMPTS {
+        warn!("Workflow run {} failed after {} attempts: {}", run.id, attempts, reason);
+        sqlx::query!(
+            "UPDATE workflow_runs SET status = 'Failed', attempts = $2, exit_reason = $3, finished_at = NOW()
+             WHERE id = $1 AND status = 'Active'",
+            run.id,
+            attempts,
+            reason
+        )
+        .execute(pool)
+        .await?;
+        return Ok(());
+    }
+
+    // Exponential backoff: 1, 2, 4, 8 minutes
+    let retry_at = OffsetDateTime::now_utc() + time::Duration::minutes(1 << (attempts - 1));
+
+    sqlx::query!(
+        "UPDATE workflow_runs SET attempts = $2, next_run_at = $3, exit_reason = $4 WHERE id = $1 AND status = 'Active'",
+        run.id,
+        attempts,
+        retry_at,
+        reason
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Executes steps until the run has to wait or ends
+async fn advance(conn: &mut PgConnection, run: &DueRun) -> Result<(), sqlx::Error> {
+    let recipient = match user::find_user(&mut *conn, run.user_id).await? {
+        Some(recipient) => recipient,
+        None => return finish(conn, run.id, "Cancelled", Some("Recipient no longer exists")).await,
+    };
+
+    let fields = json!({
+        "email_verified": recipient.email_verified.unwrap_or(false),
+        "phone_verified": recipient.phone_verified.unwrap_or(false),
+        "has_phone": recipient.phone_number.is_some(),
+        "event": run.context,
+    });
+
+    let mut index = run.current_step.max(0) as usize;
+
+    while let Some(step) = run.steps.get(index) {
+        match step {
+            Step::Send { template: key, channel } => {
+                let template = match template::get_template(&mut *conn, key).await? {
+                    Some(template) => template,
+                    None => {
+                        warn!("Workflow run {} references unknown template {}", run.id, key);
+                        return finish(conn, run.id, "Cancelled", Some(&format!("Unknown template {}", key))).await;
+                    }
+                };
+
+                let new_notification =
+                    match template::instantiate(&mut *conn, &template, run.user_id, &run.context, channel.as_deref(), None).await {
+                        Ok(new_notification) => new_notification,
+                        Err(InstantiateError::InvalidChannel(e)) => {
+                            warn!("Workflow run {} step cannot be sent: {}", run.id, e);
+                            return finish(conn, run.id, "Cancelled", Some(&e)).await;
+                        }
+                        Err(InstantiateError::Database(e)) => return Err(e),
+                    };
+                notification::create_notification(&mut *conn, new_notification).await?;
+                index += 1;
+            }
+            Step::Delay { .. } => {
+                // Runs copy their steps when they start, so one may predate the limit on delays
+                let resume_at = match step.delay().filter(|delay| *delay <= MAX_DELAY) {
+                    Some(delay) => OffsetDateTime::now_utc().checked_add(delay),
+                    None => None,
+                };
+                let Some(resume_at) = resume_at else {
+                    warn!("Workflow run {} has an invalid delay at step {}", run.id, index);
+                    return finish(conn, run.id, "Cancelled", Some(&format!("Invalid delay at step {}", index))).await;
+                };
+                sqlx::query!(
+                    "UPDATE workflow_runs SET current_step = $2, next_run_at = $3, attempts = 0, exit_reason = NULL WHERE id = $1",
+                    run.id,
+                    (index + 1) as i32,
+                    resume_at
+                )
+                .execute(&mut *conn)
+                .await?;
+                return Ok(());
+            }
+            Step::Condition { field, equals, otherwise, skip } => {
+                let actual = template::lookup(&fields, field).unwrap_or(&Value::Null);
+
+                if actual == equals {
+                    index += 1;
+                } else {
+                    match otherwise {
+                        Otherwise::Skip => index += 1 + skip,
+                        Otherwise::Exit => {
+                            let reason = format!("Condition on {} not met", field);
+                            return finish(conn, run.id, "Completed", Some(&reason)).await;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    finish(conn, run.id, "Completed", None).await
+}
+
+async fn finish(conn: &mut PgConnection, run_id: Uuid, status: &str, reason: Option<&str>) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE workflow_runs SET status = $2, exit_reason = $3, finished_at = NOW() WHERE id = $1",
+        run_id,
+        status,
+        reason
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    info!("Workflow run {} {}", run_id, status.to_lowercase());
+    Ok(())
+}
@@ -1,9 +1,7 @@
 use utoipa::{Modify, OpenApi, ToSchema};
 use utoipa::openapi::{security::{HttpAuthScheme, HttpBuilder, SecurityScheme}, ObjectBuilder, Schema, SchemaFormat, SchemaType};
 use utoipa::openapi::RefOr;
-use uuid::Uuid;
-use time::OffsetDateTime;
-use crate::api::{user, notification};
+use crate::api::{user, notification, analytics, devices, engagement, events, rules, sms, preferences, suppression, telegram, throttle, tracking, unsubscribe, voice, webhooks, workflows};
 
 
 
@@ -16,7 +14,67 @@ use crate::api::{user, notification};
         user::update_user,
         user::delete_user,
         user::verify_email,
-        notification::create_notification
+        user::get_send_times,
+        user::start_phone_verification,
+        user::confirm_phone_verification,
+        notification::create_notification,
+        notification::preview_notification,
+        notification::mark_read,
+        analytics::delivery_stats,
+        analytics::experiment_stats,
+        devices::register_device,
+        devices::remove_device,
+        devices::get_vapid_public_key,
+        devices::subscribe_web_push,
+        devices::unsubscribe_web_push,
+        engagement::get_channel_scores,
+        events::ingest_event,
+        rules::list_templates,
+        rules::get_template,
+        rules::upsert_template,
+        rules::delete_template,
+        rules::get_variants,
+        rules::replace_variants,
+        rules::list_rules,
+        rules::create_rule,
+        rules::update_rule,
+        rules::delete_rule,
+        workflows::list_workflows,
+        workflows::upsert_workflow,
+        workflows::list_runs,
+        workflows::start_run,
+        workflows::cancel_run,
+        preferences::get_preferences,
+        preferences::update_preferences,
+        preferences::list_opt_outs,
+        preferences::add_opt_out,
+        preferences::remove_opt_out,
+        preferences::list_chat_webhooks,
+        preferences::set_chat_webhook,
+        preferences::remove_chat_webhook,
+        preferences::list_push_servers,
+        preferences::set_push_server,
+        preferences::remove_push_server,
+        unsubscribe::unsubscribe,
+        suppression::email_event_webhook,
+        suppression::dsn_webhook,
+        suppression::list_suppressions,
+        suppression::remove_suppression,
+        sms::list_mock_messages,
+        sms::clear_mock_messages,
+        telegram::create_link,
+        telegram::get_account,
+        telegram::unlink,
+        telegram::telegram_webhook,
+        voice::voice_gather,
+        throttle::list_breakers,
+        throttle::reset_breaker,
+        tracking::track_click,
+        tracking::track_open,
+        webhooks::list_webhooks,
+        webhooks::create_webhook,
+        webhooks::delete_webhook,
+        webhooks::enable_webhook
     ),
     components(
         schemas(
@@ -24,16 +82,92 @@ use crate::api::{user, notification};
             user::LoginRequest, 
             user::UserGet, 
             user::UpdateUserRequest, 
+            user::SendTimesResponse,
+            user::ConfirmPhoneRequest,
+            crate::services::phone::InvalidPhoneNumber,
+            crate::services::engagement::SendTime,
+            crate::services::analytics::DeliveryStats,
+            crate::services::analytics::ExperimentStats,
+            crate::services::analytics::VariantStats,
+            engagement::ChannelScoresResponse,
+            crate::services::engagement::ChannelScore,
             notification::CreateNotification, 
             notification::NotificationResponse, 
+            notification::PreviewResponse,
+            crate::services::preview::ChannelPreview,
+            crate::services::preview::PreviewOutcome,
+            crate::channels::RenderedMessage,
+            events::EventRequest,
+            events::EventResponse,
+            rules::UpsertTemplateRequest,
+            rules::ReplaceVariantsRequest,
+            rules::VariantsResponse,
+            crate::services::template::Variant,
+            rules::CreateRuleRequest,
+            rules::UpdateRuleRequest,
+            crate::services::template::Template,
+            crate::services::rules::Rule,
+            crate::services::rules::Condition,
+            crate::services::rules::ConditionOp,
+            workflows::UpsertWorkflowRequest,
+            workflows::StartRunRequest,
+            workflows::StartRunResponse,
+            crate::services::workflow::Workflow,
+            crate::services::workflow::WorkflowRun,
+            crate::services::workflow::Step,
+            crate::services::workflow::Otherwise,
+            preferences::UpdatePreferencesRequest,
+            preferences::OptOutRequest,
+            preferences::ChatWebhookRequest,
+            crate::services::chat_webhooks::ChatWebhook,
+            preferences::PushServerRequest,
+            crate::services::push_servers::PushServer,
+            telegram::TelegramLinkResponse,
+            crate::services::telegram::TelegramAccount,
+            suppression::EmailEventRequest,
+            crate::services::suppression::Suppression,
+            crate::services::throttle::CircuitBreaker,
+            crate::services::devices::Device,
+            devices::RegisterDeviceRequest,
+            devices::RemoveDeviceRequest,
+            crate::services::web_push::WebPushSubscription,
+            devices::WebPushSubscriptionRequest,
+            devices::WebPushKeys,
+            devices::RemoveWebPushSubscriptionRequest,
+            devices::VapidPublicKeyResponse,
+            crate::channels::sms::provider::MockSmsMessage,
+            webhooks::CreateWebhookRequest,
+            webhooks::CreateWebhookResponse,
+            crate::services::webhooks::WebhookEndpoint,
             crate::db::models::Notification,
+            crate::db::models::NotificationAction,
+            crate::db::models::DeliveryMethod,
+            crate::db::models::UserPreferences,
+            crate::db::models::Category,
+            crate::db::models::SmsOverflow,
+            crate::db::models::Priority,
+            crate::db::models::OptOut,
             UuidSchema,
             OffsetDateTimeSchema
         )
     ),
     tags(
         (name = "User API", description = "User-related endpoints for account management, login, and registration."),
-        (name = "Notification API", description = "Notification management endpoints.")
+        (name = "Notification API", description = "Notification management endpoints."),
+        (name = "Analytics API", description = "Aggregated delivery and engagement statistics."),
+        (name = "Devices API", description = "Push device registration and browser (Web Push) subscriptions."),
+        (name = "Engagement API", description = "Engagement-driven channel selection."),
+        (name = "Events API", description = "Domain event ingestion."),
+        (name = "Rules API", description = "Templates and the rules that turn events into notifications."),
+        (name = "Workflows API", description = "Multi-step notification sequences."),
+        (name = "Preferences API", description = "Per-user delivery preferences, chat webhooks and self-hosted push servers."),
+        (name = "Suppression API", description = "Email bounce handling and the suppression list."),
+        (name = "SMS API", description = "SMS provider testing."),
+        (name = "Telegram API", description = "Linking Telegram chats to accounts."),
+        (name = "Voice API", description = "Keypress callbacks from voice calls."),
+        (name = "Throttling API", description = "Channel circuit breakers."),
+        (name = "Webhooks API", description = "Endpoints that receive notifications as signed JSON."),
+        (name = "Tracking", description = "Email open pixel and click redirects.")
     ),
     modifiers(&SecurityAddon)
 )]